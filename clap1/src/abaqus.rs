@@ -0,0 +1,632 @@
+//! Export and import of meshes in the Abaqus `.inp` format.
+//!
+//! The layout follows the files written by automesh: a heading, all nodes in an
+//! `ALLNODES` node set, and one `*ELEMENT` section and solid or shell section per
+//! element block, named `EB<label>`.  Node sets follow the elements as `*NSET`
+//! sections, and each side set is a `*SURFACE` made of one `*ELSET` per side.
+//!
+//! Reading keeps the `*NODE`, `*ELEMENT`, `*NSET`, `*ELSET` and `*SURFACE`
+//! sections, and skips every other keyword.
+
+use crate::mesh::{HexMesh, Mesh, NodeSet, SideSet, TriMesh};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+
+/// The Abaqus names of the element type and section of a mesh, and of the sides
+/// of its elements, in the Exodus II order of the sides.
+struct ElementKind {
+    element_type: &'static str,
+    section: &'static str,
+    sides: &'static [&'static str],
+}
+
+const HEX: ElementKind = ElementKind {
+    element_type: "C3D8R",
+    section: "SOLID SECTION",
+    sides: &["S3", "S4", "S5", "S6", "S1", "S2"],
+};
+
+const TRI: ElementKind = ElementKind {
+    element_type: "S3",
+    section: "SHELL SECTION",
+    sides: &["SPOS", "SNEG", "E1", "E2", "E3"],
+};
+
+/// The number of node or element numbers on each line of a set.
+const NUMBERS_PER_LINE: usize = 16;
+
+impl HexMesh {
+    /// Writes the mesh to an Abaqus `.inp` file, with `C3D8R` elements.
+    ///
+    /// Elements are numbered from one in order of their block, and in their
+    /// original order within a block.
+    pub fn write_inp(&self, file_path: &str) -> Result<(), String> {
+        let inp = Inp {
+            nodes: self.nodes.clone(),
+            elements: self.elements.clone(),
+            element_blocks: self.element_blocks.clone(),
+            node_sets: self.node_sets.clone(),
+            side_sets: self.side_sets.clone(),
+        };
+        inp.write(file_path, &self.blocks(), &HEX)
+    }
+}
+
+impl From<Inp<8>> for HexMesh {
+    fn from(inp: Inp<8>) -> Self {
+        Self {
+            nodes: inp.nodes,
+            elements: inp.elements,
+            element_blocks: inp.element_blocks,
+            node_sets: inp.node_sets,
+            side_sets: inp.side_sets,
+        }
+    }
+}
+
+impl TriMesh {
+    /// Writes the mesh to an Abaqus `.inp` file, with `S3` elements, numbered as
+    /// for a hexahedral mesh.
+    ///
+    /// The blocks in front of the elements are not written.
+    pub fn write_inp(&self, file_path: &str) -> Result<(), String> {
+        let inp = Inp {
+            nodes: self.nodes.clone(),
+            elements: self.elements.clone(),
+            element_blocks: self.element_blocks.clone(),
+            node_sets: self.node_sets.clone(),
+            side_sets: self.side_sets.clone(),
+        };
+        inp.write(file_path, &self.blocks(), &TRI)
+    }
+}
+
+impl From<Inp<3>> for TriMesh {
+    fn from(inp: Inp<3>) -> Self {
+        Self {
+            nodes: inp.nodes,
+            neighbor_blocks: vec![None; inp.elements.len()],
+            elements: inp.elements,
+            element_blocks: inp.element_blocks,
+            node_sets: inp.node_sets,
+            side_sets: inp.side_sets,
+        }
+    }
+}
+
+/// Reads a hexahedral or triangular mesh from an Abaqus `.inp` file, chosen by
+/// the number of nodes of its first element.
+///
+/// A triangular mesh has void in front of every element.
+pub fn read_inp(file_path: &str) -> Result<Mesh, String> {
+    let sections = Sections::read(file_path)?;
+    match sections.elements.first().map(|(_, nodes)| nodes.len()) {
+        Some(8) => Inp::from_sections(file_path, sections, &HEX).map(|inp| Mesh::Hex(inp.into())),
+        Some(3) => Inp::from_sections(file_path, sections, &TRI).map(|inp| Mesh::Tri(inp.into())),
+        Some(count) => Err(format!(
+            "{}: elements with {} nodes are not supported, use C3D8R or S3",
+            file_path, count
+        )),
+        None => Err(format!("{}: no elements found", file_path)),
+    }
+}
+
+/// The contents of an `.inp` file, with `N` nodes per element.
+struct Inp<const N: usize> {
+    nodes: Vec<[f64; 3]>,
+    elements: Vec<[usize; N]>,
+    element_blocks: Vec<u8>,
+    node_sets: Vec<NodeSet>,
+    side_sets: Vec<SideSet>,
+}
+
+impl<const N: usize> Inp<N> {
+    /// Writes the `.inp` file, with the distinct `blocks` in the order they are
+    /// written.
+    fn write(&self, file_path: &str, blocks: &[u8], kind: &ElementKind) -> Result<(), String> {
+        let file = File::create(file_path)
+            .map_err(|e| format!("Failed to create {}: {}", file_path, e))?;
+        let mut writer = BufWriter::new(file);
+        self.write_sections(&mut writer, blocks, kind)
+            .and_then(|_| writer.flush())
+            .map_err(|e| format!("Failed to write {}: {}", file_path, e))
+    }
+
+    /// Writes the sections of the `.inp` file.
+    fn write_sections<W: Write>(
+        &self,
+        writer: &mut W,
+        blocks: &[u8],
+        kind: &ElementKind,
+    ) -> std::io::Result<()> {
+        writeln!(writer, "*HEADING")?;
+        writeln!(writer, "{}", env!("CARGO_PKG_NAME"))?;
+        writeln!(writer, "version {}", env!("CARGO_PKG_VERSION"))?;
+        writeln!(writer, "**")?;
+
+        writeln!(writer, "*NODE, NSET=ALLNODES")?;
+        for (n, [x, y, z]) in self.nodes.iter().enumerate() {
+            writeln!(
+                writer,
+                "{:>7},{:>16.6e},{:>16.6e},{:>16.6e}",
+                n + 1,
+                x,
+                y,
+                z
+            )?;
+        }
+        writeln!(writer, "**")?;
+
+        let mut number_of = vec![0; self.elements.len()];
+        let mut number = 0;
+        for &block in blocks {
+            writeln!(
+                writer,
+                "*ELEMENT, TYPE={}, ELSET=EB{}",
+                kind.element_type, block
+            )?;
+            for (e, element) in self.elements.iter().enumerate() {
+                if self.element_blocks[e] != block {
+                    continue;
+                }
+                number += 1;
+                number_of[e] = number;
+                write!(writer, "{:>6}", number)?;
+                for node in element {
+                    write!(writer, ",{:>7}", node + 1)?;
+                }
+                writeln!(writer)?;
+            }
+            writeln!(writer, "**")?;
+        }
+
+        for set in &self.node_sets {
+            writeln!(writer, "*NSET, NSET={}", set.name)?;
+            write_numbers(writer, set.nodes.iter().map(|node| node + 1))?;
+            writeln!(writer, "**")?;
+        }
+        for set in &self.side_sets {
+            let mut sides: Vec<usize> = set.sides.iter().map(|&(_, side)| side).collect();
+            sides.sort_unstable();
+            sides.dedup();
+            let name = |side: usize| match kind.sides.get(side.wrapping_sub(1)) {
+                Some(label) => Ok(*label),
+                None => Err(std::io::Error::other(format!(
+                    "side set {} has an invalid side {}",
+                    set.name, side
+                ))),
+            };
+            if let Some(&(element, _)) = set.sides.iter().find(|(e, _)| *e >= self.elements.len()) {
+                return Err(std::io::Error::other(format!(
+                    "side set {} has no element {}",
+                    set.name,
+                    element + 1
+                )));
+            }
+            for &side in &sides {
+                writeln!(writer, "*ELSET, ELSET={}_{}", set.name, name(side)?)?;
+                let elements = set.sides.iter().filter(|&&(_, s)| s == side);
+                write_numbers(writer, elements.map(|&(e, _)| number_of[e]))?;
+            }
+            writeln!(writer, "*SURFACE, NAME={}, TYPE=ELEMENT", set.name)?;
+            for &side in &sides {
+                writeln!(writer, "{}_{}, {}", set.name, name(side)?, name(side)?)?;
+            }
+            writeln!(writer, "**")?;
+        }
+
+        for block in blocks {
+            writeln!(
+                writer,
+                "*{}, ELSET=EB{}, MATERIAL=Default-Steel",
+                kind.section, block
+            )?;
+            // A shell section takes its thickness on the following line.
+            if kind.section == "SHELL SECTION" {
+                writeln!(writer, "1.0")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Numbers the nodes and elements of the `sections` from zero, in the order of
+    /// the file.
+    fn from_sections(
+        file_path: &str,
+        sections: Sections,
+        kind: &ElementKind,
+    ) -> Result<Self, String> {
+        let error = |message: String| format!("{}: {}", file_path, message);
+
+        let mut nodes = Vec::with_capacity(sections.nodes.len());
+        let mut node_index = HashMap::new();
+        for (number, node) in sections.nodes {
+            node_index.insert(number, nodes.len());
+            nodes.push(node);
+        }
+        let node = |number: &usize| {
+            node_index
+                .get(number)
+                .copied()
+                .ok_or_else(|| error(format!("node {} is not defined", number)))
+        };
+
+        let mut elements = Vec::with_capacity(sections.elements.len());
+        let mut element_index = HashMap::new();
+        for (number, element_nodes) in &sections.elements {
+            let element: [usize; N] = element_nodes
+                .iter()
+                .map(node)
+                .collect::<Result<Vec<usize>, String>>()?
+                .try_into()
+                .map_err(|nodes: Vec<usize>| {
+                    error(format!(
+                        "element {} has {} nodes, expected {} for {}",
+                        number,
+                        nodes.len(),
+                        N,
+                        kind.element_type
+                    ))
+                })?;
+            element_index.insert(*number, elements.len());
+            elements.push(element);
+        }
+        let element = |number: &usize| {
+            element_index
+                .get(number)
+                .copied()
+                .ok_or_else(|| error(format!("element {} is not defined", number)))
+        };
+
+        let mut node_sets = Vec::new();
+        for (name, numbers) in &sections.node_sets {
+            node_sets.push(NodeSet {
+                id: node_sets.len() + 1,
+                name: name.clone(),
+                nodes: numbers.iter().map(node).collect::<Result<_, _>>()?,
+            });
+        }
+        let mut side_sets = Vec::new();
+        for (name, faces) in &sections.surfaces {
+            let mut sides = Vec::new();
+            for (elements, label) in faces {
+                let side = kind
+                    .sides
+                    .iter()
+                    .position(|side| side.eq_ignore_ascii_case(label))
+                    .ok_or_else(|| {
+                        error(format!("surface {} has an invalid side {}", name, label))
+                    })?;
+                // A face names either an element set or a single element.
+                let numbers = match sections
+                    .element_sets
+                    .iter()
+                    .find(|(set, _)| set.eq_ignore_ascii_case(elements))
+                {
+                    Some((_, numbers)) => numbers.clone(),
+                    None => vec![elements.parse().map_err(|_| {
+                        error(format!("surface {} has no element set {}", name, elements))
+                    })?],
+                };
+                for number in &numbers {
+                    sides.push((element(number)?, side + 1));
+                }
+            }
+            side_sets.push(SideSet {
+                id: side_sets.len() + 1,
+                name: name.clone(),
+                sides,
+            });
+        }
+
+        Ok(Self {
+            nodes,
+            elements,
+            element_blocks: sections.element_blocks,
+            node_sets,
+            side_sets,
+        })
+    }
+}
+
+/// Writes node or element numbers, several to a line.
+fn write_numbers<W: Write>(
+    writer: &mut W,
+    numbers: impl Iterator<Item = usize>,
+) -> std::io::Result<()> {
+    let numbers: Vec<String> = numbers.map(|number| format!("{:>7}", number)).collect();
+    for line in numbers.chunks(NUMBERS_PER_LINE) {
+        writeln!(writer, "{}", line.join(","))?;
+    }
+    Ok(())
+}
+
+/// The keyword sections of an `.inp` file that make a mesh, with the node and
+/// element numbers of the file.
+#[derive(Default)]
+struct Sections {
+    nodes: Vec<(usize, [f64; 3])>,
+    elements: Vec<(usize, Vec<usize>)>,
+    element_blocks: Vec<u8>,
+    node_sets: Vec<(String, Vec<usize>)>,
+    element_sets: Vec<(String, Vec<usize>)>,
+    /// The faces of each surface, as an element set or element, and a side.
+    surfaces: Vec<(String, Vec<(String, String)>)>,
+}
+
+/// The section that the data lines belong to.
+enum Section {
+    Node,
+    /// An element section, with its block and its element set.
+    Element(u8, usize),
+    NodeSet(usize, bool),
+    ElementSet(usize, bool),
+    Surface(usize),
+    Other,
+}
+
+/// Returns the set with the `name`, adding it if it is new.
+fn set_index<T>(sets: &mut Vec<(String, Vec<T>)>, name: &str) -> usize {
+    match sets
+        .iter()
+        .position(|(set, _)| set.eq_ignore_ascii_case(name))
+    {
+        Some(index) => index,
+        None => {
+            sets.push((name.to_string(), Vec::new()));
+            sets.len() - 1
+        }
+    }
+}
+
+/// Returns the numbers of a data line, which may be generated from a start, an
+/// end, and a step.
+fn set_numbers(fields: &[&str], generate: bool) -> Result<Vec<usize>, String> {
+    let numbers = fields
+        .iter()
+        .map(|field| {
+            field
+                .parse::<usize>()
+                .map_err(|e| format!("invalid number '{}': {}", field, e))
+        })
+        .collect::<Result<Vec<usize>, String>>()?;
+    match (generate, numbers.as_slice()) {
+        (false, _) => Ok(numbers),
+        (true, &[start, end]) => Ok((start..=end).collect()),
+        (true, &[start, end, step]) if step > 0 => Ok((start..=end).step_by(step).collect()),
+        (true, _) => Err("expected a start, an end and a step to generate".to_string()),
+    }
+}
+
+impl Sections {
+    fn read(file_path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(file_path)
+            .map_err(|e| format!("Failed to read {}: {}", file_path, e))?;
+        let mut sections = Self::default();
+        let mut section = Section::Other;
+        let mut blocks = 0;
+        // An element may continue on the next line after a trailing comma.
+        let mut pending: Vec<usize> = Vec::new();
+        for (k, line) in contents.lines().enumerate() {
+            let error = |message: String| format!("{}:{}: {}", file_path, k + 1, message);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("**") {
+                continue;
+            }
+            if let Some(keyword) = line.strip_prefix('*') {
+                let mut fields = keyword.split(',').map(str::trim);
+                let keyword = fields.next().unwrap_or("").to_ascii_uppercase();
+                let parameters: Vec<(String, &str)> = fields
+                    .map(|field| match field.split_once('=') {
+                        Some((key, value)) => (key.trim().to_ascii_uppercase(), value.trim()),
+                        None => (field.to_ascii_uppercase(), ""),
+                    })
+                    .collect();
+                let parameter = |key: &str| {
+                    parameters
+                        .iter()
+                        .find(|(k, _)| k == key)
+                        .map(|(_, value)| *value)
+                };
+                let generate = parameter("GENERATE").is_some();
+                section = match keyword.as_str() {
+                    "NODE" => Section::Node,
+                    "ELEMENT" => {
+                        blocks += 1;
+                        let set = parameter("ELSET").unwrap_or("");
+                        // The block is the number at the end of the set name, as in
+                        // EB<label>, or else the count of element sections.
+                        let digits = set.trim_start_matches(|c: char| !c.is_ascii_digit());
+                        let block = match digits.parse::<u8>() {
+                            Ok(label) => label,
+                            Err(_) => u8::try_from(blocks)
+                                .map_err(|_| error("too many element blocks".to_string()))?,
+                        };
+                        Section::Element(block, set_index(&mut sections.element_sets, set))
+                    }
+                    "NSET" => match parameter("NSET") {
+                        Some(name) => {
+                            Section::NodeSet(set_index(&mut sections.node_sets, name), generate)
+                        }
+                        None => return Err(error("*NSET has no NSET name".to_string())),
+                    },
+                    "ELSET" => match parameter("ELSET") {
+                        Some(name) => Section::ElementSet(
+                            set_index(&mut sections.element_sets, name),
+                            generate,
+                        ),
+                        None => return Err(error("*ELSET has no ELSET name".to_string())),
+                    },
+                    "SURFACE"
+                        if parameter("TYPE").is_none_or(|t| t.eq_ignore_ascii_case("ELEMENT")) =>
+                    {
+                        match parameter("NAME") {
+                            Some(name) => Section::Surface(set_index(&mut sections.surfaces, name)),
+                            None => return Err(error("*SURFACE has no NAME".to_string())),
+                        }
+                    }
+                    _ => Section::Other,
+                };
+                continue;
+            }
+
+            let fields: Vec<&str> = line
+                .split(',')
+                .map(str::trim)
+                .filter(|field| !field.is_empty())
+                .collect();
+            if fields.is_empty() {
+                continue;
+            }
+            match section {
+                Section::Node => {
+                    let number = fields[0].parse::<usize>().map_err(|e| {
+                        error(format!("invalid node number '{}': {}", fields[0], e))
+                    })?;
+                    let mut node = [0.0; 3];
+                    if fields.len() < 3 || fields.len() > 4 {
+                        return Err(error(format!(
+                            "node {} needs two or three coordinates",
+                            number
+                        )));
+                    }
+                    for (coordinate, field) in node.iter_mut().zip(&fields[1..]) {
+                        *coordinate = field
+                            .parse()
+                            .map_err(|e| error(format!("invalid coordinate '{}': {}", field, e)))?;
+                    }
+                    sections.nodes.push((number, node));
+                }
+                Section::Element(block, set) => {
+                    for field in &fields {
+                        pending.push(
+                            field
+                                .parse()
+                                .map_err(|e| error(format!("invalid number '{}': {}", field, e)))?,
+                        );
+                    }
+                    if !line.ends_with(',') {
+                        let number = pending[0];
+                        sections.elements.push((number, pending.split_off(1)));
+                        sections.element_blocks.push(block);
+                        sections.element_sets[set].1.push(number);
+                        pending.clear();
+                    }
+                }
+                Section::NodeSet(set, generate) => {
+                    let numbers = set_numbers(&fields, generate).map_err(error)?;
+                    sections.node_sets[set].1.extend(numbers);
+                }
+                Section::ElementSet(set, generate) => {
+                    let numbers = set_numbers(&fields, generate).map_err(error)?;
+                    sections.element_sets[set].1.extend(numbers);
+                }
+                Section::Surface(surface) => match fields[..] {
+                    [elements, side] => sections.surfaces[surface]
+                        .1
+                        .push((elements.to_string(), side.to_string())),
+                    _ => return Err(error("expected an element set and a side".to_string())),
+                },
+                Section::Other => (),
+            }
+        }
+        Ok(sections)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxels::{VoxelData, Voxels};
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("clap1_{}_{}", std::process::id(), name))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // Labels 2 and 1 alternate in x, so the elements are renumbered by block.
+        let data = VoxelData::from_shape_fn((2, 2, 2), |(_, _, x)| 2 - x as u8);
+        let mut mesh = HexMesh::from_voxels(&Voxels::from(data), &[]);
+        mesh.node_sets.push(NodeSet {
+            id: 1,
+            name: "bottom".to_string(),
+            nodes: (0..9).collect(),
+        });
+        mesh.side_sets.push(SideSet {
+            id: 1,
+            name: "left".to_string(),
+            sides: vec![(0, 4), (6, 4), (1, 5)],
+        });
+        let output = temp_path("abaqus_round_trip.inp");
+        mesh.write_inp(&output).unwrap();
+
+        let Ok(Mesh::Hex(read)) = read_inp(&output) else {
+            panic!("expected a hexahedral mesh")
+        };
+        assert_eq!(read.nodes, mesh.nodes);
+        assert_eq!(read.element_blocks, vec![1, 1, 1, 1, 2, 2, 2, 2]);
+        assert_eq!(read.elements[0], mesh.elements[1]);
+        assert_eq!(read.elements[4], mesh.elements[0]);
+        assert_eq!(read.node_sets, mesh.node_sets);
+        assert_eq!(read.side_sets[0].name, "left");
+        assert_eq!(read.side_sets[0].sides, vec![(4, 4), (7, 4), (0, 5)]);
+
+        // Writing the read mesh gives the same file.
+        let again = temp_path("abaqus_round_trip_again.inp");
+        read.write_inp(&again).unwrap();
+        assert_eq!(fs::read(&again).unwrap(), fs::read(&output).unwrap());
+
+        let data = VoxelData::from_shape_fn((2, 1, 1), |(z, _, _)| z as u8 + 1);
+        let tri = TriMesh::from_voxels(&Voxels::from(data), &[]);
+        tri.write_inp(&output).unwrap();
+        match read_inp(&output) {
+            Ok(Mesh::Tri(read)) => {
+                assert_eq!(read.nodes, tri.nodes);
+                assert_eq!(read.blocks(), tri.blocks());
+            }
+            _ => panic!("expected a triangular mesh"),
+        }
+    }
+
+    #[test]
+    fn test_read_keywords() {
+        // Lower case keywords, a continued element, generated sets, and an
+        // unknown keyword.
+        let input = temp_path("abaqus_read_keywords.inp");
+        fs::write(
+            &input,
+            "*Heading\n** comment\n*Node\n10, 0, 0, 0\n20, 1, 0, 0\n30, 1, 1, 0\n\
+             40, 0, 1, 0\n50, 0, 0, 1\n60, 1, 0, 1\n70, 1, 1, 1\n80, 0, 1, 1\n\
+             *Element, type=C3D8, elset=Part\n5, 10, 20, 30, 40,\n50, 60, 70, 80\n\
+             *Nset, nset=corners, generate\n10, 40, 30\n*Material, name=Steel\n\
+             *Elastic\n200e3, 0.3\n*Surface, name=top\n5, S2\n",
+        )
+        .unwrap();
+        let Ok(Mesh::Hex(mesh)) = read_inp(&input) else {
+            panic!("expected a hexahedral mesh")
+        };
+        assert_eq!(mesh.elements, vec![[0, 1, 2, 3, 4, 5, 6, 7]]);
+        assert_eq!(mesh.element_blocks, vec![1]);
+        assert_eq!(mesh.node_sets[0].nodes, vec![0, 3]);
+        assert_eq!(mesh.side_sets[0].sides, vec![(0, 6)]);
+
+        fs::write(&input, "*NODE\n1, 0, 0, x\n").unwrap();
+        let error = read_inp(&input).unwrap_err();
+        assert!(error.contains("abaqus_read_keywords.inp:2: invalid coordinate 'x'"));
+
+        // Elements of both types in one file.
+        fs::write(
+            &input,
+            "*NODE\n1, 0, 0, 0\n2, 1, 0, 0\n3, 0, 1, 0\n*ELEMENT, TYPE=S3\n1, 1, 2, 3\n\
+             *ELEMENT, TYPE=C3D8R\n2, 1, 2, 3, 1, 1, 2, 3, 1\n",
+        )
+        .unwrap();
+        let error = read_inp(&input).unwrap_err();
+        assert!(error.contains("element 2 has 8 nodes, expected 3 for S3"));
+    }
+}
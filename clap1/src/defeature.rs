@@ -0,0 +1,139 @@
+//! Removal of small clusters of voxels from a segmentation.
+//!
+//! A cluster is a set of voxels with the same label, connected through their
+//! faces.  Each cluster with fewer voxels than a minimum takes the label that is
+//! most common among the voxels that touch its faces, which merges it into a
+//! neighboring cluster.  Since every change merges two clusters, repeating this
+//! until nothing changes always ends.
+
+use crate::voxels::Voxels;
+
+/// The six face neighbors of a voxel, as offsets in x, y and z.
+const FACE_NEIGHBORS: [[isize; 3]; 6] = [
+    [-1, 0, 0],
+    [1, 0, 0],
+    [0, -1, 0],
+    [0, 1, 0],
+    [0, 0, -1],
+    [0, 0, 1],
+];
+
+/// Returns the face neighbors of the `voxel` inside a grid of `nel` voxels.
+fn face_neighbors(voxel: [usize; 3], nel: [usize; 3]) -> impl Iterator<Item = [usize; 3]> {
+    FACE_NEIGHBORS.iter().filter_map(move |offset| {
+        let mut neighbor = [0; 3];
+        for a in 0..3 {
+            neighbor[a] = voxel[a].checked_add_signed(offset[a])?;
+            if neighbor[a] >= nel[a] {
+                return None;
+            }
+        }
+        Some(neighbor)
+    })
+}
+
+impl Voxels {
+    /// Returns the clusters of the segmentation, as lists of voxels.
+    pub fn clusters(&self) -> Vec<Vec<[usize; 3]>> {
+        let nel = self.nel();
+        let mut seen = vec![false; nel[0] * nel[1] * nel[2]];
+        let index = |[x, y, z]: [usize; 3]| x + nel[0] * (y + nel[1] * z);
+        let mut clusters = Vec::new();
+        for ((z, y, x), &label) in self.get_data().indexed_iter() {
+            if seen[index([x, y, z])] {
+                continue;
+            }
+            seen[index([x, y, z])] = true;
+            let mut cluster = vec![[x, y, z]];
+            let mut next = 0;
+            while next < cluster.len() {
+                for neighbor in face_neighbors(cluster[next], nel) {
+                    if !seen[index(neighbor)] && self.label(neighbor) == label {
+                        seen[index(neighbor)] = true;
+                        cluster.push(neighbor);
+                    }
+                }
+                next += 1;
+            }
+            clusters.push(cluster);
+        }
+        clusters
+    }
+
+    /// Relabels every cluster with fewer than `min` voxels, until none remain
+    /// that touch a voxel of another label.
+    ///
+    /// # Returns
+    ///
+    /// The number of clusters that were relabeled.
+    pub fn defeature(&mut self, min: usize) -> usize {
+        let nel = self.nel();
+        let mut relabeled = 0;
+        loop {
+            let mut small: Vec<Vec<[usize; 3]>> = self
+                .clusters()
+                .into_iter()
+                .filter(|cluster| cluster.len() < min)
+                .collect();
+            small.sort_by_key(|cluster| cluster.len());
+
+            let mut changed = false;
+            for cluster in small {
+                let label = self.label(cluster[0]);
+                let mut counts = [0usize; 256];
+                for &voxel in &cluster {
+                    for neighbor in face_neighbors(voxel, nel) {
+                        counts[self.label(neighbor) as usize] += 1;
+                    }
+                }
+                counts[label as usize] = 0;
+                let max = counts.iter().max().copied().unwrap_or(0);
+                if max == 0 {
+                    continue;
+                }
+                // The most common neighboring label, and the smallest one on a tie.
+                let new_label = counts.iter().position(|&c| c == max).unwrap_or(0) as u8;
+                for &[x, y, z] in &cluster {
+                    self.get_data_mut()[[z, y, x]] = new_label;
+                }
+                relabeled += 1;
+                changed = true;
+            }
+            if !changed {
+                return relabeled;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::voxels::{VoxelData, Voxels};
+
+    #[test]
+    fn test_defeature() {
+        // A block of label 1 with a single voxel of 2 inside, a pair of 3 on a
+        // face, and a corner of 4 touching both 1 and 3.
+        let mut data = VoxelData::from_elem((4, 4, 4), 1);
+        data[[1, 1, 1]] = 2;
+        data[[0, 3, 2]] = 3;
+        data[[0, 3, 3]] = 3;
+        data[[0, 2, 3]] = 4;
+        let mut voxels = Voxels::from(data);
+        assert_eq!(voxels.clusters().len(), 4);
+
+        // Clusters of two or more voxels are kept with a minimum of two.
+        let mut kept = voxels.clone();
+        assert_eq!(kept.defeature(2), 2);
+        assert_eq!(kept.clusters().len(), 2);
+        assert_eq!(kept.get_data().iter().filter(|&&l| l == 3).count(), 2);
+
+        assert_eq!(voxels.defeature(3), 3);
+        assert_eq!(voxels.clusters().len(), 1);
+        assert!(voxels.get_data().iter().all(|&l| l == 1));
+
+        // A cluster with no other label around it is left alone.
+        let mut single = Voxels::from(VoxelData::from_elem((2, 2, 2), 5));
+        assert_eq!(single.defeature(100), 0);
+    }
+}
@@ -0,0 +1,404 @@
+//! Edits of a segmentation before meshing, which change its voxels or its shape.
+//!
+//! Each edit returns a new segmentation, indexed as `[[z, y, x]]` like the one
+//! it comes from.  Cropping, padding and resampling change the number of voxels
+//! in each direction, relabeling changes only the labels, and flipping and
+//! permuting reorder the voxels, such as those of data stored with another axis
+//! order.
+
+use crate::error::Error;
+use crate::voxels::{VoxelData, Voxels};
+use clap::ValueEnum;
+use ndarray::{Axis, s};
+use std::collections::BTreeMap;
+use std::fs;
+
+/// A direction of the segmentation.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Direction {
+    X,
+    Y,
+    Z,
+}
+
+impl Direction {
+    /// Returns the index of the direction in `[x, y, z]`.
+    fn index(self) -> usize {
+        match self {
+            Direction::X => 0,
+            Direction::Y => 1,
+            Direction::Z => 2,
+        }
+    }
+
+    /// Returns the axis of the voxel data, which is indexed as `[[z, y, x]]`.
+    fn axis(self) -> Axis {
+        match self {
+            Direction::X => Axis(2),
+            Direction::Y => Axis(1),
+            Direction::Z => Axis(0),
+        }
+    }
+}
+
+/// The order of the axes of voxel data, from the slowest changing to the
+/// fastest.
+///
+/// The orders are numbered as the `spn_xyz_order` of an Autotwin recipe, from
+/// zero for `xyz` to five for `zyx`, which is the order of a segmentation, with
+/// `x` changing fastest.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum AxisOrder {
+    #[value(alias = "0")]
+    Xyz,
+    #[value(alias = "1")]
+    Xzy,
+    #[value(alias = "2")]
+    Yxz,
+    #[value(alias = "3")]
+    Yzx,
+    #[value(alias = "4")]
+    Zxy,
+    #[value(alias = "5")]
+    Zyx,
+}
+
+impl AxisOrder {
+    /// Returns the order numbered by an Autotwin `spn_xyz_order`.
+    pub fn from_spn_xyz_order(number: usize) -> Result<Self, Error> {
+        Self::value_variants()
+            .get(number)
+            .copied()
+            .ok_or_else(|| Error::Value {
+                name: "spn_xyz_order",
+                value: number.to_string(),
+                expected: "0 (xyz) to 5 (zyx)",
+            })
+    }
+
+    /// Returns the number of voxels in each direction of data stored in this
+    /// order, as read with its fastest changing axis taken for x, from the
+    /// number of voxels in the x, y and z directions of the segmentation.
+    pub fn stored_nel<T: Copy>(self, nel: [T; 3]) -> [T; 3] {
+        let [slowest, middle, fastest] = self.directions();
+        [fastest, middle, slowest].map(|direction| nel[direction.index()])
+    }
+
+    /// Returns the directions of the axes, from the slowest changing.
+    fn directions(self) -> [Direction; 3] {
+        use Direction::{X, Y, Z};
+        match self {
+            AxisOrder::Xyz => [X, Y, Z],
+            AxisOrder::Xzy => [X, Z, Y],
+            AxisOrder::Yxz => [Y, X, Z],
+            AxisOrder::Yzx => [Y, Z, X],
+            AxisOrder::Zxy => [Z, X, Y],
+            AxisOrder::Zyx => [Z, Y, X],
+        }
+    }
+}
+
+/// An edit of a segmentation, as run by the `segmentation` commands.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Edit {
+    /// Keeps the voxels from `min` to `max`, inclusive, in x, y and z.  Either
+    /// one left out comes from the smallest box with every voxel not labeled
+    /// `void`.
+    Crop {
+        min: Option<[usize; 3]>,
+        max: Option<[usize; 3]>,
+        void: u8,
+    },
+    /// Adds `width` voxels labeled `label` before and after the segmentation in
+    /// x, y and z.
+    Pad { width: [usize; 3], label: u8 },
+    /// Merges each block of `factor` voxels in every direction into one.
+    Downsample { factor: usize },
+    /// Splits each voxel into `factor` voxels in every direction.
+    Upsample { factor: usize },
+    /// Changes each label that is a key of the map to its value.
+    Relabel(BTreeMap<u8, u8>),
+    /// Reverses the order of the voxels in each of the directions.
+    Flip(Vec<Direction>),
+    /// Reorders voxel data stored in the given order.
+    Permute(AxisOrder),
+}
+
+impl Edit {
+    /// Returns the verb of the edit, as in the terminal output.
+    pub fn verb(&self) -> &'static str {
+        match self {
+            Edit::Crop { .. } => "Cropping",
+            Edit::Pad { .. } => "Padding",
+            Edit::Downsample { .. } => "Downsampling",
+            Edit::Upsample { .. } => "Upsampling",
+            Edit::Relabel(_) => "Relabeling",
+            Edit::Flip(_) => "Flipping",
+            Edit::Permute(_) => "Permuting",
+        }
+    }
+
+    /// Checks the resampling factor, which must be at least one.
+    pub fn validate(&self) -> Result<(), Error> {
+        match self {
+            Edit::Downsample { factor } | Edit::Upsample { factor } if *factor == 0 => {
+                Err(Error::Value {
+                    name: "resampling factor",
+                    value: factor.to_string(),
+                    expected: "at least 1",
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the segmentation with the edit applied.
+    pub fn apply(&self, voxels: &Voxels) -> Result<Voxels, Error> {
+        self.validate()?;
+        Ok(match self {
+            Edit::Crop { min, max, void } => {
+                let fit = voxels.bounding_box(*void);
+                let bound = |given: Option<[usize; 3]>, k: usize| {
+                    given.or(fit.map(|fit| fit[k])).ok_or_else(|| {
+                        Error::Invalid(format!(
+                            "Every voxel is labeled {}, so there is no box to crop to",
+                            void
+                        ))
+                    })
+                };
+                voxels.crop(bound(*min, 0)?, bound(*max, 1)?)?
+            }
+            Edit::Pad { width, label } => voxels.pad(*width, *label),
+            Edit::Downsample { factor } => voxels.downsample(*factor),
+            Edit::Upsample { factor } => voxels.upsample(*factor),
+            Edit::Relabel(map) => voxels.relabel(map),
+            Edit::Flip(directions) => voxels.flip(directions),
+            Edit::Permute(order) => voxels.permute(*order),
+        })
+    }
+}
+
+/// Reads the map of a relabeling from a YAML file, with one `old: new` pair of
+/// labels per line.
+pub fn read_relabel_map(file_path: &str) -> Result<BTreeMap<u8, u8>, Error> {
+    let contents = fs::read_to_string(file_path)
+        .map_err(|e| format!("Failed to read {}: {}", file_path, e))?;
+    serde_yaml::from_str(&contents).map_err(|e| Error::Invalid(format!("{}: {}", file_path, e)))
+}
+
+impl Voxels {
+    /// Returns the smallest box with every voxel not labeled `void`, as its
+    /// first and last voxel in x, y and z, or `None` if every voxel is void.
+    pub fn bounding_box(&self, void: u8) -> Option<[[usize; 3]; 2]> {
+        let mut bounds: Option<[[usize; 3]; 2]> = None;
+        for ((z, y, x), &label) in self.get_data().indexed_iter() {
+            if label == void {
+                continue;
+            }
+            let voxel = [x, y, z];
+            let [min, max] = bounds.get_or_insert([voxel, voxel]);
+            for a in 0..3 {
+                min[a] = min[a].min(voxel[a]);
+                max[a] = max[a].max(voxel[a]);
+            }
+        }
+        bounds
+    }
+
+    /// Returns the voxels from `min` to `max`, inclusive, in x, y and z.
+    pub fn crop(&self, min: [usize; 3], max: [usize; 3]) -> Result<Self, Error> {
+        let nel = self.nel();
+        if (0..3).any(|a| min[a] > max[a] || max[a] >= nel[a]) {
+            return Err(Error::Invalid(format!(
+                "Cannot crop from {:?} to {:?} in a segmentation of {:?} voxels",
+                min, max, nel
+            )));
+        }
+        let [x0, y0, z0] = min;
+        let [x1, y1, z1] = max;
+        Ok(Self::from(
+            self.get_data()
+                .slice(s![z0..=z1, y0..=y1, x0..=x1])
+                .to_owned(),
+        ))
+    }
+
+    /// Returns the segmentation with `width` voxels labeled `label` before and
+    /// after it in x, y and z.
+    pub fn pad(&self, width: [usize; 3], label: u8) -> Self {
+        let [nelx, nely, nelz] = self.nel();
+        let [wx, wy, wz] = width;
+        let mut data = VoxelData::from_elem((nelz + 2 * wz, nely + 2 * wy, nelx + 2 * wx), label);
+        data.slice_mut(s![wz..wz + nelz, wy..wy + nely, wx..wx + nelx])
+            .assign(self.get_data());
+        Self::from(data)
+    }
+
+    /// Returns the segmentation with each block of `factor` voxels in every
+    /// direction merged into one, labeled by a majority vote of the block.
+    ///
+    /// A tie goes to the smallest label, and the blocks at the end of a
+    /// direction with a number of voxels that is not a multiple of `factor`
+    /// are smaller.
+    pub fn downsample(&self, factor: usize) -> Self {
+        let (nelz, nely, nelx) = self.get_data().dim();
+        let shape = (
+            nelz.div_ceil(factor),
+            nely.div_ceil(factor),
+            nelx.div_ceil(factor),
+        );
+        Self::from(VoxelData::from_shape_fn(shape, |(z, y, x)| {
+            let block = self.get_data().slice(s![
+                z * factor..((z + 1) * factor).min(nelz),
+                y * factor..((y + 1) * factor).min(nely),
+                x * factor..((x + 1) * factor).min(nelx)
+            ]);
+            let mut counts = [0usize; 256];
+            for &label in block {
+                counts[label as usize] += 1;
+            }
+            // The first of the largest counts, which is the smallest label.
+            let (label, _) = counts
+                .iter()
+                .enumerate()
+                .rev()
+                .max_by_key(|&(_, count)| count)
+                .unwrap_or((0, &0));
+            label as u8
+        }))
+    }
+
+    /// Returns the segmentation with each voxel split into `factor` voxels in
+    /// every direction.
+    pub fn upsample(&self, factor: usize) -> Self {
+        let (nelz, nely, nelx) = self.get_data().dim();
+        let shape = (nelz * factor, nely * factor, nelx * factor);
+        Self::from(VoxelData::from_shape_fn(shape, |(z, y, x)| {
+            self.get_data()[[z / factor, y / factor, x / factor]]
+        }))
+    }
+
+    /// Returns the segmentation with each label that is a key of the map
+    /// changed to its value.
+    pub fn relabel(&self, map: &BTreeMap<u8, u8>) -> Self {
+        Self::from(
+            self.get_data()
+                .mapv(|label| map.get(&label).copied().unwrap_or(label)),
+        )
+    }
+
+    /// Returns the segmentation with the order of the voxels reversed in each
+    /// of the directions.
+    pub fn flip(&self, directions: &[Direction]) -> Self {
+        let mut data = self.get_data().view();
+        for direction in directions {
+            data.invert_axis(direction.axis());
+        }
+        Self::from(data.as_standard_layout().into_owned())
+    }
+
+    /// Returns the segmentation of voxel data stored in the given order, such
+    /// as that of a `.npy` file of shape `(nelx, nely, nelz)` for `xyz`.
+    pub fn permute(&self, order: AxisOrder) -> Self {
+        let stored = order.directions();
+        let axes = [Direction::Z, Direction::Y, Direction::X]
+            .map(|direction| stored.iter().position(|&d| d == direction).unwrap_or(0));
+        Self::from(
+            self.get_data()
+                .view()
+                .permuted_axes(axes)
+                .as_standard_layout()
+                .into_owned(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segmentation() -> Voxels {
+        Voxels::from(VoxelData::from_shape_fn((2, 3, 4), |(z, y, x)| {
+            (x + 4 * y + 12 * z) as u8
+        }))
+    }
+
+    #[test]
+    fn test_crop_and_pad() {
+        let voxels = segmentation();
+        let cropped = voxels.crop([1, 0, 1], [2, 1, 1]).unwrap();
+        assert_eq!(cropped.nel(), [2, 2, 1]);
+        assert_eq!(cropped.label([0, 0, 0]), voxels.label([1, 0, 1]));
+        assert!(voxels.crop([0, 0, 0], [4, 0, 0]).is_err());
+
+        let padded = voxels.pad([1, 0, 2], 255);
+        assert_eq!(padded.nel(), [6, 3, 6]);
+        assert_eq!(padded.label([0, 0, 0]), 255);
+        assert_eq!(padded.label([1, 2, 3]), voxels.label([0, 2, 1]));
+        assert_eq!(padded.bounding_box(255), Some([[1, 0, 2], [4, 2, 3]]));
+
+        // Cropping to fit undoes the padding.
+        let edit = Edit::Crop {
+            min: None,
+            max: None,
+            void: 255,
+        };
+        assert_eq!(edit.apply(&padded).unwrap(), voxels);
+        let void = Voxels::from(VoxelData::zeros((1, 1, 1)));
+        assert!(
+            Edit::Crop {
+                min: None,
+                max: None,
+                void: 0
+            }
+            .apply(&void)
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_resample() {
+        let voxels = Voxels::from(VoxelData::from_shape_fn((2, 2, 3), |(_, y, x)| {
+            (x == 2 || y == 1) as u8 + 1
+        }));
+        // The first block ties, four voxels to four, and goes to label 1, while
+        // the last block, which is one voxel wide, is all label 2.
+        let downsampled = voxels.downsample(2);
+        assert_eq!(downsampled.nel(), [2, 1, 1]);
+        assert_eq!(downsampled.get_data().as_slice(), Some(&[1, 2][..]));
+
+        let upsampled = voxels.upsample(2);
+        assert_eq!(upsampled.nel(), [6, 4, 4]);
+        assert_eq!(upsampled.downsample(2), voxels);
+        assert!(Edit::Upsample { factor: 0 }.apply(&voxels).is_err());
+    }
+
+    #[test]
+    fn test_relabel_flip_and_permute() {
+        let voxels = segmentation();
+        let relabeled = voxels.relabel(&BTreeMap::from([(0, 7), (1, 0)]));
+        assert_eq!(relabeled.label([0, 0, 0]), 7);
+        assert_eq!(relabeled.label([1, 0, 0]), 0);
+        assert_eq!(relabeled.label([2, 0, 0]), 2);
+
+        let flipped = voxels.flip(&[Direction::X, Direction::Z]);
+        assert_eq!(flipped.label([0, 1, 0]), voxels.label([3, 1, 1]));
+        assert_eq!(flipped.flip(&[Direction::Z, Direction::X]), voxels);
+
+        // Data stored as (nelx, nely, nelz) has x changing slowest.
+        let stored = Voxels::from(
+            voxels
+                .get_data()
+                .view()
+                .reversed_axes()
+                .as_standard_layout()
+                .into_owned(),
+        );
+        assert_eq!(stored.permute(AxisOrder::Xyz), voxels);
+        assert_eq!(stored.nel(), AxisOrder::Xyz.stored_nel(voxels.nel()));
+        assert_eq!(AxisOrder::Yzx.stored_nel([4, 3, 2]), [4, 2, 3]);
+        assert_eq!(voxels.permute(AxisOrder::Zyx), voxels);
+        assert_eq!(AxisOrder::from_spn_xyz_order(5), Ok(AxisOrder::Zyx));
+        assert!(AxisOrder::from_spn_xyz_order(6).is_err());
+    }
+}
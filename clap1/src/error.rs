@@ -0,0 +1,96 @@
+//! The errors of `clap1`, each with the exit code of the command line.
+//!
+//! The arguments of a step are checked before it reads anything, so that an
+//! unsupported file extension or an out of range value stops it at once.  Those
+//! errors exit with the code of a usage error, as for arguments clap rejects,
+//! while failures to read, mesh or write exit with one.
+
+use std::fmt;
+
+/// An error from checking the arguments of a step, or from running it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// A file with an extension that is not one of the formats of its use.
+    Format { file_path: String, expected: String },
+    /// A `.spn` file without the number of voxels in each direction.
+    Nel { file_path: String },
+    /// A value outside of its range, or not one of its choices.
+    Value {
+        name: &'static str,
+        value: String,
+        expected: &'static str,
+    },
+    /// Arguments that are each valid, but cannot run together, such as a
+    /// recipe step before the step it needs.
+    Invalid(String),
+    /// A failure while reading, meshing or writing.
+    Failed(String),
+}
+
+impl Error {
+    /// Returns the exit code of the command line, which is 2 for invalid
+    /// arguments and 1 for failures.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Format { .. } | Error::Nel { .. } | Error::Value { .. } | Error::Invalid(_) => 2,
+            Error::Failed(_) => 1,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Format {
+                file_path,
+                expected,
+            } => write!(
+                f,
+                "Unsupported file extension for {}, use {}",
+                file_path, expected
+            ),
+            Error::Nel { file_path } => write!(
+                f,
+                "Reading {} requires the number of voxels in each direction, nelx, nely and nelz",
+                file_path
+            ),
+            Error::Value {
+                name,
+                value,
+                expected,
+            } => write!(f, "Invalid {} {}, use {}", name, value, expected),
+            Error::Invalid(message) | Error::Failed(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<String> for Error {
+    /// Wraps the message of a failure from the readers, meshers and writers.
+    fn from(message: String) -> Self {
+        Error::Failed(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error() {
+        let error = Error::Value {
+            name: "smoothing scale",
+            value: 1.5.to_string(),
+            expected: "a value between 0 and 1",
+        };
+        assert_eq!(
+            error.to_string(),
+            "Invalid smoothing scale 1.5, use a value between 0 and 1"
+        );
+        assert_eq!(error.exit_code(), 2);
+        let error = Error::from("Failed to open model.exo".to_string());
+        assert_eq!(error, Error::Failed("Failed to open model.exo".to_string()));
+        assert_eq!(error.exit_code(), 1);
+    }
+}
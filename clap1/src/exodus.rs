@@ -0,0 +1,486 @@
+//! Export and import of meshes in the Exodus II format.
+//!
+//! An Exodus II file is a netCDF file with the node coordinates, one block of
+//! elements per label, with the label as the block identifier, and any node sets
+//! and side sets.  Elements are numbered from one in order of their block, as in
+//! an Abaqus `.inp` file, and the node and element numbers of the sets follow.
+
+use crate::mesh::{HexMesh, Mesh, NodeSet, SideSet, TriMesh};
+use crate::netcdf::{NetCdf, Values};
+use std::fs;
+
+/// The length of the names, including the terminating null.
+const LEN_NAME: usize = 33;
+
+impl HexMesh {
+    /// Writes the mesh to an Exodus II `.exo` file, with `HEX8` elements.
+    pub fn write_exo(&self, file_path: &str) -> Result<(), String> {
+        let exodus = Exodus {
+            nodes: self.nodes.clone(),
+            elements: self.elements.clone(),
+            element_blocks: self.element_blocks.clone(),
+            node_sets: self.node_sets.clone(),
+            side_sets: self.side_sets.clone(),
+        };
+        exodus.write(file_path, "HEX8")
+    }
+
+    /// Reads a mesh of eight-node elements from an Exodus II `.exo` file.
+    pub fn read_exo(file_path: &str) -> Result<Self, String> {
+        Exodus::read(file_path).map(Self::from)
+    }
+}
+
+impl From<Exodus<8>> for HexMesh {
+    fn from(exodus: Exodus<8>) -> Self {
+        Self {
+            nodes: exodus.nodes,
+            elements: exodus.elements,
+            element_blocks: exodus.element_blocks,
+            node_sets: exodus.node_sets,
+            side_sets: exodus.side_sets,
+        }
+    }
+}
+
+impl TriMesh {
+    /// Writes the mesh to an Exodus II `.exo` file, with `TRISHELL3` elements.
+    ///
+    /// The blocks in front of the elements are not written.
+    pub fn write_exo(&self, file_path: &str) -> Result<(), String> {
+        let exodus = Exodus {
+            nodes: self.nodes.clone(),
+            elements: self.elements.clone(),
+            element_blocks: self.element_blocks.clone(),
+            node_sets: self.node_sets.clone(),
+            side_sets: self.side_sets.clone(),
+        };
+        exodus.write(file_path, "TRISHELL3")
+    }
+
+    /// Reads a mesh of three-node elements from an Exodus II `.exo` file, with
+    /// void in front of every element.
+    pub fn read_exo(file_path: &str) -> Result<Self, String> {
+        Exodus::read(file_path).map(Self::from)
+    }
+}
+
+impl From<Exodus<3>> for TriMesh {
+    fn from(exodus: Exodus<3>) -> Self {
+        Self {
+            nodes: exodus.nodes,
+            neighbor_blocks: vec![None; exodus.elements.len()],
+            elements: exodus.elements,
+            element_blocks: exodus.element_blocks,
+            node_sets: exodus.node_sets,
+            side_sets: exodus.side_sets,
+        }
+    }
+}
+
+/// Reads a hexahedral or triangular mesh from an Exodus II `.exo` file, chosen by
+/// the number of nodes of the elements of its first block.
+///
+/// A triangular mesh has void in front of every element.
+pub fn read_exo(file_path: &str) -> Result<Mesh, String> {
+    let file = read_netcdf(file_path)?;
+    match file.dimension_length("num_nod_per_el1") {
+        Some(8) => Exodus::from_netcdf(file_path, &file).map(|exodus| Mesh::Hex(exodus.into())),
+        Some(3) => Exodus::from_netcdf(file_path, &file).map(|exodus| Mesh::Tri(exodus.into())),
+        Some(count) => Err(format!(
+            "{}: elements with {} nodes are not supported, use HEX8 or TRISHELL3",
+            file_path, count
+        )),
+        None => Err(format!("{}: no element blocks found", file_path)),
+    }
+}
+
+/// Reads the netCDF file that holds an Exodus II mesh.
+fn read_netcdf(file_path: &str) -> Result<NetCdf, String> {
+    let bytes = fs::read(file_path).map_err(|e| format!("Failed to read {}: {}", file_path, e))?;
+    NetCdf::from_bytes(&bytes).map_err(|e| format!("{}: {}", file_path, e))
+}
+
+/// The contents of an Exodus II file, with `N` nodes per element.
+struct Exodus<const N: usize> {
+    nodes: Vec<[f64; 3]>,
+    elements: Vec<[usize; N]>,
+    element_blocks: Vec<u8>,
+    node_sets: Vec<NodeSet>,
+    side_sets: Vec<SideSet>,
+}
+
+/// Returns the names as a null-padded array of characters.
+fn names<'a>(names: impl Iterator<Item = &'a str>) -> Values {
+    let mut chars = Vec::new();
+    for name in names {
+        let name = &name.as_bytes()[..name.len().min(LEN_NAME - 1)];
+        chars.extend_from_slice(name);
+        chars.resize(chars.len() + LEN_NAME - name.len(), 0);
+    }
+    Values::Char(chars)
+}
+
+/// Returns one-based numbers as netCDF integers.
+fn numbers(indices: impl Iterator<Item = usize>) -> Values {
+    Values::Int(indices.map(|index| index as i32 + 1).collect())
+}
+
+impl<const N: usize> Exodus<N> {
+    fn write(&self, file_path: &str, element_type: &str) -> Result<(), String> {
+        if self.elements.is_empty() {
+            return Err(format!(
+                "Failed to write {}: the mesh has no elements",
+                file_path
+            ));
+        }
+        let mut blocks = self.element_blocks.clone();
+        blocks.sort_unstable();
+        blocks.dedup();
+        // A netCDF dimension cannot have a length of zero, so empty sets are left out.
+        let node_sets: Vec<&NodeSet> = self
+            .node_sets
+            .iter()
+            .filter(|s| !s.nodes.is_empty())
+            .collect();
+        let side_sets: Vec<&SideSet> = self
+            .side_sets
+            .iter()
+            .filter(|s| !s.sides.is_empty())
+            .collect();
+
+        // Elements are numbered in order of their block.
+        let mut order = Vec::with_capacity(self.elements.len());
+        for &block in &blocks {
+            order.extend((0..self.elements.len()).filter(|&e| self.element_blocks[e] == block));
+        }
+        let mut number_of = vec![0; self.elements.len()];
+        for (number, &element) in order.iter().enumerate() {
+            number_of[element] = number;
+        }
+
+        let mut file = NetCdf::default();
+        let text = |text: &str| Values::text(text);
+        file.attributes = vec![
+            ("api_version".to_string(), Values::Float(vec![8.03])),
+            ("version".to_string(), Values::Float(vec![8.03])),
+            ("floating_point_word_size".to_string(), Values::Int(vec![8])),
+            ("file_size".to_string(), Values::Int(vec![1])),
+            (
+                "maximum_name_length".to_string(),
+                Values::Int(vec![LEN_NAME as i32 - 1]),
+            ),
+            ("int64_status".to_string(), Values::Int(vec![0])),
+            ("title".to_string(), text(env!("CARGO_PKG_NAME"))),
+        ];
+        file.add_dimension("len_string", LEN_NAME);
+        file.add_dimension("len_line", 81);
+        file.add_dimension("four", 4);
+        file.add_dimension("len_name", LEN_NAME);
+        file.add_dimension("time_step", 0);
+        file.add_dimension("num_dim", 3);
+        file.add_dimension("num_nodes", self.nodes.len());
+        file.add_dimension("num_elem", self.elements.len());
+        file.add_dimension("num_el_blk", blocks.len());
+        if !node_sets.is_empty() {
+            file.add_dimension("num_node_sets", node_sets.len());
+        }
+        if !side_sets.is_empty() {
+            file.add_dimension("num_side_sets", side_sets.len());
+        }
+
+        let id = || vec![("name".to_string(), text("ID"))];
+        file.add_variable("time_whole", &["time_step"], vec![], Values::Double(vec![]))?;
+        file.add_variable(
+            "eb_status",
+            &["num_el_blk"],
+            vec![],
+            Values::Int(vec![1; blocks.len()]),
+        )?;
+        let ids = blocks.iter().map(|&block| block as i32).collect();
+        file.add_variable("eb_prop1", &["num_el_blk"], id(), Values::Int(ids))?;
+        let block_names: Vec<String> = blocks.iter().map(|block| format!("EB{}", block)).collect();
+        let block_names = names(block_names.iter().map(String::as_str));
+        file.add_variable("eb_names", &["num_el_blk", "len_name"], vec![], block_names)?;
+        if !node_sets.is_empty() {
+            let dims = ["num_node_sets"];
+            let ids = node_sets.iter().map(|s| s.id as i32).collect();
+            file.add_variable(
+                "ns_status",
+                &dims,
+                vec![],
+                Values::Int(vec![1; node_sets.len()]),
+            )?;
+            file.add_variable("ns_prop1", &dims, id(), Values::Int(ids))?;
+            let set_names = names(node_sets.iter().map(|s| s.name.as_str()));
+            file.add_variable(
+                "ns_names",
+                &["num_node_sets", "len_name"],
+                vec![],
+                set_names,
+            )?;
+        }
+        if !side_sets.is_empty() {
+            let dims = ["num_side_sets"];
+            let ids = side_sets.iter().map(|s| s.id as i32).collect();
+            file.add_variable(
+                "ss_status",
+                &dims,
+                vec![],
+                Values::Int(vec![1; side_sets.len()]),
+            )?;
+            file.add_variable("ss_prop1", &dims, id(), Values::Int(ids))?;
+            let set_names = names(side_sets.iter().map(|s| s.name.as_str()));
+            file.add_variable(
+                "ss_names",
+                &["num_side_sets", "len_name"],
+                vec![],
+                set_names,
+            )?;
+        }
+
+        for (axis, name) in ["coordx", "coordy", "coordz"].iter().enumerate() {
+            let coordinates = self.nodes.iter().map(|node| node[axis]).collect();
+            file.add_variable(name, &["num_nodes"], vec![], Values::Double(coordinates))?;
+        }
+        let coordinate_names = names(["x", "y", "z"].into_iter());
+        file.add_variable(
+            "coor_names",
+            &["num_dim", "len_name"],
+            vec![],
+            coordinate_names,
+        )?;
+
+        for (k, &block) in blocks.iter().enumerate() {
+            let (count, per) = (
+                format!("num_el_in_blk{}", k + 1),
+                format!("num_nod_per_el{}", k + 1),
+            );
+            let block_order = order.iter().filter(|&&e| self.element_blocks[e] == block);
+            file.add_dimension(&count, block_order.clone().count());
+            file.add_dimension(&per, N);
+            let connectivity = numbers(block_order.flat_map(|&e| self.elements[e]));
+            let elem_type = vec![("elem_type".to_string(), text(element_type))];
+            file.add_variable(
+                &format!("connect{}", k + 1),
+                &[&count, &per],
+                elem_type,
+                connectivity,
+            )?;
+        }
+        for (k, set) in node_sets.iter().enumerate() {
+            let count = format!("num_nod_ns{}", k + 1);
+            file.add_dimension(&count, set.nodes.len());
+            let nodes = numbers(set.nodes.iter().copied());
+            file.add_variable(&format!("node_ns{}", k + 1), &[&count], vec![], nodes)?;
+        }
+        for (k, set) in side_sets.iter().enumerate() {
+            let count = format!("num_side_ss{}", k + 1);
+            file.add_dimension(&count, set.sides.len());
+            if let Some(&(element, _)) = set.sides.iter().find(|(e, _)| *e >= self.elements.len()) {
+                return Err(format!(
+                    "Side set {} has no element {}",
+                    set.id,
+                    element + 1
+                ));
+            }
+            let elements = numbers(set.sides.iter().map(|&(e, _)| number_of[e]));
+            let sides = Values::Int(set.sides.iter().map(|&(_, side)| side as i32).collect());
+            file.add_variable(&format!("elem_ss{}", k + 1), &[&count], vec![], elements)?;
+            file.add_variable(&format!("side_ss{}", k + 1), &[&count], vec![], sides)?;
+        }
+
+        fs::write(file_path, file.to_bytes())
+            .map_err(|e| format!("Failed to write {}: {}", file_path, e))
+    }
+
+    fn read(file_path: &str) -> Result<Self, String> {
+        Self::from_netcdf(file_path, &read_netcdf(file_path)?)
+    }
+
+    /// Reads the mesh from the netCDF `file`, naming `file_path` in any error.
+    fn from_netcdf(file_path: &str, file: &NetCdf) -> Result<Self, String> {
+        let error = |message: String| format!("{}: {}", file_path, message);
+        let count = |name: &str| file.dimension_length(name).unwrap_or(0);
+        let values = |name: &str| {
+            file.variable(name)
+                .map(|variable| &variable.values)
+                .ok_or_else(|| error(format!("missing variable {}", name)))
+        };
+        let ints = |name: &str| {
+            values(name)?
+                .as_ints()
+                .ok_or_else(|| error(format!("{} is not an integer variable", name)))
+        };
+        // Converts one-based numbers to indices, checked against the `count`.
+        let indices = |name: &str, count: usize| {
+            ints(name)?
+                .iter()
+                .map(|&number| match number {
+                    1.. if number as usize <= count => Ok(number as usize - 1),
+                    _ => Err(error(format!("{} has an invalid number {}", name, number))),
+                })
+                .collect::<Result<Vec<usize>, String>>()
+        };
+        // Names are rows of characters, as long as the len_name dimension.
+        let names = |name: &str, count: usize| -> Vec<String> {
+            match file.variable(name).map(|variable| &variable.values) {
+                Some(Values::Char(chars)) if count > 0 && chars.len() % count == 0 => chars
+                    .chunks_exact(chars.len() / count)
+                    .map(|row| Values::Char(row.to_vec()).to_text().unwrap_or_default())
+                    .collect(),
+                _ => vec![String::new(); count],
+            }
+        };
+
+        if count("num_dim") != 3 {
+            return Err(error(format!(
+                "expected 3 dimensions, found {}",
+                count("num_dim")
+            )));
+        }
+        let num_nodes = count("num_nodes");
+        let mut nodes = vec![[0.0; 3]; num_nodes];
+        for (axis, name) in ["coordx", "coordy", "coordz"].iter().enumerate() {
+            let coordinates = match file.variable(name) {
+                Some(variable) => variable.values.to_doubles(),
+                // Older files store all coordinates in one variable.
+                None => values("coord")?
+                    .to_doubles()
+                    .map(|all| all[axis * num_nodes..(axis + 1) * num_nodes].to_vec()),
+            }
+            .ok_or_else(|| error(format!("{} is not a floating point variable", name)))?;
+            for (node, coordinate) in nodes.iter_mut().zip(coordinates) {
+                node[axis] = coordinate;
+            }
+        }
+
+        let mut elements = Vec::new();
+        let mut element_blocks = Vec::new();
+        let ids = ints("eb_prop1")?;
+        for (k, &id) in ids.iter().enumerate() {
+            let label = u8::try_from(id)
+                .map_err(|_| error(format!("block {} is not a voxel label", id)))?;
+            let per = count(&format!("num_nod_per_el{}", k + 1));
+            if per != N {
+                return Err(error(format!(
+                    "block {} has elements with {} nodes, expected {}",
+                    id, per, N
+                )));
+            }
+            let connectivity = indices(&format!("connect{}", k + 1), num_nodes)?;
+            for element in connectivity.chunks_exact(N) {
+                elements.push(element.try_into().unwrap());
+                element_blocks.push(label);
+            }
+        }
+
+        let set_ids = |name: &str| match file.variable(name) {
+            Some(_) => ints(name).map(|ids| ids.iter().map(|&id| id as usize).collect()),
+            None => Ok(Vec::new()),
+        };
+        let ns_ids: Vec<usize> = set_ids("ns_prop1")?;
+        let ns_names = names("ns_names", ns_ids.len());
+        let mut node_sets = Vec::new();
+        for (k, (&id, name)) in ns_ids.iter().zip(ns_names).enumerate() {
+            let nodes = indices(&format!("node_ns{}", k + 1), num_nodes)?;
+            node_sets.push(NodeSet { id, name, nodes });
+        }
+        let ss_ids: Vec<usize> = set_ids("ss_prop1")?;
+        let ss_names = names("ss_names", ss_ids.len());
+        let mut side_sets = Vec::new();
+        for (k, (&id, name)) in ss_ids.iter().zip(ss_names).enumerate() {
+            let set_elements = indices(&format!("elem_ss{}", k + 1), elements.len())?;
+            let sides = ints(&format!("side_ss{}", k + 1))?;
+            let sides = set_elements
+                .into_iter()
+                .zip(sides.iter().map(|&side| side as usize))
+                .collect();
+            side_sets.push(SideSet { id, name, sides });
+        }
+
+        Ok(Self {
+            nodes,
+            elements,
+            element_blocks,
+            node_sets,
+            side_sets,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxels::{VoxelData, Voxels};
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("clap1_{}_{}", std::process::id(), name))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_hex_round_trip() {
+        // Labels 2 and 1 alternate in x, so the elements are renumbered by block.
+        let data = VoxelData::from_shape_fn((2, 2, 2), |(_, _, x)| 2 - x as u8);
+        let mut mesh = HexMesh::from_voxels(&Voxels::from(data), &[]);
+        mesh.node_sets.push(NodeSet {
+            id: 1,
+            name: "bottom".to_string(),
+            nodes: vec![0, 1, 2],
+        });
+        mesh.node_sets.push(NodeSet::default());
+        mesh.side_sets.push(SideSet {
+            id: 7,
+            name: "left".to_string(),
+            sides: vec![(0, 4), (6, 4)],
+        });
+        let output = temp_path("exodus_hex_round_trip.exo");
+        mesh.write_exo(&output).unwrap();
+
+        let read = HexMesh::read_exo(&output).unwrap();
+        assert_eq!(read.nodes, mesh.nodes);
+        assert_eq!(read.element_blocks, vec![1, 1, 1, 1, 2, 2, 2, 2]);
+        assert_eq!(read.elements[0], mesh.elements[1]);
+        assert_eq!(read.elements[4], mesh.elements[0]);
+        assert_eq!(read.node_sets, mesh.node_sets[..1]);
+        assert_eq!(read.side_sets[0].sides, vec![(4, 4), (7, 4)]);
+        assert_eq!(read.side_sets[0].name, "left");
+
+        // Writing the read mesh gives the same file.
+        let again = temp_path("exodus_hex_round_trip_again.exo");
+        read.write_exo(&again).unwrap();
+        assert_eq!(fs::read(&again).unwrap(), fs::read(&output).unwrap());
+
+        let error = TriMesh::read_exo(&output).unwrap_err();
+        assert!(error.contains("block 1 has elements with 8 nodes, expected 3"));
+        assert_eq!(read_exo(&output).unwrap(), Mesh::Hex(read));
+    }
+
+    #[test]
+    fn test_tri_round_trip() {
+        let data = VoxelData::from_shape_fn((2, 3, 4), |(z, _, _)| z as u8 + 1);
+        let mesh = TriMesh::from_voxels(&Voxels::from(data), &[]);
+        let output = temp_path("exodus_tri_round_trip.exo");
+        mesh.write_exo(&output).unwrap();
+
+        let read = TriMesh::read_exo(&output).unwrap();
+        assert_eq!(read.nodes, mesh.nodes);
+        assert_eq!(read.elements.len(), mesh.elements.len());
+        assert_eq!(read.blocks(), mesh.blocks());
+        assert!(read.neighbor_blocks.iter().all(Option::is_none));
+        for block in mesh.blocks() {
+            let of = |mesh: &TriMesh| -> Vec<[usize; 3]> {
+                (0..mesh.elements.len())
+                    .filter(|&e| mesh.element_blocks[e] == block)
+                    .map(|e| mesh.elements[e])
+                    .collect()
+            };
+            assert_eq!(of(&read), of(&mesh));
+        }
+
+        assert!(TriMesh::default().write_exo(&output).is_err());
+    }
+}
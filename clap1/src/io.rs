@@ -0,0 +1,345 @@
+//! Reading and writing of meshes in the format given by the file extension.
+//!
+//! Every format goes through the same in-memory meshes, so converting a mesh is
+//! reading it with `MeshReader` and writing it with `MeshWriter`.  The Exodus II
+//! and Abaqus formats hold the element blocks, node sets and side sets, while
+//! the other formats keep less, as told by `MeshWriter::dropped`.
+//!
+//! The formats of each kind of file are value enums, named by their extensions,
+//! so that a file path is checked against the formats of its use before anything
+//! is read or written.
+
+use crate::abaqus;
+use crate::error::Error;
+use crate::exodus;
+use crate::mesh::{HexMesh, Mesh, NodeSet, SideSet, TriMesh};
+use crate::stl::StlMesh;
+use clap::ValueEnum;
+use std::path::Path;
+
+/// Returns the extension of a file path, or an empty string if it has none.
+pub fn extension(file_path: &str) -> &str {
+    Path::new(file_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+}
+
+/// A file format, whose value name is the file extension.
+pub trait FileFormat: ValueEnum + Copy {
+    /// Returns the format given by the extension of the file, if it is one of
+    /// the `allowed` formats.
+    fn from_path_in(file_path: &str, allowed: &[Self]) -> Result<Self, Error> {
+        let extension = extension(file_path);
+        allowed
+            .iter()
+            .copied()
+            .find(|format| {
+                format
+                    .to_possible_value()
+                    .is_some_and(|value| value.get_name() == extension)
+            })
+            .ok_or_else(|| Error::Format {
+                file_path: file_path.to_string(),
+                expected: choices(allowed),
+            })
+    }
+
+    /// Returns the format given by the extension of the file.
+    fn from_path(file_path: &str) -> Result<Self, Error> {
+        Self::from_path_in(file_path, Self::value_variants())
+    }
+}
+
+/// Returns the extensions of the formats as a list, such as `"exo, inp or stl"`.
+fn choices<F: FileFormat>(formats: &[F]) -> String {
+    let names: Vec<String> = formats
+        .iter()
+        .filter_map(|format| format.to_possible_value())
+        .map(|value| value.get_name().to_string())
+        .collect();
+    match names.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
+
+/// The formats of segmentation files.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum SegmentationFormat {
+    /// A NumPy array of shape `(nelz, nely, nelx)`.
+    Npy,
+    /// A text file with one label per line, and `x` changing fastest.
+    Spn,
+}
+
+impl FileFormat for SegmentationFormat {}
+
+impl SegmentationFormat {
+    /// Returns the format of a segmentation input file, which for `.spn` also
+    /// needs the number of voxels in each direction.
+    pub fn from_input(file_path: &str, nel: [Option<usize>; 3]) -> Result<Self, Error> {
+        let format = Self::from_path(file_path)?;
+        if format == Self::Spn && nel.contains(&None) {
+            return Err(Error::Nel {
+                file_path: file_path.to_string(),
+            });
+        }
+        Ok(format)
+    }
+}
+
+/// The formats of mesh files.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum MeshFormat {
+    /// Exodus II, in the NetCDF classic format.
+    Exo,
+    /// Abaqus input.
+    Inp,
+    /// Medit, which has no node sets or side sets.
+    Mesh,
+    /// STL, for triangles only.
+    Stl,
+    /// Legacy VTK, which has no node sets or side sets.
+    Vtk,
+}
+
+impl FileFormat for MeshFormat {}
+
+impl MeshFormat {
+    /// The formats that meshes are read from.
+    pub const INPUTS: [MeshFormat; 3] = [MeshFormat::Exo, MeshFormat::Inp, MeshFormat::Stl];
+
+    /// The formats that hexahedral meshes are written to, which are all but STL.
+    pub const HEX_OUTPUTS: [MeshFormat; 4] = [
+        MeshFormat::Exo,
+        MeshFormat::Inp,
+        MeshFormat::Mesh,
+        MeshFormat::Vtk,
+    ];
+
+    /// Returns the formats that a mesh is written to, which for hexahedra are
+    /// all but STL.  An Exodus II or Abaqus file may hold either element type,
+    /// so this is known only once the mesh is read.
+    pub fn outputs(mesh: &Mesh) -> &'static [MeshFormat] {
+        match mesh {
+            Mesh::Hex(_) => &MeshFormat::HEX_OUTPUTS,
+            Mesh::Tri(_) => MeshFormat::value_variants(),
+        }
+    }
+}
+
+/// The formats of quality metrics files.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum MetricsFormat {
+    /// Comma-separated values, with a header.
+    Csv,
+    /// A NumPy array of shape `(elements, 4)`.
+    Npy,
+}
+
+impl FileFormat for MetricsFormat {}
+
+/// A mesh that can be read from a file.
+pub trait MeshReader: Sized {
+    /// Reads the mesh in the format given by the extension of the file.
+    fn read(file_path: &str) -> Result<Self, Error>;
+}
+
+/// A mesh that can be written to a file.
+pub trait MeshWriter {
+    /// Writes the mesh in the format given by the extension of the file.
+    fn write(&self, file_path: &str) -> Result<(), Error>;
+
+    /// Returns the parts of the mesh that the format given by the extension of
+    /// the file cannot hold, such as `"node sets"`, or nothing when it holds
+    /// them all.
+    fn dropped(&self, file_path: &str) -> Vec<&'static str>;
+}
+
+impl MeshReader for Mesh {
+    fn read(file_path: &str) -> Result<Self, Error> {
+        Ok(
+            match MeshFormat::from_path_in(file_path, &MeshFormat::INPUTS)? {
+                MeshFormat::Exo => exodus::read_exo(file_path)?,
+                MeshFormat::Inp => abaqus::read_inp(file_path)?,
+                _ => Mesh::Tri(StlMesh::read(file_path)?.into()),
+            },
+        )
+    }
+}
+
+impl MeshReader for HexMesh {
+    fn read(file_path: &str) -> Result<Self, Error> {
+        match Mesh::read(file_path)? {
+            Mesh::Hex(mesh) => Ok(mesh),
+            Mesh::Tri(_) => Err(Error::Invalid(format!(
+                "{} has triangular elements, expected hexahedra",
+                file_path
+            ))),
+        }
+    }
+}
+
+impl MeshReader for TriMesh {
+    fn read(file_path: &str) -> Result<Self, Error> {
+        match Mesh::read(file_path)? {
+            Mesh::Hex(_) => Err(Error::Invalid(format!(
+                "{} has hexahedral elements, expected triangles",
+                file_path
+            ))),
+            Mesh::Tri(mesh) => Ok(mesh),
+        }
+    }
+}
+
+/// Returns the sets that a `.mesh`, `.stl` or `.vtk` file cannot hold.
+fn dropped_sets(node_sets: &[NodeSet], side_sets: &[SideSet]) -> Vec<&'static str> {
+    let mut dropped = Vec::new();
+    if !node_sets.is_empty() {
+        dropped.push("node sets");
+    }
+    if !side_sets.is_empty() {
+        dropped.push("side sets");
+    }
+    dropped
+}
+
+impl MeshWriter for HexMesh {
+    fn write(&self, file_path: &str) -> Result<(), Error> {
+        match MeshFormat::from_path_in(file_path, &MeshFormat::HEX_OUTPUTS)? {
+            MeshFormat::Exo => self.write_exo(file_path),
+            MeshFormat::Inp => self.write_inp(file_path),
+            MeshFormat::Mesh => self.write_mesh(file_path),
+            _ => self.write_vtk(file_path),
+        }
+        .map_err(Error::from)
+    }
+
+    fn dropped(&self, file_path: &str) -> Vec<&'static str> {
+        match MeshFormat::from_path(file_path) {
+            Ok(MeshFormat::Mesh | MeshFormat::Vtk) => {
+                dropped_sets(&self.node_sets, &self.side_sets)
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl MeshWriter for TriMesh {
+    fn write(&self, file_path: &str) -> Result<(), Error> {
+        match MeshFormat::from_path(file_path)? {
+            MeshFormat::Exo => self.write_exo(file_path),
+            MeshFormat::Inp => self.write_inp(file_path),
+            MeshFormat::Mesh => self.write_mesh(file_path),
+            MeshFormat::Stl => self.write_stl(file_path),
+            MeshFormat::Vtk => self.write_vtk(file_path),
+        }
+        .map_err(Error::from)
+    }
+
+    fn dropped(&self, file_path: &str) -> Vec<&'static str> {
+        let mut dropped = Vec::new();
+        // No format holds the block in front of each element.
+        if self.neighbor_blocks.iter().any(Option::is_some) {
+            dropped.push("blocks in front of the elements");
+        }
+        // An STL file is read back as the one block labeled one.
+        let format = MeshFormat::from_path(file_path).ok();
+        if format == Some(MeshFormat::Stl) && self.element_blocks.iter().any(|&block| block != 1) {
+            dropped.push("element blocks");
+        }
+        if let Some(MeshFormat::Mesh | MeshFormat::Stl | MeshFormat::Vtk) = format {
+            dropped.extend(dropped_sets(&self.node_sets, &self.side_sets));
+        }
+        dropped
+    }
+}
+
+impl MeshWriter for Mesh {
+    fn write(&self, file_path: &str) -> Result<(), Error> {
+        match self {
+            Mesh::Hex(mesh) => mesh.write(file_path),
+            Mesh::Tri(mesh) => mesh.write(file_path),
+        }
+    }
+
+    fn dropped(&self, file_path: &str) -> Vec<&'static str> {
+        match self {
+            Mesh::Hex(mesh) => mesh.dropped(file_path),
+            Mesh::Tri(mesh) => mesh.dropped(file_path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxels::{VoxelData, Voxels};
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("clap1_{}_{}", std::process::id(), name))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_convert() {
+        let data = VoxelData::from_shape_fn((2, 1, 2), |(z, _, _)| z as u8 + 1);
+        let mut mesh = HexMesh::from_voxels(&Voxels::from(data), &[]);
+        mesh.node_sets.push(NodeSet {
+            id: 1,
+            name: "bottom".to_string(),
+            nodes: vec![0, 1, 2],
+        });
+        let inp = temp_path("io_convert.inp");
+        mesh.write(&inp).unwrap();
+
+        // From inp to exo keeps the blocks and sets, and back again.
+        let read = Mesh::read(&inp).unwrap();
+        let exo = temp_path("io_convert.exo");
+        assert!(read.dropped(&exo).is_empty());
+        read.write(&exo).unwrap();
+        assert_eq!(HexMesh::read(&exo).unwrap(), HexMesh::read(&inp).unwrap());
+        assert!(
+            TriMesh::read(&exo)
+                .unwrap_err()
+                .to_string()
+                .contains("expected triangles")
+        );
+
+        assert_eq!(read.dropped("model.vtk"), vec!["node sets"]);
+        assert_eq!(
+            read.write("model.stl").unwrap_err().to_string(),
+            "Unsupported file extension for model.stl, use exo, inp, mesh or vtk"
+        );
+        assert_eq!(Mesh::read("model.vtk").unwrap_err().exit_code(), 2);
+
+        let tri = TriMesh::from_voxels(&Voxels::from(VoxelData::from_elem((1, 1, 2), 3)), &[]);
+        assert!(tri.dropped("model.exo").is_empty());
+        assert_eq!(tri.dropped("model.stl"), vec!["element blocks"]);
+    }
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(MeshFormat::from_path("a/model.mesh"), Ok(MeshFormat::Mesh));
+        assert_eq!(
+            MetricsFormat::from_path("metrics.txt")
+                .unwrap_err()
+                .to_string(),
+            "Unsupported file extension for metrics.txt, use csv or npy"
+        );
+        assert_eq!(
+            SegmentationFormat::from_input("model.npy", [None; 3]),
+            Ok(SegmentationFormat::Npy)
+        );
+        assert_eq!(
+            SegmentationFormat::from_input("model.spn", [Some(2), None, Some(2)]),
+            Err(Error::Nel {
+                file_path: "model.spn".to_string()
+            })
+        );
+    }
+}
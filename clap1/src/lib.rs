@@ -0,0 +1,35 @@
+//! Automatic mesh generation from segmentations, behind the `clap1` command line.
+//!
+//! The core types are the segmentation, `Voxels`, with its `Edit`s, and the
+//! meshes, `HexMesh` and `TriMesh`, with a `Transform` from voxel units and the
+//! `SmoothingParams` of smoothing.  Meshes are read and written through the `MeshReader` and
+//! `MeshWriter` traits, the `pipeline` module runs the steps of each command,
+//! and a `Recipe` runs several of them from one YAML file.  Their arguments are
+//! checked before they run, and any failure is an `Error`.
+
+pub mod abaqus;
+pub mod defeature;
+pub mod edit;
+pub mod error;
+pub mod exodus;
+pub mod io;
+pub mod medit;
+pub mod mesh;
+pub mod metrics;
+pub mod netcdf;
+pub mod npy;
+pub mod octree;
+pub mod pipeline;
+pub mod recipe;
+pub mod smooth;
+pub mod stl;
+pub mod surface;
+pub mod voxels;
+pub mod vtk;
+
+pub use edit::Edit;
+pub use error::Error;
+pub use io::{FileFormat, MeshFormat, MeshReader, MeshWriter};
+pub use mesh::{HexMesh, Mesh, Transform, TriMesh};
+pub use smooth::SmoothingParams;
+pub use voxels::Voxels;
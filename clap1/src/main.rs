@@ -1,4 +1,11 @@
 use clap::{Parser, Subcommand};
+use clap1::Error;
+use clap1::edit::{self, AxisOrder, Direction, Edit};
+use clap1::mesh::Transform;
+use clap1::pipeline::{self, MeshOptions};
+use clap1::recipe::Recipe;
+use clap1::smooth::{self, SmoothingMethod, SmoothingParams};
+use std::process::ExitCode;
 use std::time::Instant;
 
 macro_rules! about {
@@ -52,17 +59,8 @@ enum Commands {
         #[arg(long, short, value_name = "MIN")]
         min: usize,
 
-        /// Number of voxels in the x-direction
-        #[arg(long, short = 'x', value_name = "NEL")]
-        nelx: Option<usize>,
-
-        /// Number of voxels in the y-direction
-        #[arg(long, short = 'y', value_name = "NEL")]
-        nely: Option<usize>,
-
-        /// Number of voxels in the z-direction
-        #[arg(long, short = 'z', value_name = "NEL")]
-        nelz: Option<usize>,
+        #[command(flatten)]
+        nel: NelArgs,
 
         /// Pass to quiet the terminal output
         #[arg(action, long, short)]
@@ -75,7 +73,7 @@ enum Commands {
     },
     /// Quality metrics for an existing finite element mesh
     Metrics {
-        /// Mesh input file (inp | stl)
+        /// Mesh input file (exo | inp | stl)
         #[arg(long, short, value_name = "FILE")]
         input: String,
 
@@ -87,13 +85,21 @@ enum Commands {
         #[arg(action, long, short)]
         quiet: bool,
     },
+    /// Runs the steps of a YAML recipe file
+    ///
+    /// The options take the place of the input file, the numbers of voxels, and
+    /// the scaling and translation of the recipe.  The parameters of the steps,
+    /// such as those of smoothing and defeaturing, come only from the recipe, and
+    /// its relative paths are relative to the directory of the recipe file.
+    Run(RunArgs),
+    /// Edits a segmentation before meshing
+    Segmentation {
+        #[command(subcommand)]
+        subcommand: SegmentationSubcommand,
+    },
     /// Applies smoothing to an existing mesh
     Smooth {
-        /// Pass to enable hierarchical control
-        #[arg(action, long, short = 'c')]
-        hierarchical: bool,
-
-        /// Mesh input file (inp | stl)
+        /// Mesh input file (exo | inp | stl)
         #[arg(long, short, value_name = "FILE")]
         input: String,
 
@@ -101,21 +107,8 @@ enum Commands {
         #[arg(long, short, value_name = "FILE")]
         output: String,
 
-        /// Number of smoothing iterations
-        #[arg(default_value_t = 20, long, short = 'n', value_name = "NUM")]
-        iterations: usize,
-
-        /// Smoothing method (Laplace | Taubin) [default: Taubin]
-        #[arg(long, short, value_name = "NAME")]
-        method: Option<String>,
-
-        /// Pass-band frequency (for Taubin only)
-        #[arg(default_value_t = 0.1, long, short = 'k', value_name = "FREQ")]
-        pass_band: f64,
-
-        /// Scaling parameter for all smoothing methods
-        #[arg(default_value_t = 0.6307, long, short, value_name = "SCALE")]
-        scale: f64,
+        #[command(flatten)]
+        smoothing: SmoothArgs,
 
         /// Quality metrics output file (csv | npy)
         #[arg(long, value_name = "FILE")]
@@ -124,12 +117,12 @@ enum Commands {
         /// Pass to quiet the terminal output
         #[arg(action, long, short)]
         quiet: bool,
-    }
+    },
 }
 
 #[derive(Subcommand)]
 enum ConvertSubcommand {
-    /// Converts mesh file types (inp) -> (exo | mesh | vtk)
+    /// Converts mesh file types (exo | inp | stl) -> (exo | inp | mesh | stl | vtk)
     Mesh(ConvertMeshArgs),
     /// Converts segmentation file types (npy | spn) -> (npy | spn)
     Segmentation(ConvertSegmentationArgs),
@@ -143,13 +136,152 @@ enum MeshSubcommand {
     Tri(MeshTriArgs),
 }
 
+#[derive(Subcommand)]
+enum SegmentationSubcommand {
+    /// Crops to a box of voxels, by default the smallest without void
+    Crop {
+        #[command(flatten)]
+        segmentation: SegmentationArgs,
+
+        /// First voxel of the box in x, y and z [default: fit]
+        #[arg(long, num_args = 3, value_names = ["X", "Y", "Z"])]
+        min: Option<Vec<usize>>,
+
+        /// Last voxel of the box in x, y and z [default: fit]
+        #[arg(long, num_args = 3, value_names = ["X", "Y", "Z"])]
+        max: Option<Vec<usize>>,
+
+        /// Voxel ID left out of the box that fits
+        #[arg(default_value_t = 0, long, value_name = "ID")]
+        void: u8,
+    },
+    /// Merges blocks of voxels into one, by majority vote
+    Downsample {
+        #[command(flatten)]
+        segmentation: SegmentationArgs,
+
+        /// Number of voxels merged in each direction
+        #[arg(long, short, value_name = "NUM")]
+        factor: usize,
+    },
+    /// Reverses the order of the voxels along axes
+    Flip {
+        #[command(flatten)]
+        segmentation: SegmentationArgs,
+
+        /// Axes to flip
+        #[arg(long, num_args = 1.., required = true, short, value_delimiter = ' ', value_enum, value_name = "AXIS")]
+        axis: Vec<Direction>,
+    },
+    /// Pads each side with voxels of one ID
+    Pad {
+        #[command(flatten)]
+        segmentation: SegmentationArgs,
+
+        /// Number of voxels added to each side
+        #[arg(long, short, value_name = "NUM")]
+        width: usize,
+
+        /// Voxel ID of the padding
+        #[arg(default_value_t = 0, long, short, value_name = "ID")]
+        label: u8,
+    },
+    /// Reorders voxel data stored with another axis order
+    Permute {
+        #[command(flatten)]
+        segmentation: SegmentationArgs,
+
+        /// Axis order of the input, from the slowest changing, or its spn_xyz_order 0 to 5
+        #[arg(long, value_enum, value_name = "ORDER")]
+        order: AxisOrder,
+    },
+    /// Changes voxel IDs with a YAML map of old: new IDs
+    Relabel {
+        #[command(flatten)]
+        segmentation: SegmentationArgs,
+
+        /// Relabeling map file (yml)
+        #[arg(long, short, value_name = "FILE")]
+        map: String,
+    },
+    /// Splits each voxel into blocks of voxels
+    Upsample {
+        #[command(flatten)]
+        segmentation: SegmentationArgs,
+
+        /// Number of voxels split into in each direction
+        #[arg(long, short, value_name = "NUM")]
+        factor: usize,
+    },
+}
+
+impl SegmentationSubcommand {
+    /// Returns the options shared by every edit.
+    fn segmentation(&self) -> &SegmentationArgs {
+        match self {
+            SegmentationSubcommand::Crop { segmentation, .. }
+            | SegmentationSubcommand::Downsample { segmentation, .. }
+            | SegmentationSubcommand::Flip { segmentation, .. }
+            | SegmentationSubcommand::Pad { segmentation, .. }
+            | SegmentationSubcommand::Permute { segmentation, .. }
+            | SegmentationSubcommand::Relabel { segmentation, .. }
+            | SegmentationSubcommand::Upsample { segmentation, .. } => segmentation,
+        }
+    }
+
+    /// Runs the edit on the input file.
+    fn run(self) -> Result<(), Error> {
+        let edit = match &self {
+            SegmentationSubcommand::Crop { min, max, void, .. } => Edit::Crop {
+                min: min.as_deref().map(voxel),
+                max: max.as_deref().map(voxel),
+                void: *void,
+            },
+            SegmentationSubcommand::Downsample { factor, .. } => {
+                Edit::Downsample { factor: *factor }
+            }
+            SegmentationSubcommand::Flip { axis, .. } => Edit::Flip(axis.clone()),
+            SegmentationSubcommand::Pad { width, label, .. } => Edit::Pad {
+                width: [*width; 3],
+                label: *label,
+            },
+            SegmentationSubcommand::Permute { order, .. } => Edit::Permute(*order),
+            SegmentationSubcommand::Relabel { map, .. } => {
+                Edit::Relabel(edit::read_relabel_map(map)?)
+            }
+            SegmentationSubcommand::Upsample { factor, .. } => Edit::Upsample { factor: *factor },
+        };
+        let args = self.segmentation();
+        pipeline::edit_segmentation(&args.input, &args.output, args.nel.nel(), &edit, args.quiet)
+    }
+}
+
+/// The options shared by the `segmentation` commands.
+#[derive(clap::Args)]
+struct SegmentationArgs {
+    /// Segmentation input file (npy | spn)
+    #[arg(long, short, value_name = "FILE")]
+    input: String,
+
+    /// Edited segmentation output file (npy | spn)
+    #[arg(long, short, value_name = "FILE")]
+    output: String,
+
+    #[command(flatten)]
+    nel: NelArgs,
+
+    /// Pass to quiet the terminal output
+    #[arg(action, long, short)]
+    quiet: bool,
+}
+
 #[derive(clap::Args)]
 struct ConvertMeshArgs {
-    /// Mesh input file (inp)
+    /// Mesh input file (exo | inp | stl)
     #[arg(long, short, value_name = "FILE")]
     input: String,
 
-    /// Mesh output file (exo | mesh | vtk)
+    /// Mesh output file (exo | inp | mesh | stl | vtk)
     #[arg(long, short, value_name = "FILE")]
     output: String,
 
@@ -168,6 +300,17 @@ struct ConvertSegmentationArgs {
     #[arg(long, short, value_name = "FILE")]
     output: String,
 
+    #[command(flatten)]
+    nel: NelArgs,
+
+    /// Pass to quiet the terminal output
+    #[arg(action, long, short)]
+    quiet: bool,
+}
+
+/// The number of voxels in each direction, which a `.spn` file does not hold.
+#[derive(clap::Args)]
+struct NelArgs {
     /// Number of voxels in the x-direction
     #[arg(long, short = 'x', value_name = "NEL")]
     nelx: Option<usize>,
@@ -179,22 +322,70 @@ struct ConvertSegmentationArgs {
     /// Number of voxels in the z-direction
     #[arg(long, short = 'z', value_name = "NEL")]
     nelz: Option<usize>,
+}
 
-    /// Pass to quiet the terminal output
-    #[arg(action, long, short)]
-    quiet: bool,
+impl NelArgs {
+    fn nel(&self) -> [Option<usize>; 3] {
+        [self.nelx, self.nely, self.nelz]
+    }
 }
 
+/// The scaling and translation of a new mesh from voxel units.
 #[derive(clap::Args)]
-struct MeshHexArgs {
-    #[command(subcommand)]
-    smoothing: Option<MeshSmoothCommands>,
+struct TransformArgs {
+    /// Scaling (> 0.0) in the x-direction, applied before translation [default: 1]
+    #[arg(long, value_name = "SCALE")]
+    xscale: Option<f64>,
+
+    /// Scaling (> 0.0) in the y-direction, applied before translation [default: 1]
+    #[arg(long, value_name = "SCALE")]
+    yscale: Option<f64>,
+
+    /// Scaling (> 0.0) in the z-direction, applied before translation [default: 1]
+    #[arg(long, value_name = "SCALE")]
+    zscale: Option<f64>,
+
+    /// Translation in the x-direction [default: 0]
+    #[arg(long, allow_negative_numbers = true, value_name = "VAL")]
+    xtranslate: Option<f64>,
+
+    /// Translation in the y-direction [default: 0]
+    #[arg(long, allow_negative_numbers = true, value_name = "VAL")]
+    ytranslate: Option<f64>,
+
+    /// Translation in the z-direction [default: 0]
+    #[arg(long, allow_negative_numbers = true, value_name = "VAL")]
+    ztranslate: Option<f64>,
+}
+
+impl TransformArgs {
+    /// Returns the `transform` with the values of the given options in place of its own.
+    fn over(&self, transform: Transform) -> Transform {
+        let [xscale, yscale, zscale] = transform.scale;
+        let [xtranslate, ytranslate, ztranslate] = transform.translate;
+        Transform {
+            scale: [
+                self.xscale.unwrap_or(xscale),
+                self.yscale.unwrap_or(yscale),
+                self.zscale.unwrap_or(zscale),
+            ],
+            translate: [
+                self.xtranslate.unwrap_or(xtranslate),
+                self.ytranslate.unwrap_or(ytranslate),
+                self.ztranslate.unwrap_or(ztranslate),
+            ],
+        }
+    }
+}
 
+/// The options shared by `mesh hex` and `mesh tri`.
+#[derive(clap::Args)]
+struct MeshArgs {
     /// Segmentation input file (npy | spn)
     #[arg(long, short, value_name = "FILE")]
     input: String,
 
-    /// Mesh output file (exo | inp | mesh | vtk)
+    /// Mesh output file (exo | inp | mesh | vtk, or stl for triangles)
     #[arg(long, short, value_name = "FILE")]
     output: String,
 
@@ -202,60 +393,15 @@ struct MeshHexArgs {
     #[arg(long, short, value_name = "NUM")]
     defeature: Option<usize>,
 
-    /// Number of voxels in the x-direction
-    #[arg(long, short = 'x', value_name = "NEL")]
-    nelx: Option<usize>,
-
-    /// Number of voxels in the y-direction
-    #[arg(long, short = 'y', value_name = "NEL")]
-    nely: Option<usize>,
-
-    /// Number of voxels in the z-direction
-    #[arg(long, short = 'z', value_name = "NEL")]
-    nelz: Option<usize>,
+    #[command(flatten)]
+    nel: NelArgs,
 
     /// Voxel IDs to remove from the mesh
     #[arg(long, num_args = 1.., short, value_delimiter = ' ', value_name = "ID")]
     remove: Option<Vec<usize>>,
 
-    /// Scaling (> 0.0) in the x-direction, applied before translation
-    #[arg(default_value_t = 1.0, long, value_name = "SCALE")]
-    xscale: f64,
-
-    /// Scaling (> 0.0) in the y-direction, applied before translation
-    #[arg(default_value_t = 1.0, long, value_name = "SCALE")]
-    yscale: f64,
-
-    /// Scaling (> 0.0) in the z-direction, applied before translation
-    #[arg(default_value_t = 1.0, long, value_name = "SCALE")]
-    zscale: f64,
-
-    /// Translation in the x-direction
-    #[arg(
-        long,
-        default_value_t = 0.0,
-        allow_negative_numbers = true,
-        value_name = "VAL"
-    )]
-    xtranslate: f64,
-
-    /// Translation in the y-direction
-    #[arg(
-        long,
-        default_value_t = 0.0,
-        allow_negative_numbers = true,
-        value_name = "VAL"
-    )]
-    ytranslate: f64,
-
-    /// Translation in the z-direction
-    #[arg(
-        long,
-        default_value_t = 0.0,
-        allow_negative_numbers = true,
-        value_name = "VAL"
-    )]
-    ztranslate: f64,
+    #[command(flatten)]
+    transform: TransformArgs,
 
     /// Quality metrics output file (csv | npy)
     #[arg(long, value_name = "FILE")]
@@ -264,198 +410,183 @@ struct MeshHexArgs {
     /// Pass to quiet the terminal output
     #[arg(action, long, short)]
     quiet: bool,
+}
 
-    /// Pass to mesh using dualization
-    #[arg(action, hide = true, long)]
-    dual: bool,
+impl MeshArgs {
+    /// Runs `mesh` with hexahedra, or with triangles.
+    fn run(
+        self,
+        smoothing: Option<MeshSmoothCommands>,
+        dual: bool,
+        hex: bool,
+    ) -> Result<(), Error> {
+        let options = MeshOptions {
+            defeature: self.defeature,
+            remove: labels(self.remove)?,
+            dual,
+            transform: self.transform.over(Transform::default()),
+            smoothing: smoothing.map(|MeshSmoothCommands::Smooth(args)| args.params()),
+            metrics: self.metrics,
+        };
+        let nel = self.nel.nel();
+        if hex {
+            pipeline::mesh_hex(&self.input, &self.output, nel, &options, self.quiet)
+        } else {
+            pipeline::mesh_tri(&self.input, &self.output, nel, &options, self.quiet)
+        }
+    }
 }
 
 #[derive(clap::Args)]
-struct MeshTriArgs {
+struct MeshHexArgs {
     #[command(subcommand)]
     smoothing: Option<MeshSmoothCommands>,
 
-    /// Segmentation input file (npy | spn)
-    #[arg(long, short, value_name = "FILE")]
-    input: String,
-
-    /// Mesh output file (exo | inp | mesh | stl | vtk)
-    #[arg(long, short, value_name = "FILE")]
-    output: String,
-
-    /// Defeature clusters with less than NUM voxels
-    #[arg(long, short, value_name = "NUM")]
-    defeature: Option<usize>,
-
-    /// Number of voxels in the x-direction
-    #[arg(long, short = 'x', value_name = "NEL")]
-    nelx: Option<usize>,
-
-    /// Number of voxels in the y-direction
-    #[arg(long, short = 'y', value_name = "NEL")]
-    nely: Option<usize>,
-
-    /// Number of voxels in the z-direction
-    #[arg(long, short = 'z', value_name = "NEL")]
-    nelz: Option<usize>,
+    #[command(flatten)]
+    mesh: MeshArgs,
 
-    /// Voxel IDs to remove from the mesh
-    #[arg(long, num_args = 1.., short, value_delimiter = ' ', value_name = "ID")]
-    remove: Option<Vec<usize>>,
+    /// Pass to mesh a balanced octree, with hexahedral templates at transitions
+    #[arg(action, long)]
+    dual: bool,
+}
 
-    /// Scaling (> 0.0) in the x-direction, applied before translation
-    #[arg(default_value_t = 1.0, long, value_name = "SCALE")]
-    xscale: f64,
+#[derive(clap::Args)]
+struct MeshTriArgs {
+    #[command(subcommand)]
+    smoothing: Option<MeshSmoothCommands>,
 
-    /// Scaling (> 0.0) in the y-direction, applied before translation
-    #[arg(default_value_t = 1.0, long, value_name = "SCALE")]
-    yscale: f64,
+    #[command(flatten)]
+    mesh: MeshArgs,
+    // There is no octree meshing for triangles, only hexahedra.
+}
 
-    /// Scaling (> 0.0) in the z-direction, applied before translation
-    #[arg(default_value_t = 1.0, long, value_name = "SCALE")]
-    zscale: f64,
+#[derive(Subcommand, Debug)]
+enum MeshSmoothCommands {
+    /// Applies smoothing to the mesh before output
+    Smooth(SmoothArgs),
+}
 
-    /// Translation in the x-direction
-    #[arg(
-        long,
-        default_value_t = 0.0,
-        allow_negative_numbers = true,
-        value_name = "VAL"
-    )]
-    xtranslate: f64,
+/// The options of `run`, which take the place of the values in the recipe.
+#[derive(clap::Args)]
+struct RunArgs {
+    /// Recipe file (yml)
+    #[arg(value_name = "FILE")]
+    recipe: String,
 
-    /// Translation in the y-direction
-    #[arg(
-        long,
-        default_value_t = 0.0,
-        allow_negative_numbers = true,
-        value_name = "VAL"
-    )]
-    ytranslate: f64,
+    /// Input file in place of the recipe input (npy | spn | exo | inp | stl)
+    #[arg(long, short, value_name = "FILE")]
+    input: Option<String>,
 
-    /// Translation in the z-direction
-    #[arg(
-        long,
-        default_value_t = 0.0,
-        allow_negative_numbers = true,
-        value_name = "VAL"
-    )]
-    ztranslate: f64,
+    #[command(flatten)]
+    nel: NelArgs,
 
-    /// Quality metrics output file (csv | npy)
-    #[arg(long, value_name = "FILE")]
-    metrics: Option<String>,
+    #[command(flatten)]
+    transform: TransformArgs,
 
     /// Pass to quiet the terminal output
     #[arg(action, long, short)]
     quiet: bool,
+}
 
-    // There is no dualization for triangles, only hexahedra.
+impl RunArgs {
+    /// Reads the recipe, with the given options in place of its values, and runs it.
+    fn run(self) -> Result<(), Error> {
+        let mut recipe = Recipe::from_file(&self.recipe)?;
+        if let Some(input) = self.input {
+            recipe.input = input;
+        }
+        recipe.nelx = self.nel.nelx.or(recipe.nelx);
+        recipe.nely = self.nel.nely.or(recipe.nely);
+        recipe.nelz = self.nel.nelz.or(recipe.nelz);
+        let Transform { scale, translate } = self.transform.over(recipe.transform());
+        [recipe.scale_x, recipe.scale_y, recipe.scale_z] = scale.map(Some);
+        [recipe.translate_x, recipe.translate_y, recipe.translate_z] = translate.map(Some);
+        recipe.run(self.quiet)
+    }
 }
 
-#[derive(Subcommand, Debug)]
-enum MeshSmoothCommands {
-    /// Applies smoothing to the mesh before output
-    Smooth {
-        /// Pass to enable hierarchical control
-        #[arg(action, long, short = 'c')]
-        hierarchical: bool,
+/// The options of `smooth`, and of the nested `smooth` command of `mesh`.
+#[derive(clap::Args, Debug)]
+struct SmoothArgs {
+    /// Pass to enable hierarchical control
+    #[arg(action, long, short = 'c')]
+    hierarchical: bool,
 
-        /// Number of smoothing iterations
-        #[arg(default_value_t = 20, long, short = 'n', value_name = "NUM")]
-        iterations: usize,
+    /// Number of smoothing iterations (at least 1)
+    #[arg(default_value_t = smooth::ITERATIONS_DEFAULT, long, short = 'n', value_name = "NUM")]
+    iterations: usize,
 
-        /// Smoothing method (Laplace | Taubin) [default: Taubin]
-        #[arg(long, short, value_name = "NAME")]
-        method: Option<String>,
+    /// Smoothing method
+    #[arg(
+        default_value_t = SmoothingMethod::Taubin,
+        ignore_case = true,
+        long,
+        short,
+        value_enum,
+        value_name = "NAME"
+    )]
+    method: SmoothingMethod,
 
-        /// Pass-band frequency (for Taubin only)
-        #[arg(default_value_t = 0.1, long, short = 'k', value_name = "FREQ")]
-        pass_band: f64,
+    /// Pass-band frequency (0.0 < FREQ < 1.0, for Taubin only)
+    #[arg(default_value_t = smooth::PASS_BAND_DEFAULT, long, short = 'k', value_name = "FREQ")]
+    pass_band: f64,
 
-        /// Scaling parameter for all smoothing methods
-        #[arg(default_value_t = 0.6307, long, short, value_name = "SCALE")]
-        scale: f64,
-    },
+    /// Scaling parameter (0.0 < SCALE < 1.0) for all smoothing methods
+    #[arg(default_value_t = smooth::SCALE_DEFAULT, long, short, value_name = "SCALE")]
+    scale: f64,
+}
+
+impl SmoothArgs {
+    /// Returns the smoothing parameters, which the pipeline checks before it runs.
+    fn params(self) -> SmoothingParams {
+        SmoothingParams {
+            method: self.method,
+            iterations: self.iterations,
+            scale: self.scale,
+            pass_band: self.pass_band,
+            hierarchical: self.hierarchical,
+        }
+    }
 }
 
-fn main() {
+fn main() -> ExitCode {
     let time = Instant::now();
     let cli = Cli::parse();
     let mut is_quiet = false;
-    match cli.command {
+    let result = match cli.command {
         Some(Commands::Convert { subcommand }) => match subcommand {
             ConvertSubcommand::Mesh(args) => {
                 is_quiet = args.quiet;
-                convert_mesh(args.input, args.output, args.quiet);
+                pipeline::convert_mesh(&args.input, &args.output, args.quiet)
             }
             ConvertSubcommand::Segmentation(args) => {
                 is_quiet = args.quiet;
-                convert_segmentation(
-                    args.input,
-                    args.output,
-                    args.nelx,
-                    args.nely,
-                    args.nelz,
+                pipeline::convert_segmentation(
+                    &args.input,
+                    &args.output,
+                    args.nel.nel(),
                     args.quiet,
-                );
+                )
             }
         },
         Some(Commands::Defeature {
             input,
             output,
             min,
-            nelx,
-            nely,
-            nelz,
+            nel,
             quiet,
         }) => {
             is_quiet = quiet;
-            defeature(input, output, min, nelx, nely, nelz, quiet)
+            pipeline::defeature(&input, &output, min, nel.nel(), quiet)
         }
         Some(Commands::Mesh { subcommand }) => match subcommand {
             MeshSubcommand::Hex(args) => {
-                is_quiet = args.quiet;
-                mesh_hex(
-                    args.smoothing,
-                    args.input,
-                    args.output,
-                    args.defeature,
-                    args.nelx,
-                    args.nely,
-                    args.nelz,
-                    args.remove,
-                    args.xscale,
-                    args.yscale,
-                    args.zscale,
-                    args.xtranslate,
-                    args.ytranslate,
-                    args.ztranslate,
-                    args.metrics,
-                    args.quiet,
-                    args.dual,
-                );
+                is_quiet = args.mesh.quiet;
+                args.mesh.run(args.smoothing, args.dual, true)
             }
             MeshSubcommand::Tri(args) => {
-                is_quiet = args.quiet;
-                mesh_tri(
-                    args.smoothing,
-                    args.input,
-                    args.output,
-                    args.defeature,
-                    args.nelx,
-                    args.nely,
-                    args.nelz,
-                    args.remove,
-                    args.xscale,
-                    args.yscale,
-                    args.zscale,
-                    args.xtranslate,
-                    args.ytranslate,
-                    args.ztranslate,
-                    args.metrics,
-                    args.quiet,
-                );
+                is_quiet = args.mesh.quiet;
+                args.mesh.run(args.smoothing, false, false)
             }
         },
         Some(Commands::Metrics {
@@ -464,187 +595,54 @@ fn main() {
             quiet,
         }) => {
             is_quiet = quiet;
-            metrics(input, output, quiet)
-        },
-        Some(Commands::Smooth { hierarchical, input, output, iterations, method, pass_band, scale, metrics, quiet }) => {
+            pipeline::metrics(&input, &output, quiet)
+        }
+        Some(Commands::Run(args)) => {
+            is_quiet = args.quiet;
+            args.run()
+        }
+        Some(Commands::Segmentation { subcommand }) => {
+            is_quiet = subcommand.segmentation().quiet;
+            subcommand.run()
+        }
+        Some(Commands::Smooth {
+            input,
+            output,
+            smoothing,
+            metrics,
+            quiet,
+        }) => {
             is_quiet = quiet;
-            smooth(
-                hierarchical,
-                input,
-                output,
-                iterations,
-                method,
-                pass_band,
-                scale,
-                metrics,
-                quiet,
-            )
+            let params = smoothing.params();
+            pipeline::smooth(&input, &output, &params, metrics.as_deref(), quiet)
         }
-        None => (),
+        None => Ok(()),
+    };
+    if let Err(error) = result {
+        eprintln!("\x1b[1;91merror:\x1b[0m \x1b[1m{}.\x1b[0m", error);
+        return ExitCode::from(error.exit_code());
     }
     if !is_quiet {
         println!("       \x1b[1;98mTotal\x1b[0m {:?}", time.elapsed());
     }
+    ExitCode::SUCCESS
 }
 
-fn convert_mesh(input: String, output: String, quiet: bool) {
-    println!("function: convert");
-    println!("  input: {input}");
-    println!("  output: {output}");
-    println!("  quiet: {quiet}")
+/// Converts the three values of a `--min` or `--max` option to a voxel.
+fn voxel(values: &[usize]) -> [usize; 3] {
+    [values[0], values[1], values[2]]
 }
 
-fn convert_segmentation(
-    input: String,
-    output: String,
-    nelx: Option<usize>,
-    nely: Option<usize>,
-    nelz: Option<usize>,
-    quiet: bool,
-) {
-    println!("function: convert");
-    println!("  input: {input}");
-    println!("  output: {output}");
-    println!("  nelx: {:?}", nelx);
-    println!("  nely: {:?}", nely);
-    println!("  nelz: {:?}", nelz);
-    println!("  quiet: {quiet}")
+/// Converts voxel IDs to labels, which must be in the range of a `u8`.
+fn labels(ids: Option<Vec<usize>>) -> Result<Vec<u8>, Error> {
+    ids.unwrap_or_default()
+        .into_iter()
+        .map(|id| {
+            u8::try_from(id).map_err(|_| Error::Value {
+                name: "voxel ID",
+                value: id.to_string(),
+                expected: "a value up to 255",
+            })
+        })
+        .collect()
 }
-
-fn defeature(
-    input: String,
-    output: String,
-    min: usize,
-    nelx: Option<usize>,
-    nely: Option<usize>,
-    nelz: Option<usize>,
-    quiet: bool,
-) {
-    println!("function: defeature");
-    println!("  input: {input}");
-    println!("  output: {output}");
-    println!("  min: {min}");
-    println!("  nelx: {:?}", nelx);
-    println!("  nely: {:?}", nely);
-    println!("  nelz: {:?}", nelz);
-    println!("  quiet: {quiet}")
-}
-
-#[allow(clippy::too_many_arguments)]
-fn mesh_hex(
-    smoothing: Option<MeshSmoothCommands>,
-    input: String,
-    output: String,
-    defeature: Option<usize>,
-    nelx: Option<usize>,
-    nely: Option<usize>,
-    nelz: Option<usize>,
-    remove: Option<Vec<usize>>,
-    xscale: f64,
-    yscale: f64,
-    zscale: f64,
-    xtranslate: f64,
-    ytranslate: f64,
-    ztranslate: f64,
-    metrics: Option<String>,
-    quiet: bool,
-    dual: bool,
-    // surface: bool,
-) {
-    println!("function: mesh_hex");
-    println!("  smoothing: {:?}", smoothing);
-    println!("  input: {input}");
-    println!("  output: {output}");
-    // println!("  min: {min}");
-    println!("  defeature: {:?}", defeature);
-    println!("  nelx: {:?}", nelx);
-    println!("  nely: {:?}", nely);
-    println!("  nelz: {:?}", nelz);
-    println!("  remove: {:?}", remove);
-    println!("  xscale: {xscale}");
-    println!("  yscale: {yscale}");
-    println!("  zscale: {zscale}");
-    println!("  xtranslate: {xtranslate}");
-    println!("  ytranslate: {ytranslate}");
-    println!("  ztranslate: {ztranslate}");
-    println!("  metrics: {:?}", metrics);
-    println!("  quiet: {quiet}");
-    println!("  dual: {dual}");
-    println!("  quiet: {quiet}");
-    // println!("  surface: {surface}");
-}
-
-#[allow(clippy::too_many_arguments)]
-fn mesh_tri(
-    smoothing: Option<MeshSmoothCommands>,
-    input: String,
-    output: String,
-    defeature: Option<usize>,
-    nelx: Option<usize>,
-    nely: Option<usize>,
-    nelz: Option<usize>,
-    remove: Option<Vec<usize>>,
-    xscale: f64,
-    yscale: f64,
-    zscale: f64,
-    xtranslate: f64,
-    ytranslate: f64,
-    ztranslate: f64,
-    metrics: Option<String>,
-    quiet: bool,
-    // dual: bool,
-    // surface: bool,
-) {
-    println!("function: mesh_tri");
-    println!("  smoothing: {:?}", smoothing);
-    println!("  input: {input}");
-    println!("  output: {output}");
-    // println!("  min: {min}");
-    println!("  defeature: {:?}", defeature);
-    println!("  nelx: {:?}", nelx);
-    println!("  nely: {:?}", nely);
-    println!("  nelz: {:?}", nelz);
-    println!("  remove: {:?}", remove);
-    println!("  xscale: {xscale}");
-    println!("  yscale: {yscale}");
-    println!("  zscale: {zscale}");
-    println!("  xtranslate: {xtranslate}");
-    println!("  ytranslate: {ytranslate}");
-    println!("  ztranslate: {ztranslate}");
-    println!("  metrics: {:?}", metrics);
-    println!("  quiet: {quiet}");
-    // println!("  dual: {dual}");
-    println!("  quiet: {quiet}");
-    // println!("  surface: {surface}");
-}
-
-fn metrics(input: String, output: String, quiet: bool) {
-    println!("function: metrics");
-    println!("  input: {input}");
-    println!("  output: {output}");
-    println!("  quiet: {quiet}");
-}
-
-#[allow(clippy::too_many_arguments)]
-fn smooth(
-    hierarchical: bool,
-    input: String,
-    output: String,
-    iterations: usize,
-    method: Option<String>,
-    pass_band: f64,
-    scale: f64,
-    metrics: Option<String>,
-    quiet: bool,
-) {
-    println!("function: smooth");
-    println!("  hierarchical: {hierarchical}");
-    println!("  input: {input}");
-    println!("  output: {output}");
-    println!("  iterations: {iterations}");
-    println!("  method: {:?}", method);
-    println!("  pass_band: {pass_band}");
-    println!("  scale: {scale}");
-    println!("  metrics: {:?}", metrics);
-    println!("  quiet: {quiet}");
-}
\ No newline at end of file
@@ -0,0 +1,120 @@
+//! Export of meshes to the INRIA Medit `.mesh` format.
+//!
+//! A mesh is written as ASCII, in double precision, with the vertices and then
+//! the hexahedra or triangles, each with its element block as its reference.
+//! Vertices have a reference of zero.
+
+use crate::mesh::{HexMesh, TriMesh};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+impl HexMesh {
+    /// Writes the mesh to a Medit `.mesh` file, with the elements in their order.
+    pub fn write_mesh(&self, file_path: &str) -> Result<(), String> {
+        write_mesh(
+            file_path,
+            &self.nodes,
+            &self.elements,
+            &self.element_blocks,
+            "Hexahedra",
+        )
+    }
+}
+
+impl TriMesh {
+    /// Writes the mesh to a Medit `.mesh` file, with the elements in their order.
+    ///
+    /// The blocks in front of the elements are not written.
+    pub fn write_mesh(&self, file_path: &str) -> Result<(), String> {
+        write_mesh(
+            file_path,
+            &self.nodes,
+            &self.elements,
+            &self.element_blocks,
+            "Triangles",
+        )
+    }
+}
+
+/// Writes nodes and elements of one kind to a Medit `.mesh` file.
+fn write_mesh<const N: usize>(
+    file_path: &str,
+    nodes: &[[f64; 3]],
+    elements: &[[usize; N]],
+    element_blocks: &[u8],
+    keyword: &str,
+) -> Result<(), String> {
+    let file =
+        File::create(file_path).map_err(|e| format!("Failed to create {}: {}", file_path, e))?;
+    let mut writer = BufWriter::new(file);
+    write_mesh_sections(&mut writer, nodes, elements, element_blocks, keyword)
+        .and_then(|_| writer.flush())
+        .map_err(|e| format!("Failed to write {}: {}", file_path, e))
+}
+
+/// Writes the sections of the `.mesh` file.
+fn write_mesh_sections<W: Write, const N: usize>(
+    writer: &mut W,
+    nodes: &[[f64; 3]],
+    elements: &[[usize; N]],
+    element_blocks: &[u8],
+    keyword: &str,
+) -> std::io::Result<()> {
+    writeln!(writer, "MeshVersionFormatted 2")?;
+    writeln!(writer, "Dimension 3")?;
+
+    writeln!(writer, "Vertices")?;
+    writeln!(writer, "{}", nodes.len())?;
+    for [x, y, z] in nodes {
+        writeln!(writer, "{} {} {} 0", x, y, z)?;
+    }
+
+    writeln!(writer, "{}", keyword)?;
+    writeln!(writer, "{}", elements.len())?;
+    for (element, block) in elements.iter().zip(element_blocks) {
+        for node in element {
+            write!(writer, "{} ", node + 1)?;
+        }
+        writeln!(writer, "{}", block)?;
+    }
+    writeln!(writer, "End")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxels::{VoxelData, Voxels};
+    use std::fs;
+
+    #[test]
+    fn test_write_mesh() {
+        let data = VoxelData::from_shape_fn((1, 1, 2), |(_, _, x)| x as u8 + 1);
+        let mesh = TriMesh::from_voxels(&Voxels::from(data), &[]);
+        let output = std::env::temp_dir().join(format!(
+            "clap1_{}_medit_write_mesh.mesh",
+            std::process::id()
+        ));
+        let output = output.to_string_lossy();
+        mesh.write_mesh(&output).unwrap();
+
+        let contents = fs::read_to_string(output.as_ref()).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(
+            lines[..4],
+            ["MeshVersionFormatted 2", "Dimension 3", "Vertices", "12"]
+        );
+        assert_eq!(lines[4], "0 0 0 0");
+        assert_eq!(lines[16..18], ["Triangles", "22"]);
+        let [a, b, c, block] = lines[18]
+            .split(' ')
+            .map(|field| field.parse::<usize>().unwrap())
+            .collect::<Vec<usize>>()[..]
+        else {
+            panic!("expected three nodes and a reference")
+        };
+        assert!([a, b, c].iter().all(|&n| (1..=12).contains(&n)));
+        assert_eq!(block, mesh.element_blocks[0] as usize);
+        assert_eq!(lines.last(), Some(&"End"));
+    }
+}
@@ -0,0 +1,319 @@
+//! Finite element meshes created from segmentations.
+
+use crate::error::Error;
+use crate::stl::StlMesh;
+use crate::voxels::Voxels;
+
+/// An indexed mesh of eight-node hexahedral elements.
+///
+/// Element nodes are ordered counter-clockwise around the bottom face, and then
+/// counter-clockwise around the top face, seen from above.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HexMesh {
+    /// The node coordinates.
+    pub nodes: Vec<[f64; 3]>,
+    /// The element connectivity, as eight node indices per element.
+    pub elements: Vec<[usize; 8]>,
+    /// The element block of each element, which is its voxel label.
+    pub element_blocks: Vec<u8>,
+    /// The named sets of nodes.
+    pub node_sets: Vec<NodeSet>,
+    /// The named sets of element sides.
+    pub side_sets: Vec<SideSet>,
+}
+
+/// An indexed mesh of three-node triangular elements.
+///
+/// Element nodes are ordered counter-clockwise when seen from the side that the
+/// normal points to.  Each triangle separates the block behind it from the block
+/// in front of it, or from void.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TriMesh {
+    /// The node coordinates.
+    pub nodes: Vec<[f64; 3]>,
+    /// The element connectivity, as three node indices per element.
+    pub elements: Vec<[usize; 3]>,
+    /// The element block of each element, which is the voxel label behind it.
+    pub element_blocks: Vec<u8>,
+    /// The voxel label in front of each element, or `None` for void.
+    pub neighbor_blocks: Vec<Option<u8>>,
+    /// The named sets of nodes.
+    pub node_sets: Vec<NodeSet>,
+    /// The named sets of element sides.
+    pub side_sets: Vec<SideSet>,
+}
+
+/// A mesh of either element type, as read from a file.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mesh {
+    Hex(HexMesh),
+    Tri(TriMesh),
+}
+
+/// A set of nodes, such as the nodes with a boundary condition.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NodeSet {
+    /// The positive identifier of the set.
+    pub id: usize,
+    pub name: String,
+    /// The node indices.
+    pub nodes: Vec<usize>,
+}
+
+/// A set of element sides, such as the faces with a surface load.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SideSet {
+    /// The positive identifier of the set.
+    pub id: usize,
+    pub name: String,
+    /// The element index and the side of the element, numbered from one in the
+    /// Exodus II order.
+    pub sides: Vec<(usize, usize)>,
+}
+
+/// A scaling of the node coordinates, followed by a translation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    /// The scaling in each direction.
+    pub scale: [f64; 3],
+    /// The translation in each direction, applied after the scaling.
+    pub translate: [f64; 3],
+}
+
+impl Default for Transform {
+    /// Constructs the identity, which leaves every node in place.
+    fn default() -> Self {
+        Self {
+            scale: [1.0; 3],
+            translate: [0.0; 3],
+        }
+    }
+}
+
+impl Transform {
+    /// Scales each node coordinate, and then translates it.
+    pub fn apply(&self, nodes: &mut [[f64; 3]]) {
+        for node in nodes {
+            for ((x, scale), translate) in node.iter_mut().zip(self.scale).zip(self.translate) {
+                *x = *x * scale + translate;
+            }
+        }
+    }
+
+    /// Checks that each scaling is positive and each translation is finite, so
+    /// that no element is inverted or collapsed.
+    pub fn validate(&self) -> Result<(), Error> {
+        let scales = [
+            "scaling in the x-direction",
+            "scaling in the y-direction",
+            "scaling in the z-direction",
+        ];
+        for (name, scale) in scales.into_iter().zip(self.scale) {
+            if !(scale.is_finite() && scale > 0.0) {
+                return Err(Error::Value {
+                    name,
+                    value: scale.to_string(),
+                    expected: "a value greater than 0",
+                });
+            }
+        }
+        if let Some(translate) = self.translate.iter().find(|t| !t.is_finite()) {
+            return Err(Error::Value {
+                name: "translation",
+                value: translate.to_string(),
+                expected: "a finite value",
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Returns the distinct element blocks, in ascending order.
+fn distinct_blocks(element_blocks: &[u8]) -> Vec<u8> {
+    let mut blocks = element_blocks.to_vec();
+    blocks.sort_unstable();
+    blocks.dedup();
+    blocks
+}
+
+impl HexMesh {
+    /// Builds a mesh with one element per voxel, in voxel units.
+    ///
+    /// Nodes are shared between neighboring voxels, and are numbered in the order
+    /// of the grid of voxel corners, with `x` changing fastest, then `y`, then `z`.
+    /// Elements follow the same order, and their blocks are the voxel labels.
+    ///
+    /// # Arguments
+    ///
+    /// * `voxels` - The segmentation.
+    /// * `remove` - The labels of voxels to leave out of the mesh.
+    pub fn from_voxels(voxels: &Voxels, remove: &[u8]) -> Self {
+        let [nelx, nely, nelz] = voxels.nel();
+        let corner = |x: usize, y: usize, z: usize| x + (nelx + 1) * (y + (nely + 1) * z);
+
+        let mut kept = Vec::new();
+        let mut element_blocks = Vec::new();
+        for ((z, y, x), &label) in voxels.get_data().indexed_iter() {
+            if remove.contains(&label) {
+                continue;
+            }
+            kept.push([
+                corner(x, y, z),
+                corner(x + 1, y, z),
+                corner(x + 1, y + 1, z),
+                corner(x, y + 1, z),
+                corner(x, y, z + 1),
+                corner(x + 1, y, z + 1),
+                corner(x + 1, y + 1, z + 1),
+                corner(x, y + 1, z + 1),
+            ]);
+            element_blocks.push(label);
+        }
+
+        // Number only the corners used by an element, in the order of the grid.
+        let mut used = vec![false; (nelx + 1) * (nely + 1) * (nelz + 1)];
+        for &corner in kept.iter().flatten() {
+            used[corner] = true;
+        }
+        let mut index_of = vec![0; used.len()];
+        let mut nodes = Vec::new();
+        for corner in 0..used.len() {
+            if used[corner] {
+                index_of[corner] = nodes.len();
+                let x = corner % (nelx + 1);
+                let y = (corner / (nelx + 1)) % (nely + 1);
+                let z = corner / ((nelx + 1) * (nely + 1));
+                nodes.push([x as f64, y as f64, z as f64]);
+            }
+        }
+        let elements = kept
+            .iter()
+            .map(|element| element.map(|corner| index_of[corner]))
+            .collect();
+
+        Self {
+            nodes,
+            elements,
+            element_blocks,
+            ..Default::default()
+        }
+    }
+
+    /// Scales and then translates every node.
+    pub fn transform(&mut self, transform: &Transform) {
+        transform.apply(&mut self.nodes);
+    }
+
+    /// Returns the distinct element blocks, in ascending order.
+    pub fn blocks(&self) -> Vec<u8> {
+        distinct_blocks(&self.element_blocks)
+    }
+}
+
+impl TriMesh {
+    /// Scales and then translates every node.
+    pub fn transform(&mut self, transform: &Transform) {
+        transform.apply(&mut self.nodes);
+    }
+
+    /// Returns the distinct element blocks, in ascending order.
+    pub fn blocks(&self) -> Vec<u8> {
+        distinct_blocks(&self.element_blocks)
+    }
+
+    /// Writes the mesh to a binary `.stl` file, without its blocks.
+    pub fn write_stl(&self, file_path: &str) -> Result<(), String> {
+        StlMesh {
+            vertices: self.nodes.clone(),
+            facets: self.elements.clone(),
+        }
+        .write_binary(file_path)
+    }
+}
+
+impl Mesh {
+    /// Scales and then translates every node.
+    pub fn transform(&mut self, transform: &Transform) {
+        match self {
+            Mesh::Hex(mesh) => mesh.transform(transform),
+            Mesh::Tri(mesh) => mesh.transform(transform),
+        }
+    }
+}
+
+impl From<StlMesh> for TriMesh {
+    /// Constructs a mesh of one block, labeled one, with void in front of every
+    /// element.
+    fn from(stl: StlMesh) -> Self {
+        Self {
+            element_blocks: vec![1; stl.facets.len()],
+            neighbor_blocks: vec![None; stl.facets.len()],
+            nodes: stl.vertices,
+            elements: stl.facets,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxels::VoxelData;
+    use std::fs;
+
+    #[test]
+    fn test_from_voxels() {
+        // Two voxels side by side in x, and a removed voxel on top of the first.
+        let mut data = VoxelData::from_elem((2, 1, 2), 0);
+        data[[0, 0, 0]] = 1;
+        data[[0, 0, 1]] = 2;
+        let mesh = HexMesh::from_voxels(&Voxels::from(data), &[0]);
+        assert_eq!(mesh.nodes.len(), 12);
+        assert_eq!(
+            mesh.elements,
+            vec![[0, 1, 4, 3, 6, 7, 10, 9], [1, 2, 5, 4, 7, 8, 11, 10]]
+        );
+        assert_eq!(mesh.element_blocks, vec![1, 2]);
+        assert_eq!(mesh.blocks(), vec![1, 2]);
+
+        let mut scaled = mesh.clone();
+        scaled.transform(&Transform {
+            scale: [2.0, 1.0, 0.5],
+            translate: [1.0, 0.0, -1.0],
+        });
+        assert_eq!(scaled.nodes[11], [5.0, 1.0, -0.5]);
+        assert_eq!(Transform::default().validate(), Ok(()));
+        let flipped = Transform {
+            scale: [1.0, -1.0, 1.0],
+            ..Default::default()
+        };
+        assert!(
+            flipped
+                .validate()
+                .unwrap_err()
+                .to_string()
+                .contains("scaling in the y-direction -1")
+        );
+    }
+
+    #[test]
+    fn test_letter_f() {
+        // The same nodes and elements as the automesh letter F, without void.
+        let input = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/input/letter_f.npy");
+        let voxels = Voxels::from_npy(input).unwrap();
+        let mesh = HexMesh::from_voxels(&voxels, &[0]);
+        assert_eq!(mesh.nodes.len(), 102);
+        assert_eq!(mesh.elements.len(), 39);
+
+        let output =
+            std::env::temp_dir().join(format!("clap1_{}_mesh_letter_f.inp", std::process::id()));
+        let output = output.to_string_lossy();
+        mesh.write_inp(&output).unwrap();
+        let expected = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/input/letter_f.inp");
+        let expected = fs::read_to_string(expected).unwrap();
+        let written = fs::read_to_string(output.as_ref()).unwrap();
+        // Everything after the heading is the same.
+        let start = |contents: &str| contents.find("*NODE").unwrap();
+        assert_eq!(written[start(&written)..], expected[start(&expected)..]);
+    }
+}
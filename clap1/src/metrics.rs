@@ -0,0 +1,408 @@
+//! Element quality metrics for hexahedral and triangular meshes.
+//!
+//! The metrics follow the definitions of the Verdict library, as in the
+//! `quadtree` prototype, so that meshes can be compared with those of other
+//! tools.
+
+use crate::mesh::{HexMesh, Mesh, TriMesh};
+use ndarray::Array2;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// The names of the hexahedral metrics, in column order.
+pub const HEX_METRICS: [&str; 4] = [
+    "min_scaled_jacobian",
+    "max_edge_ratio",
+    "max_skew",
+    "volume",
+];
+
+/// The names of the triangular metrics, in column order.
+pub const TRI_METRICS: [&str; 4] = ["min_angle", "aspect_ratio", "area", "edge_ratio"];
+
+/// The three neighbors of each hexahedron corner, ordered so that the edges to
+/// them form a right-handed frame in an element that is not inverted.
+const HEX_CORNERS: [[usize; 3]; 8] = [
+    [1, 3, 4],
+    [2, 0, 5],
+    [3, 1, 6],
+    [0, 2, 7],
+    [7, 5, 0],
+    [4, 6, 1],
+    [5, 7, 2],
+    [6, 4, 3],
+];
+
+/// The corners of the reference hexahedron, with coordinates of -1 or 1.
+const HEX_REFERENCE: [[f64; 3]; 8] = [
+    [-1.0, -1.0, -1.0],
+    [1.0, -1.0, -1.0],
+    [1.0, 1.0, -1.0],
+    [-1.0, 1.0, -1.0],
+    [-1.0, -1.0, 1.0],
+    [1.0, -1.0, 1.0],
+    [1.0, 1.0, 1.0],
+    [-1.0, 1.0, 1.0],
+];
+
+/// The quality metrics of every element of a mesh.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Metrics {
+    /// The names of the metrics, in column order.
+    pub names: [&'static str; 4],
+    /// The metric values of each element, in element order.
+    pub values: Vec<[f64; 4]>,
+    /// The indices of the inverted hexahedra, with a negative scaled Jacobian.
+    /// A triangle has no orientation to invert.
+    pub inverted: Vec<usize>,
+    /// The indices of the degenerate elements, which are the hexahedra with a
+    /// scaled Jacobian of zero, such as collapsed hexahedra, and the triangles
+    /// with no area.
+    pub degenerate: Vec<usize>,
+}
+
+/// The minimum, maximum and mean of one metric over all elements.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub name: &'static str,
+    pub min: f64,
+    pub max: f64,
+    /// The mean of the finite values.
+    pub mean: f64,
+}
+
+fn sub(b: &[f64; 3], a: &[f64; 3]) -> [f64; 3] {
+    [b[0] - a[0], b[1] - a[1], b[2] - a[2]]
+}
+
+fn add(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn norm(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+fn determinant(a: [f64; 3], b: [f64; 3], c: [f64; 3]) -> f64 {
+    dot(a, cross(b, c))
+}
+
+/// Computes the minimum scaled Jacobian, maximum edge ratio, maximum skew and
+/// volume of a hexahedron.
+pub fn hex_metrics(nodes: &[[f64; 3]; 8]) -> [f64; 4] {
+    // A collapsed edge gives a corner a scaled Jacobian of zero.
+    let min_scaled_jacobian = scaled_jacobians(nodes)
+        .iter()
+        .map(|jacobian| jacobian.unwrap_or(0.0))
+        .fold(f64::INFINITY, f64::min);
+    axis_metrics(nodes, min_scaled_jacobian)
+}
+
+/// Returns the determinant of the unit edge vectors at each corner of a
+/// hexahedron, or `None` at a corner with a collapsed edge.
+fn scaled_jacobians(nodes: &[[f64; 3]; 8]) -> [Option<f64>; 8] {
+    std::array::from_fn(|k| {
+        let edges = HEX_CORNERS[k].map(|n| sub(&nodes[n], &nodes[k]));
+        let lengths = edges.iter().map(|&e| norm(e)).product::<f64>();
+        (lengths > 0.0).then(|| determinant(edges[0], edges[1], edges[2]) / lengths)
+    })
+}
+
+/// Completes the metrics of a hexahedron with the `min_scaled_jacobian` of its
+/// corners.
+fn axis_metrics(nodes: &[[f64; 3]; 8], min_scaled_jacobian: f64) -> [f64; 4] {
+    // The principal axes, joining the centers of opposite faces.
+    let axis = |pairs: [(usize, usize); 4]| {
+        pairs
+            .iter()
+            .map(|&(from, to)| sub(&nodes[to], &nodes[from]))
+            .fold([0.0; 3], add)
+    };
+    let axes = [
+        axis([(0, 1), (3, 2), (4, 5), (7, 6)]),
+        axis([(0, 3), (1, 2), (4, 7), (5, 6)]),
+        axis([(0, 4), (1, 5), (2, 6), (3, 7)]),
+    ];
+    let lengths = axes.map(norm);
+    let (max_edge_ratio, max_skew) = if lengths.iter().all(|&l| l > 0.0) {
+        let longest = lengths.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let shortest = lengths.iter().copied().fold(f64::INFINITY, f64::min);
+        let skew = |i: usize, j: usize| (dot(axes[i], axes[j]) / (lengths[i] * lengths[j])).abs();
+        (
+            longest / shortest,
+            skew(0, 1).max(skew(0, 2)).max(skew(1, 2)),
+        )
+    } else {
+        (f64::INFINITY, 1.0)
+    };
+
+    // The Jacobian of the trilinear map is quadratic in each reference
+    // coordinate, so the eight-point Gauss rule integrates it exactly.
+    let gauss = 1.0 / 3.0_f64.sqrt();
+    let volume = HEX_REFERENCE
+        .iter()
+        .map(|point| {
+            let point = point.map(|x| x * gauss);
+            let mut jacobian = [[0.0; 3]; 3];
+            for (node, corner) in nodes.iter().zip(HEX_REFERENCE.iter()) {
+                let factors = [0, 1, 2].map(|i| 1.0 + corner[i] * point[i]);
+                for (i, column) in jacobian.iter_mut().enumerate() {
+                    let derivative = corner[i] * factors[(i + 1) % 3] * factors[(i + 2) % 3] / 8.0;
+                    for (x, y) in column.iter_mut().zip(node) {
+                        *x += derivative * y;
+                    }
+                }
+            }
+            determinant(jacobian[0], jacobian[1], jacobian[2])
+        })
+        .sum();
+
+    [min_scaled_jacobian, max_edge_ratio, max_skew, volume]
+}
+
+/// Computes the minimum angle, in degrees, aspect ratio, area and edge ratio of
+/// a triangle.
+pub fn tri_metrics(nodes: &[[f64; 3]; 3]) -> [f64; 4] {
+    let edges = [0, 1, 2].map(|k| sub(&nodes[(k + 1) % 3], &nodes[k]));
+    let lengths = edges.map(norm);
+    let area = 0.5 * norm(cross(edges[0], edges[1]));
+
+    // The angle at each node is between the edges into and out of it.
+    let min_angle = (0..3)
+        .map(|k| {
+            let (into, out) = (edges[(k + 2) % 3], edges[k]);
+            norm(cross(into, out)).atan2(-dot(into, out)).to_degrees()
+        })
+        .fold(f64::INFINITY, f64::min);
+
+    let longest = lengths.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let shortest = lengths.iter().copied().fold(f64::INFINITY, f64::min);
+    // One for an equilateral triangle.
+    let aspect_ratio = if area > 0.0 {
+        longest * lengths.iter().sum::<f64>() / (4.0 * 3.0_f64.sqrt() * area)
+    } else {
+        f64::INFINITY
+    };
+    let edge_ratio = if shortest > 0.0 {
+        longest / shortest
+    } else {
+        f64::INFINITY
+    };
+
+    [min_angle, aspect_ratio, area, edge_ratio]
+}
+
+impl HexMesh {
+    /// Computes the quality metrics of every element, in the columns of `HEX_METRICS`.
+    pub fn metrics(&self) -> Metrics {
+        let values: Vec<[f64; 4]> = self
+            .elements
+            .iter()
+            .map(|element| hex_metrics(&element.map(|node| self.nodes[node])))
+            .collect();
+        Metrics {
+            names: HEX_METRICS,
+            inverted: flagged(&values, |row| row[0] < 0.0),
+            degenerate: flagged(&values, |row| row[0] == 0.0),
+            values,
+        }
+    }
+}
+
+impl TriMesh {
+    /// Computes the quality metrics of every element, in the columns of `TRI_METRICS`.
+    pub fn metrics(&self) -> Metrics {
+        let values: Vec<[f64; 4]> = self
+            .elements
+            .iter()
+            .map(|element| tri_metrics(&element.map(|node| self.nodes[node])))
+            .collect();
+        Metrics {
+            names: TRI_METRICS,
+            inverted: Vec::new(),
+            degenerate: flagged(&values, |row| row[2] <= 0.0),
+            values,
+        }
+    }
+}
+
+impl Mesh {
+    /// Computes the quality metrics of every element.
+    pub fn metrics(&self) -> Metrics {
+        match self {
+            Mesh::Hex(mesh) => mesh.metrics(),
+            Mesh::Tri(mesh) => mesh.metrics(),
+        }
+    }
+}
+
+/// Returns the indices of the elements whose metrics match `predicate`.
+fn flagged(values: &[[f64; 4]], predicate: impl Fn(&[f64; 4]) -> bool) -> Vec<usize> {
+    values
+        .iter()
+        .enumerate()
+        .filter(|(_, row)| predicate(row))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+impl Metrics {
+    /// Summarizes each metric over all elements, in column order.
+    ///
+    /// A metric with no finite value, as for a mesh with no elements, has no
+    /// mean and is left out.
+    pub fn summaries(&self) -> Vec<Summary> {
+        self.names
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &name)| {
+                let column = self.values.iter().map(|row| row[i]);
+                let finite: Vec<f64> = column.clone().filter(|v| v.is_finite()).collect();
+                (!finite.is_empty()).then(|| Summary {
+                    name,
+                    min: column.clone().fold(f64::INFINITY, f64::min),
+                    max: column.fold(f64::NEG_INFINITY, f64::max),
+                    mean: finite.iter().sum::<f64>() / finite.len() as f64,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the metrics as an array with one row per element.
+    pub fn to_array(&self) -> Array2<f64> {
+        Array2::from_shape_fn((self.values.len(), 4), |(i, j)| self.values[i][j])
+    }
+
+    /// Writes the metrics to a CSV file, with a header row and one row per
+    /// element, numbered from zero in element order as in a `.vtk` file.
+    pub fn write_csv(&self, file_path: &str) -> Result<(), String> {
+        let file = File::create(file_path)
+            .map_err(|e| format!("Failed to create {}: {}", file_path, e))?;
+        let mut writer = BufWriter::new(file);
+        let write_error = |e: std::io::Error| format!("Failed to write {}: {}", file_path, e);
+
+        writeln!(writer, "element,{}", self.names.join(",")).map_err(write_error)?;
+        for (i, row) in self.values.iter().enumerate() {
+            let row: Vec<String> = row.iter().map(|v| format!("{:e}", v)).collect();
+            writeln!(writer, "{},{}", i, row.join(",")).map_err(write_error)?;
+        }
+        writer.flush().map_err(write_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxels::{VoxelData, Voxels};
+
+    #[test]
+    fn test_hex_metrics() {
+        let mut nodes = HEX_REFERENCE.map(|[x, y, z]| [x + 1.0, 2.0 * (y + 1.0), z + 1.0]);
+        let [min_scaled_jacobian, max_edge_ratio, max_skew, volume] = hex_metrics(&nodes);
+        assert!((min_scaled_jacobian - 1.0).abs() < 1.0e-12);
+        assert!((max_edge_ratio - 2.0).abs() < 1.0e-12);
+        assert!(max_skew.abs() < 1.0e-12);
+        assert!((volume - 16.0).abs() < 1.0e-12);
+
+        // Sheared by a quarter of the height, which keeps the volume.
+        for node in nodes.iter_mut() {
+            node[0] += 0.25 * node[2];
+        }
+        let [min_scaled_jacobian, _, max_skew, volume] = hex_metrics(&nodes);
+        assert!(min_scaled_jacobian < 1.0 && min_scaled_jacobian > 0.0);
+        assert!(max_skew > 0.0);
+        assert!((volume - 16.0).abs() < 1.0e-12);
+
+        // Mirrored, so that the element is inverted.
+        let mirrored = nodes.map(|[x, y, z]| [x, y, -z]);
+        let [min_scaled_jacobian, _, _, volume] = hex_metrics(&mirrored);
+        assert!(min_scaled_jacobian < 0.0);
+        assert!((volume + 16.0).abs() < 1.0e-12);
+
+        // A pyramid over the unit square, with its apex above the center, has
+        // corners of zero.
+        let mut pyramid = HEX_REFERENCE.map(|[x, y, _]| [(x + 1.0) / 2.0, (y + 1.0) / 2.0, 0.0]);
+        pyramid[4..].fill([0.5, 0.5, 1.0]);
+        assert_eq!(hex_metrics(&pyramid)[0], 0.0);
+    }
+
+    #[test]
+    fn test_tri_metrics() {
+        let equilateral = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.5, 0.75_f64.sqrt(), 0.0],
+        ];
+        let [min_angle, aspect_ratio, area, edge_ratio] = tri_metrics(&equilateral);
+        assert!((min_angle - 60.0).abs() < 1.0e-12);
+        assert!((aspect_ratio - 1.0).abs() < 1.0e-12);
+        assert!((area - 0.75_f64.sqrt() / 2.0).abs() < 1.0e-12);
+        assert!((edge_ratio - 1.0).abs() < 1.0e-12);
+
+        let right = [[0.0, 0.0, 0.0], [0.0, 2.0, 0.0], [0.0, 0.0, 2.0]];
+        let [min_angle, _, area, edge_ratio] = tri_metrics(&right);
+        assert!((min_angle - 45.0).abs() < 1.0e-12);
+        assert!((area - 2.0).abs() < 1.0e-12);
+        assert!((edge_ratio - 2.0_f64.sqrt()).abs() < 1.0e-12);
+
+        let degenerate = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [2.0, 0.0, 0.0]];
+        let [_, aspect_ratio, area, _] = tri_metrics(&degenerate);
+        assert_eq!(area, 0.0);
+        assert_eq!(aspect_ratio, f64::INFINITY);
+    }
+
+    #[test]
+    fn test_mesh_metrics() {
+        let voxels = Voxels::from(VoxelData::from_elem((1, 2, 3), 1));
+        let mut mesh = HexMesh::from_voxels(&voxels, &[]);
+        let metrics = mesh.metrics();
+        assert_eq!(metrics.values.len(), 6);
+        assert!(metrics.inverted.is_empty() && metrics.degenerate.is_empty());
+        let summaries = metrics.summaries();
+        assert_eq!(summaries[0].name, "min_scaled_jacobian");
+        assert!((summaries[0].min - 1.0).abs() < 1.0e-12);
+        assert!((summaries[3].mean - 1.0).abs() < 1.0e-12);
+        assert_eq!(metrics.to_array().shape(), &[6, 4]);
+
+        // A collapsed hexahedron is degenerate.
+        mesh.elements[2][1] = mesh.elements[2][0];
+        let metrics = mesh.metrics();
+        assert_eq!(metrics.values[2][0], 0.0);
+        assert!(metrics.inverted.is_empty());
+        assert_eq!(metrics.degenerate, vec![2]);
+
+        // A mesh without elements has no summaries.
+        assert!(HexMesh::default().metrics().summaries().is_empty());
+
+        let metrics = TriMesh::from_voxels(&voxels, &[]).metrics();
+        assert!(metrics.inverted.is_empty() && metrics.degenerate.is_empty());
+        assert!(
+            metrics
+                .values
+                .iter()
+                .all(|row| (row[0] - 45.0).abs() < 1.0e-12)
+        );
+
+        let path = std::env::temp_dir().join(format!(
+            "clap1_{}_metrics_mesh_metrics.csv",
+            std::process::id()
+        ));
+        let path = path.to_string_lossy();
+        metrics.write_csv(&path).unwrap();
+        let contents = std::fs::read_to_string(path.as_ref()).unwrap();
+        assert_eq!(contents.lines().count(), metrics.values.len() + 1);
+        assert!(contents.starts_with("element,min_angle,aspect_ratio,area,edge_ratio\n"));
+    }
+}
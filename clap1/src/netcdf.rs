@@ -0,0 +1,503 @@
+//! Reading and writing of netCDF classic and 64-bit offset files, without the C
+//! netCDF library.
+//!
+//! A file is a header, with the dimensions, the global attributes, and the
+//! variables with their attributes and data offsets, followed by the data of each
+//! variable.  All values are big-endian, and every section is padded to a multiple
+//! of four bytes.  Files are written in the 64-bit offset format, and both
+//! formats are read.  Record variables, along the unlimited dimension, are
+//! written with no records.
+
+const NC_DIMENSION: u32 = 0x0A;
+const NC_VARIABLE: u32 = 0x0B;
+const NC_ATTRIBUTE: u32 = 0x0C;
+
+const NC_BYTE: u32 = 1;
+const NC_CHAR: u32 = 2;
+const NC_SHORT: u32 = 3;
+const NC_INT: u32 = 4;
+const NC_FLOAT: u32 = 5;
+const NC_DOUBLE: u32 = 6;
+
+/// The values of an attribute or a variable.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Values {
+    /// Bytes, which are also text.
+    Char(Vec<u8>),
+    /// 32-bit integers.
+    Int(Vec<i32>),
+    /// 32-bit floating point numbers.
+    Float(Vec<f32>),
+    /// 64-bit floating point numbers.
+    Double(Vec<f64>),
+}
+
+impl Values {
+    /// Returns the text of a string of characters, up to the first null.
+    pub fn text(text: &str) -> Self {
+        Values::Char(text.as_bytes().to_vec())
+    }
+
+    /// Returns the netCDF type code.
+    fn nc_type(&self) -> u32 {
+        match self {
+            Values::Char(_) => NC_CHAR,
+            Values::Int(_) => NC_INT,
+            Values::Float(_) => NC_FLOAT,
+            Values::Double(_) => NC_DOUBLE,
+        }
+    }
+
+    /// Returns the number of values.
+    fn len(&self) -> usize {
+        match self {
+            Values::Char(v) => v.len(),
+            Values::Int(v) => v.len(),
+            Values::Float(v) => v.len(),
+            Values::Double(v) => v.len(),
+        }
+    }
+
+    /// Appends the big-endian values, padded to four bytes, to the `bytes`.
+    fn write(&self, bytes: &mut Vec<u8>) {
+        match self {
+            Values::Char(v) => bytes.extend_from_slice(v),
+            Values::Int(v) => v.iter().for_each(|x| bytes.extend(x.to_be_bytes())),
+            Values::Float(v) => v.iter().for_each(|x| bytes.extend(x.to_be_bytes())),
+            Values::Double(v) => v.iter().for_each(|x| bytes.extend(x.to_be_bytes())),
+        }
+        pad(bytes);
+    }
+
+    /// Returns the values as integers, if they are integers.
+    pub fn as_ints(&self) -> Option<&[i32]> {
+        match self {
+            Values::Int(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the values as doubles, converting from floats.
+    pub fn to_doubles(&self) -> Option<Vec<f64>> {
+        match self {
+            Values::Double(v) => Some(v.clone()),
+            Values::Float(v) => Some(v.iter().map(|&x| x as f64).collect()),
+            _ => None,
+        }
+    }
+
+    /// Returns the values as text, up to the first null.
+    pub fn to_text(&self) -> Option<String> {
+        match self {
+            Values::Char(v) => {
+                let end = v.iter().position(|&c| c == 0).unwrap_or(v.len());
+                Some(String::from_utf8_lossy(&v[..end]).into_owned())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A variable, with the indices of its dimensions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    pub dimensions: Vec<usize>,
+    pub attributes: Vec<(String, Values)>,
+    pub values: Values,
+}
+
+/// The contents of a netCDF file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NetCdf {
+    /// The dimension names and lengths, where a length of zero is unlimited.
+    pub dimensions: Vec<(String, usize)>,
+    pub attributes: Vec<(String, Values)>,
+    pub variables: Vec<Variable>,
+}
+
+/// Pads the `bytes` with zeros to a multiple of four.
+fn pad(bytes: &mut Vec<u8>) {
+    while !bytes.len().is_multiple_of(4) {
+        bytes.push(0);
+    }
+}
+
+/// Appends a name, as its length and its padded characters.
+fn write_name(bytes: &mut Vec<u8>, name: &str) {
+    bytes.extend((name.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(name.as_bytes());
+    pad(bytes);
+}
+
+/// Appends a list of attributes, or the absent marker if there are none.
+fn write_attributes(bytes: &mut Vec<u8>, attributes: &[(String, Values)]) {
+    if attributes.is_empty() {
+        bytes.extend([0; 8]);
+        return;
+    }
+    bytes.extend(NC_ATTRIBUTE.to_be_bytes());
+    bytes.extend((attributes.len() as u32).to_be_bytes());
+    for (name, values) in attributes {
+        write_name(bytes, name);
+        bytes.extend(values.nc_type().to_be_bytes());
+        bytes.extend((values.len() as u32).to_be_bytes());
+        values.write(bytes);
+    }
+}
+
+impl NetCdf {
+    /// Adds a dimension and returns its index.
+    pub fn add_dimension(&mut self, name: &str, length: usize) -> usize {
+        self.dimensions.push((name.to_string(), length));
+        self.dimensions.len() - 1
+    }
+
+    /// Returns the index of the dimension with the `name`.
+    pub fn dimension(&self, name: &str) -> Option<usize> {
+        self.dimensions.iter().position(|(n, _)| n == name)
+    }
+
+    /// Returns the length of the dimension with the `name`.
+    pub fn dimension_length(&self, name: &str) -> Option<usize> {
+        self.dimension(name).map(|d| self.dimensions[d].1)
+    }
+
+    /// Adds a variable with the named dimensions and attributes.
+    pub fn add_variable(
+        &mut self,
+        name: &str,
+        dimensions: &[&str],
+        attributes: Vec<(String, Values)>,
+        values: Values,
+    ) -> Result<(), String> {
+        let dimensions = dimensions
+            .iter()
+            .map(|d| {
+                self.dimension(d)
+                    .ok_or_else(|| format!("Variable {} has no dimension {}", name, d))
+            })
+            .collect::<Result<Vec<usize>, String>>()?;
+        let expected = self.variable_length(&dimensions);
+        if values.len() != expected && !self.is_record(&dimensions) {
+            return Err(format!(
+                "Variable {} has {} values, expected {}",
+                name,
+                values.len(),
+                expected
+            ));
+        }
+        self.variables.push(Variable {
+            name: name.to_string(),
+            dimensions,
+            attributes,
+            values,
+        });
+        Ok(())
+    }
+
+    /// Returns the variable with the `name`.
+    pub fn variable(&self, name: &str) -> Option<&Variable> {
+        self.variables.iter().find(|v| v.name == name)
+    }
+
+    /// Returns whether a variable with the `dimensions` is along the unlimited one.
+    fn is_record(&self, dimensions: &[usize]) -> bool {
+        dimensions
+            .first()
+            .is_some_and(|&d| self.dimensions[d].1 == 0)
+    }
+
+    /// Returns the number of values of a variable, or of one record of a record
+    /// variable.
+    fn variable_length(&self, dimensions: &[usize]) -> usize {
+        let fixed = if self.is_record(dimensions) {
+            &dimensions[1..]
+        } else {
+            dimensions
+        };
+        fixed.iter().map(|&d| self.dimensions[d].1).product()
+    }
+
+    /// Encodes the contents as a 64-bit offset netCDF file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let header = |begins: &[u64]| {
+            let mut bytes = b"CDF\x02".to_vec();
+            bytes.extend(0u32.to_be_bytes());
+            if self.dimensions.is_empty() {
+                bytes.extend([0; 8]);
+            } else {
+                bytes.extend(NC_DIMENSION.to_be_bytes());
+                bytes.extend((self.dimensions.len() as u32).to_be_bytes());
+                for (name, length) in &self.dimensions {
+                    write_name(&mut bytes, name);
+                    bytes.extend((*length as u32).to_be_bytes());
+                }
+            }
+            write_attributes(&mut bytes, &self.attributes);
+            if self.variables.is_empty() {
+                bytes.extend([0; 8]);
+            } else {
+                bytes.extend(NC_VARIABLE.to_be_bytes());
+                bytes.extend((self.variables.len() as u32).to_be_bytes());
+                for (variable, begin) in self.variables.iter().zip(begins) {
+                    write_name(&mut bytes, &variable.name);
+                    bytes.extend((variable.dimensions.len() as u32).to_be_bytes());
+                    for &d in &variable.dimensions {
+                        bytes.extend((d as u32).to_be_bytes());
+                    }
+                    write_attributes(&mut bytes, &variable.attributes);
+                    bytes.extend(variable.values.nc_type().to_be_bytes());
+                    bytes.extend((self.variable_size(variable) as u32).to_be_bytes());
+                    bytes.extend(begin.to_be_bytes());
+                }
+            }
+            bytes
+        };
+
+        // The offsets do not change the size of the header, so it is sized first.
+        let mut begin = header(&vec![0; self.variables.len()]).len() as u64;
+        let mut begins = vec![0; self.variables.len()];
+        for record in [false, true] {
+            for (k, variable) in self.variables.iter().enumerate() {
+                if self.is_record(&variable.dimensions) == record {
+                    begins[k] = begin;
+                    if !record {
+                        begin += self.variable_size(variable) as u64;
+                    }
+                }
+            }
+        }
+
+        let mut bytes = header(&begins);
+        for variable in &self.variables {
+            if !self.is_record(&variable.dimensions) {
+                variable.values.write(&mut bytes);
+            }
+        }
+        bytes
+    }
+
+    /// Returns the size in bytes of the data of a variable, or of one record.
+    fn variable_size(&self, variable: &Variable) -> usize {
+        let size = match variable.values {
+            Values::Char(_) => 1,
+            Values::Int(_) | Values::Float(_) => 4,
+            Values::Double(_) => 8,
+        };
+        (self.variable_length(&variable.dimensions) * size).div_ceil(4) * 4
+    }
+
+    /// Decodes a netCDF classic or 64-bit offset file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { bytes, offset: 0 };
+        let version = match reader.take(4)? {
+            b"CDF\x01" => 1,
+            b"CDF\x02" => 2,
+            _ => return Err("Not a netCDF classic or 64-bit offset file".to_string()),
+        };
+        let records = reader.u32()? as usize;
+
+        let mut file = NetCdf::default();
+        match reader.u32()? {
+            NC_DIMENSION => {
+                for _ in 0..reader.u32()? {
+                    let name = reader.name()?;
+                    let length = reader.u32()? as usize;
+                    file.dimensions.push((name, length));
+                }
+            }
+            0 => {
+                reader.u32()?;
+            }
+            tag => return Err(reader.error(&format!("expected dimensions, found tag {}", tag))),
+        }
+        file.attributes = reader.attributes()?;
+
+        let mut begins = Vec::new();
+        match reader.u32()? {
+            NC_VARIABLE => {
+                for _ in 0..reader.u32()? {
+                    let name = reader.name()?;
+                    let dimensions = (0..reader.u32()?)
+                        .map(|_| reader.u32().map(|d| d as usize))
+                        .collect::<Result<Vec<usize>, String>>()?;
+                    if let Some(&d) = dimensions.iter().find(|&&d| d >= file.dimensions.len()) {
+                        return Err(reader.error(&format!("{} has no dimension {}", name, d)));
+                    }
+                    let attributes = reader.attributes()?;
+                    let nc_type = reader.u32()?;
+                    let _size = reader.u32()?;
+                    let begin = match version {
+                        1 => reader.u32()? as usize,
+                        _ => reader.u64()? as usize,
+                    };
+                    begins.push((nc_type, begin));
+                    file.variables.push(Variable {
+                        name,
+                        dimensions,
+                        attributes,
+                        values: Values::Char(Vec::new()),
+                    });
+                }
+            }
+            0 => {
+                reader.u32()?;
+            }
+            tag => return Err(reader.error(&format!("expected variables, found tag {}", tag))),
+        }
+
+        for (k, (nc_type, begin)) in begins.into_iter().enumerate() {
+            let dimensions = &file.variables[k].dimensions;
+            // Only the first record of a record variable is read.
+            let count = match file.is_record(dimensions) && records == 0 {
+                true => 0,
+                false => file.variable_length(dimensions),
+            };
+            let mut data = Reader {
+                bytes,
+                offset: begin,
+            };
+            file.variables[k].values = data.values(nc_type, count)?;
+        }
+        Ok(file)
+    }
+}
+
+/// A cursor over the bytes of a netCDF file.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, message: &str) -> String {
+        format!("Invalid netCDF file at byte {}: {}", self.offset, message)
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self.offset + count;
+        if end > self.bytes.len() {
+            return Err(self.error("unexpected end of file"));
+        }
+        let slice = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn skip_padding(&mut self) {
+        self.offset = self.offset.div_ceil(4) * 4;
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let length = self.u32()? as usize;
+        let name = String::from_utf8_lossy(self.take(length)?).into_owned();
+        self.skip_padding();
+        Ok(name)
+    }
+
+    fn values(&mut self, nc_type: u32, count: usize) -> Result<Values, String> {
+        let values = match nc_type {
+            NC_BYTE | NC_CHAR => Values::Char(self.take(count)?.to_vec()),
+            NC_SHORT => Values::Int(
+                self.take(2 * count)?
+                    .chunks_exact(2)
+                    .map(|b| i16::from_be_bytes([b[0], b[1]]) as i32)
+                    .collect(),
+            ),
+            NC_INT => Values::Int(
+                self.take(4 * count)?
+                    .chunks_exact(4)
+                    .map(|b| i32::from_be_bytes(b.try_into().unwrap()))
+                    .collect(),
+            ),
+            NC_FLOAT => Values::Float(
+                self.take(4 * count)?
+                    .chunks_exact(4)
+                    .map(|b| f32::from_be_bytes(b.try_into().unwrap()))
+                    .collect(),
+            ),
+            NC_DOUBLE => Values::Double(
+                self.take(8 * count)?
+                    .chunks_exact(8)
+                    .map(|b| f64::from_be_bytes(b.try_into().unwrap()))
+                    .collect(),
+            ),
+            _ => return Err(self.error(&format!("unknown type {}", nc_type))),
+        };
+        self.skip_padding();
+        Ok(values)
+    }
+
+    fn attributes(&mut self) -> Result<Vec<(String, Values)>, String> {
+        match self.u32()? {
+            NC_ATTRIBUTE => (0..self.u32()?)
+                .map(|_| {
+                    let name = self.name()?;
+                    let nc_type = self.u32()?;
+                    let count = self.u32()? as usize;
+                    Ok((name, self.values(nc_type, count)?))
+                })
+                .collect(),
+            0 => {
+                self.u32()?;
+                Ok(Vec::new())
+            }
+            tag => Err(self.error(&format!("expected attributes, found tag {}", tag))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut file = NetCdf::default();
+        file.add_dimension("time", 0);
+        file.add_dimension("three", 3);
+        file.add_dimension("two", 2);
+        file.attributes
+            .push(("title".to_string(), Values::text("x")));
+        file.add_variable("time_whole", &["time"], vec![], Values::Double(vec![]))
+            .unwrap();
+        file.add_variable(
+            "ints",
+            &["two", "three"],
+            vec![("name".to_string(), Values::text("ID"))],
+            Values::Int(vec![1, -2, 3, 4, 5, 6]),
+        )
+        .unwrap();
+        file.add_variable("odd", &["three"], vec![], Values::text("abc"))
+            .unwrap();
+        file.add_variable(
+            "doubles",
+            &["two"],
+            vec![],
+            Values::Double(vec![0.5, -1e10]),
+        )
+        .unwrap();
+        assert!(
+            file.add_variable("short", &["two"], vec![], Values::Int(vec![1]))
+                .is_err()
+        );
+
+        let bytes = file.to_bytes();
+        assert_eq!(&bytes[..4], b"CDF\x02");
+        assert_eq!(bytes.len() % 4, 0);
+        assert_eq!(NetCdf::from_bytes(&bytes).unwrap(), file);
+
+        let error = NetCdf::from_bytes(&bytes[..bytes.len() - 4]).unwrap_err();
+        assert!(error.contains("unexpected end of file"));
+        assert!(NetCdf::from_bytes(b"HDF5").is_err());
+    }
+}
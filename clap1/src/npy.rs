@@ -0,0 +1,281 @@
+//! Reading and writing of NumPy `.npy` files, without a dependency on NumPy or
+//! ndarray-npy.
+//!
+//! The format is a magic string, a version, a Python dictionary literal with the
+//! `descr`, `fortran_order` and `shape` keys, and then the raw array data.
+
+use ndarray::{Array2, Array3, ShapeBuilder};
+use std::fs;
+use std::io::Write;
+
+/// The first six bytes of every `.npy` file.
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// The parsed header of a `.npy` file.
+#[derive(Debug, PartialEq)]
+struct Header {
+    /// The array data type, such as `|u1` or `<i8`.
+    descr: String,
+    /// Whether the data is in column-major order.
+    fortran_order: bool,
+    /// The array dimensions.
+    shape: Vec<usize>,
+}
+
+/// Returns the text of the value that follows `'key':` in the header dictionary.
+fn header_value<'a>(dict: &'a str, key: &str) -> Result<&'a str, String> {
+    let pattern = format!("'{}':", key);
+    let start = dict
+        .find(&pattern)
+        .ok_or_else(|| format!("NPY header has no '{}' entry", key))?
+        + pattern.len();
+    Ok(dict[start..].trim_start())
+}
+
+/// Parses the header dictionary of a `.npy` file.
+fn parse_header(dict: &str) -> Result<Header, String> {
+    let descr = header_value(dict, "descr")?;
+    let descr = descr
+        .strip_prefix('\'')
+        .and_then(|d| d.split('\'').next())
+        .ok_or_else(|| format!("NPY header has an invalid 'descr': {}", dict))?
+        .to_string();
+
+    let fortran_order = header_value(dict, "fortran_order")?.starts_with("True");
+
+    let shape = header_value(dict, "shape")?;
+    let shape = shape
+        .strip_prefix('(')
+        .and_then(|s| s.split(')').next())
+        .ok_or_else(|| format!("NPY header has an invalid 'shape': {}", dict))?
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse::<usize>()
+                .map_err(|e| format!("NPY header has an invalid dimension '{}': {}", s, e))
+        })
+        .collect::<Result<Vec<usize>, String>>()?;
+
+    Ok(Header {
+        descr,
+        fortran_order,
+        shape,
+    })
+}
+
+/// Splits the contents of a `.npy` file into its header and its data.
+fn split_npy(bytes: &[u8]) -> Result<(Header, &[u8]), String> {
+    if bytes.len() < 10 || &bytes[..6] != MAGIC {
+        return Err("Not an NPY file, the magic string is missing".to_string());
+    }
+    // Version 1 has a two-byte header length, versions 2 and 3 have four bytes.
+    let (length, offset) = match bytes[6] {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        2 | 3 if bytes.len() >= 12 => (
+            u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize,
+            12,
+        ),
+        version => return Err(format!("Unsupported NPY version {}", version)),
+    };
+    let end = offset + length;
+    if bytes.len() < end {
+        return Err("NPY file ends within its header".to_string());
+    }
+    let dict = std::str::from_utf8(&bytes[offset..end])
+        .map_err(|e| format!("NPY header is not text: {}", e))?;
+    Ok((parse_header(dict)?, &bytes[end..]))
+}
+
+/// Converts the raw data to `u8` values, checking that each one is in range.
+fn to_u8(descr: &str, data: &[u8], count: usize) -> Result<Vec<u8>, String> {
+    // The first character is the byte order, which may be missing or not ASCII
+    // in a malformed header.
+    let kind = descr.get(1..).unwrap_or_default();
+    let size = match kind {
+        "u1" | "i1" | "b1" => 1,
+        "u2" | "i2" => 2,
+        "u4" | "i4" => 4,
+        "u8" | "i8" => 8,
+        _ => return Err(format!("Unsupported NPY data type '{}'", descr)),
+    };
+    if size > 1 && descr.starts_with('>') {
+        return Err(format!("Unsupported big-endian NPY data type '{}'", descr));
+    }
+    if data.len() < count * size {
+        return Err(format!(
+            "NPY file has {} bytes of data, expected {}",
+            data.len(),
+            count * size
+        ));
+    }
+    let signed = kind.starts_with('i');
+    data.chunks_exact(size)
+        .take(count)
+        .map(|bytes| {
+            let mut buffer = [0u8; 8];
+            buffer[..size].copy_from_slice(bytes);
+            // Sign-extend negative values so that they are rejected below.
+            if signed && bytes[size - 1] & 0x80 != 0 {
+                buffer[size..].fill(0xff);
+            }
+            let value = i64::from_le_bytes(buffer);
+            u8::try_from(value).map_err(|_| format!("NPY value {} does not fit in a u8", value))
+        })
+        .collect()
+}
+
+/// Reads a three-dimensional array of integers from a `.npy` file.
+///
+/// Any unsigned or signed little-endian integer type is accepted, provided every
+/// value is in the range of a `u8`.
+///
+/// # Arguments
+///
+/// * `file_path` - The `.npy` file path.
+///
+/// # Returns
+///
+/// The array with the dimensions of the file, in row-major order.
+pub fn read_npy_u8(file_path: &str) -> Result<Array3<u8>, String> {
+    let bytes = fs::read(file_path).map_err(|e| format!("Failed to read {}: {}", file_path, e))?;
+    let (header, data) = split_npy(&bytes).map_err(|e| format!("{}: {}", file_path, e))?;
+    let (nz, ny, nx) = match header.shape[..] {
+        [nz, ny, nx] => (nz, ny, nx),
+        _ => {
+            return Err(format!(
+                "{}: expected a 3D array, found shape {:?}",
+                file_path, header.shape
+            ));
+        }
+    };
+    let values =
+        to_u8(&header.descr, data, nz * ny * nx).map_err(|e| format!("{}: {}", file_path, e))?;
+    let shape = (nz, ny, nx).set_f(header.fortran_order);
+    let array = Array3::from_shape_vec(shape, values).map_err(|e| e.to_string())?;
+    Ok(array.as_standard_layout().into_owned())
+}
+
+/// Writes an array to a version 1.0 `.npy` file.
+///
+/// # Arguments
+///
+/// * `file_path` - The `.npy` file path.
+/// * `descr` - The NumPy data type of the values, such as `|u1`.
+/// * `shape` - The array dimensions.
+/// * `data` - The raw values, in row-major order.
+fn write_npy(file_path: &str, descr: &str, shape: &[usize], data: &[u8]) -> Result<(), String> {
+    let shape = match shape {
+        [n] => format!("({},)", n),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(|n| n.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+    };
+    let mut dict = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+        descr, shape
+    );
+    // The header, with the ten bytes before it, is padded to a multiple of 64 bytes.
+    let length = (10 + dict.len() + 1).div_ceil(64) * 64 - 10;
+    dict.push_str(&" ".repeat(length - dict.len() - 1));
+    dict.push('\n');
+
+    let mut bytes = Vec::with_capacity(10 + length + data.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&[1, 0]);
+    bytes.extend_from_slice(&(length as u16).to_le_bytes());
+    bytes.extend_from_slice(dict.as_bytes());
+    bytes.extend_from_slice(data);
+    fs::File::create(file_path)
+        .and_then(|mut file| file.write_all(&bytes))
+        .map_err(|e| format!("Failed to write {}: {}", file_path, e))
+}
+
+/// Writes a three-dimensional array of `u8` values to a `.npy` file.
+pub fn write_npy_u8(file_path: &str, array: &Array3<u8>) -> Result<(), String> {
+    let data: Vec<u8> = array.iter().copied().collect();
+    write_npy(file_path, "|u1", array.shape(), &data)
+}
+
+/// Writes a two-dimensional array of `f64` values to a `.npy` file.
+pub fn write_npy_f64(file_path: &str, array: &Array2<f64>) -> Result<(), String> {
+    let data: Vec<u8> = array.iter().flat_map(|value| value.to_le_bytes()).collect();
+    write_npy(file_path, "<f8", array.shape(), &data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_header() {
+        let header =
+            parse_header("{'descr': '<i8', 'fortran_order': True, 'shape': (3, 5, 4), }").unwrap();
+        assert_eq!(header.descr, "<i8");
+        assert!(header.fortran_order);
+        assert_eq!(header.shape, vec![3, 5, 4]);
+        assert_eq!(
+            parse_header("{'descr': '|u1', 'fortran_order': False, 'shape': (7,), }")
+                .unwrap()
+                .shape,
+            vec![7]
+        );
+        assert!(parse_header("{'descr': '|u1', 'shape': (7,), }").is_err());
+    }
+
+    #[test]
+    fn test_to_u8() {
+        assert_eq!(to_u8("|u1", &[0, 7, 255], 3).unwrap(), vec![0, 7, 255]);
+        let data: Vec<u8> = [3i64, 200].iter().flat_map(|v| v.to_le_bytes()).collect();
+        assert_eq!(to_u8("<i8", &data, 2).unwrap(), vec![3, 200]);
+        let data: Vec<u8> = [-1i16].iter().flat_map(|v| v.to_le_bytes()).collect();
+        assert!(to_u8("<i2", &data, 1).is_err());
+        assert!(to_u8("<f8", &[0; 8], 1).is_err());
+        assert!(to_u8("|u1", &[0], 2).is_err());
+        assert_eq!(
+            to_u8("", &[0], 1).unwrap_err(),
+            "Unsupported NPY data type ''"
+        );
+        assert!(to_u8("éu1", &[0], 1).is_err());
+    }
+
+    #[test]
+    fn test_write_and_read_npy() {
+        let path = std::env::temp_dir().join(format!(
+            "clap1_{}_npy_write_and_read_npy.npy",
+            std::process::id()
+        ));
+        let path = path.to_string_lossy();
+        let array = Array3::from_shape_fn((2, 3, 4), |(z, y, x)| (x + 4 * y + 12 * z) as u8);
+        write_npy_u8(&path, &array).unwrap();
+        let bytes = fs::read(path.as_ref()).unwrap();
+        // The 24 values follow a header padded to a multiple of 64 bytes.
+        let header = bytes.len() - 24;
+        assert_eq!(header % 64, 0);
+        assert_eq!(bytes[header - 1], b'\n');
+        assert_eq!(read_npy_u8(&path).unwrap(), array);
+    }
+
+    #[test]
+    fn test_write_npy_f64() {
+        let path = std::env::temp_dir().join(format!(
+            "clap1_{}_npy_write_npy_f64.npy",
+            std::process::id()
+        ));
+        let path = path.to_string_lossy();
+        let array = Array2::from_shape_fn((3, 2), |(i, j)| i as f64 + 0.5 * j as f64);
+        write_npy_f64(&path, &array).unwrap();
+        let bytes = fs::read(path.as_ref()).unwrap();
+        let (header, data) = split_npy(&bytes).unwrap();
+        assert_eq!(header.descr, "<f8");
+        assert!(!header.fortran_order);
+        assert_eq!(header.shape, vec![3, 2]);
+        assert_eq!(data.len(), 48);
+        assert_eq!(data[40..], 2.5f64.to_le_bytes());
+    }
+}
@@ -0,0 +1,732 @@
+//! Adaptive octrees over segmentations, and their hexahedral meshes.
+//!
+//! Every cell of the octree splits into twenty-seven children, a third of its
+//! size, and the root spans the smallest power-of-three cube of voxels that holds
+//! the segmentation.  A cell stays a leaf when all of its voxels, and the voxels
+//! that touch it, share one label, so every leaf along a material interface is a
+//! single voxel.  After strong 3:1 balancing, across faces, edges and corners, a
+//! corner of a leaf is marked where it touches a leaf a third of its size.  A leaf
+//! with no marked corner is one hexahedron, and a leaf marked at one corner, along
+//! one edge, over one face, or at all eight corners is split by the template of
+//! that case, into 4, 11, 22 or 27 hexahedra.  A leaf marked in any other way has
+//! more of its corners marked, the fewest that give it a template, and a coarser
+//! leaf that touches such a corner is subdivided.  Each face of a template is
+//! split by its marked corners alone, so neighboring leaves meet face to face,
+//! and the mesh is conforming and all hexahedral.
+
+use crate::mesh::HexMesh;
+use crate::voxels::Voxels;
+use ndarray::s;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+/// The parts of a leaf side in the points of the templates, so that every node
+/// of a template is at a whole part.
+const PARTS: usize = 27;
+
+/// The corners of a hexahedral element in node order, where `0` is the lower and
+/// `1` the upper end of each axis.
+const CORNERS: [[usize; 3]; 8] = [
+    [0, 0, 0],
+    [1, 0, 0],
+    [1, 1, 0],
+    [0, 1, 0],
+    [0, 0, 1],
+    [1, 0, 1],
+    [1, 1, 1],
+    [0, 1, 1],
+];
+
+/// The faces of a hexahedral element, counter-clockwise seen from outside.
+const FACES: [[usize; 4]; 6] = [
+    [0, 3, 2, 1],
+    [4, 5, 6, 7],
+    [0, 1, 5, 4],
+    [1, 2, 6, 5],
+    [2, 3, 7, 6],
+    [3, 0, 4, 7],
+];
+
+/// The quads that take three edges at `t = 0` to one edge at `t = 27`, as
+/// `[x, t]` points, with single edges at `x = 0` and `x = 27`.
+const TRANSITION: [[[usize; 2]; 4]; 4] = [
+    [[0, 0], [9, 0], [9, 18], [0, 27]],
+    [[9, 0], [18, 0], [18, 18], [9, 18]],
+    [[18, 0], [27, 0], [27, 27], [18, 18]],
+    [[9, 18], [18, 18], [27, 27], [0, 27]],
+];
+
+/// The marked corners of each template, as bits `x + 2 y + 4 z`: none, the
+/// lower corner, the edge along x at y = z = 0, the face at z = 0, and all.
+const TEMPLATES: [u8; 5] = [0b0000, 0b0001, 0b0011, 0b1111, 0b1111_1111];
+
+/// The state of an octree cell.
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    /// A leaf with the label of all its voxels, or `None` outside the segmentation.
+    Leaf(Option<u8>),
+    /// An internal cell with twenty-seven children, indexed by `x + 3 y + 9 z` for
+    /// the lower (`0`), middle (`1`) or upper (`2`) third in each direction.
+    Children(Box<[Node; 27]>),
+}
+
+/// A leaf of the octree inside the segmentation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Leaf {
+    /// The voxel at the lower corner of the leaf, as `[x, y, z]`.
+    pub origin: [usize; 3],
+    /// The number of voxels along each side of the leaf.
+    pub size: usize,
+    /// The label of every voxel in the leaf.
+    pub label: u8,
+}
+
+/// An adaptive octree over a segmentation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Octree {
+    /// The number of voxels in the x, y and z directions.
+    nel: [usize; 3],
+    /// The number of voxels along each side of the root.
+    size: usize,
+    /// The root cell.
+    root: Node,
+}
+
+/// Returns the third of a cell that holds the child, along each axis.
+fn child_offset(child: usize) -> [usize; 3] {
+    [child % 3, (child / 3) % 3, child / 9]
+}
+
+impl Octree {
+    /// Builds the octree of a segmentation, refined to single voxels wherever the
+    /// label changes.
+    pub fn from_voxels(voxels: &Voxels) -> Self {
+        let nel = voxels.nel();
+        let mut size = 1;
+        while nel.iter().any(|&n| n > size) {
+            size *= 3;
+        }
+        Self {
+            nel,
+            size,
+            root: Self::build(voxels, [0, 0, 0], size),
+        }
+    }
+
+    /// Builds the cell with the lower corner `origin` and `size` voxels per side.
+    fn build(voxels: &Voxels, origin: [usize; 3], size: usize) -> Node {
+        let nel = voxels.nel();
+        if (0..3).any(|a| origin[a] >= nel[a]) {
+            return Node::Leaf(None);
+        }
+        if size == 1 {
+            return Node::Leaf(Some(voxels.label(origin)));
+        }
+        if (0..3).all(|a| origin[a] + size <= nel[a]) {
+            // The cell and the voxels touching it, clipped to the segmentation.
+            let low = origin.map(|o| o.saturating_sub(1));
+            let high = [0, 1, 2].map(|a| (origin[a] + size + 1).min(nel[a]));
+            let region =
+                voxels
+                    .get_data()
+                    .slice(s![low[2]..high[2], low[1]..high[1], low[0]..high[0]]);
+            let label = voxels.label(origin);
+            if region.iter().all(|&l| l == label) {
+                return Node::Leaf(Some(label));
+            }
+        }
+        let third = size / 3;
+        Node::Children(Box::new(std::array::from_fn(|child| {
+            let offset = child_offset(child);
+            Self::build(
+                voxels,
+                [0, 1, 2].map(|a| origin[a] + offset[a] * third),
+                third,
+            )
+        })))
+    }
+
+    /// Returns the leaves inside the segmentation, in depth-first order.
+    pub fn leaves(&self) -> Vec<Leaf> {
+        let mut leaves = Vec::new();
+        let mut stack = vec![(&self.root, [0, 0, 0], self.size)];
+        while let Some((node, origin, size)) = stack.pop() {
+            match node {
+                Node::Leaf(Some(label)) => leaves.push(Leaf {
+                    origin,
+                    size,
+                    label: *label,
+                }),
+                Node::Leaf(None) => {}
+                Node::Children(children) => {
+                    let third = size / 3;
+                    for (child, node) in children.iter().enumerate().rev() {
+                        let offset = child_offset(child);
+                        stack.push((
+                            node,
+                            [0, 1, 2].map(|a| origin[a] + offset[a] * third),
+                            third,
+                        ));
+                    }
+                }
+            }
+        }
+        leaves
+    }
+
+    /// Returns the leaf that holds the `voxel`, which must be inside the segmentation.
+    fn leaf_at(&self, voxel: [usize; 3]) -> Leaf {
+        let (mut node, mut origin, mut size) = (&self.root, [0, 0, 0], self.size);
+        loop {
+            match node {
+                Node::Leaf(label) => {
+                    return Leaf {
+                        origin,
+                        size,
+                        label: label.expect("voxels inside the segmentation have a label"),
+                    };
+                }
+                Node::Children(children) => {
+                    size /= 3;
+                    let offset = [0, 1, 2].map(|a| (voxel[a] - origin[a]) / size);
+                    origin = [0, 1, 2].map(|a| origin[a] + offset[a] * size);
+                    node = &children[offset[0] + 3 * offset[1] + 9 * offset[2]];
+                }
+            }
+        }
+    }
+
+    /// Returns the leaves that touch the `point`, in voxel units.
+    fn leaves_touching(&self, point: [usize; 3]) -> Vec<Leaf> {
+        (0..8)
+            .filter_map(|c| {
+                let voxel: Option<Vec<usize>> = (0..3)
+                    .map(|a| match (c >> a) & 1 {
+                        0 => point[a].checked_sub(1),
+                        _ => Some(point[a]).filter(|&p| p < self.nel[a]),
+                    })
+                    .collect();
+                voxel.map(|v| self.leaf_at([v[0], v[1], v[2]]))
+            })
+            .collect()
+    }
+
+    /// Returns the marked corners of the `leaves`, each with the size of the leaves
+    /// it is marked for.
+    ///
+    /// A corner of a leaf is marked for the leaves three times its size, and a leaf
+    /// without a template for its marked corners has more of them marked, until
+    /// every leaf has a template.
+    ///
+    /// # Errors
+    ///
+    /// The leaves to subdivide first, which touch a newly marked corner and are
+    /// coarser than the leaves it is marked for.
+    fn marked_corners(&self, leaves: &[Leaf]) -> Result<HashSet<([usize; 3], usize)>, Vec<Leaf>> {
+        let promotions = promotions();
+        let mut corners: HashSet<_> = leaves
+            .iter()
+            .flat_map(|leaf| (0..8).map(move |c| (corner(leaf, c), 3 * leaf.size)))
+            .collect();
+        let mut coarse = Vec::new();
+        let mut queue = leaves.to_vec();
+        while let Some(leaf) = queue.pop() {
+            let bits = marked(&leaf, &corners);
+            let extra = promotions[bits as usize] & !bits;
+            for c in (0..8).filter(|&c| (extra >> c) & 1 == 1) {
+                let point = corner(&leaf, c);
+                corners.insert((point, leaf.size));
+                for other in self.leaves_touching(point) {
+                    match other.size.cmp(&leaf.size) {
+                        Ordering::Greater => coarse.push(other),
+                        Ordering::Equal => queue.push(other),
+                        Ordering::Less => {}
+                    }
+                }
+            }
+        }
+        if coarse.is_empty() {
+            Ok(corners)
+        } else {
+            Err(coarse)
+        }
+    }
+
+    /// Subdivides the `leaf` into twenty-seven children with its label.
+    fn subdivide(&mut self, leaf: &Leaf) {
+        let (mut node, mut origin, mut size) = (&mut self.root, [0, 0, 0], self.size);
+        while size > leaf.size {
+            let Node::Children(children) = node else {
+                return;
+            };
+            size /= 3;
+            let offset = [0, 1, 2].map(|a| (leaf.origin[a] - origin[a]) / size);
+            origin = [0, 1, 2].map(|a| origin[a] + offset[a] * size);
+            node = &mut children[offset[0] + 3 * offset[1] + 9 * offset[2]];
+        }
+        if let Node::Leaf(label) = node {
+            *node = Node::Children(Box::new(std::array::from_fn(|_| Node::Leaf(*label))));
+        }
+    }
+
+    /// Subdivides leaves until every two leaves that share a face, an edge, or a
+    /// corner differ by at most one level, and no leaf touches a corner marked for
+    /// smaller leaves.
+    ///
+    /// # Returns
+    ///
+    /// The number of leaves that were subdivided.
+    pub fn balance(&mut self) -> usize {
+        let mut subdivided = 0;
+        loop {
+            let leaves = self.leaves();
+            let mut coarse = Vec::new();
+            for leaf in &leaves {
+                for offset in (0..27).filter(|&n| n != 13) {
+                    let direction = child_offset(offset);
+                    // The voxel just beyond the leaf's face, edge or corner.
+                    let probe: Option<Vec<usize>> = (0..3)
+                        .map(|a| match direction[a] {
+                            0 => leaf.origin[a].checked_sub(1),
+                            1 => Some(leaf.origin[a]),
+                            _ => Some(leaf.origin[a] + leaf.size).filter(|&p| p < self.nel[a]),
+                        })
+                        .collect();
+                    if let Some(probe) = probe {
+                        let neighbor = self.leaf_at([probe[0], probe[1], probe[2]]);
+                        if neighbor.size > 3 * leaf.size {
+                            coarse.push(neighbor);
+                        }
+                    }
+                }
+            }
+            if coarse.is_empty() {
+                // Only once balanced are the marked corners those of a finer neighbor.
+                match self.marked_corners(&leaves) {
+                    Ok(_) => return subdivided,
+                    Err(leaves) => coarse = leaves,
+                }
+            }
+            coarse.sort_by_key(|leaf| (leaf.size, leaf.origin));
+            coarse.dedup_by_key(|leaf| (leaf.size, leaf.origin));
+            subdivided += coarse.len();
+            for leaf in coarse {
+                self.subdivide(&leaf);
+            }
+        }
+    }
+
+    /// Builds the hexahedral mesh of the balanced octree, in voxel units.
+    ///
+    /// Every leaf is split by the template of its marked corners, and its elements
+    /// take its label, so material interfaces lie on the voxel faces.
+    ///
+    /// # Arguments
+    ///
+    /// * `remove` - The labels of leaves to leave out of the mesh.
+    ///
+    /// # Panics
+    ///
+    /// If the octree has not been balanced.
+    pub fn hex_mesh(&self, remove: &[u8]) -> HexMesh {
+        let templates = template_hexahedra();
+        let leaves = self.leaves();
+        let corners = self
+            .marked_corners(&leaves)
+            .expect("a balanced octree has no leaf to subdivide");
+        let mut mesh = HexMesh::default();
+        let mut index_of: HashMap<[usize; 3], usize> = HashMap::new();
+
+        for leaf in leaves {
+            if remove.contains(&leaf.label) {
+                continue;
+            }
+            let (k, symmetry) =
+                template(marked(&leaf, &corners)).expect("marked corners have a template");
+            for hexahedron in &templates[k] {
+                // Node keys are in parts of a voxel, so that every node is exact.
+                let keys = hexahedron.map(|point| {
+                    let point = symmetry.point(point);
+                    [0, 1, 2].map(|a| PARTS * leaf.origin[a] + leaf.size * point[a])
+                });
+                let mut element = keys.map(|key| {
+                    *index_of.entry(key).or_insert_with(|| {
+                        mesh.nodes.push(key.map(|k| k as f64 / PARTS as f64));
+                        mesh.nodes.len() - 1
+                    })
+                });
+                // A reflection turns the element inside out.
+                if volume(&element.map(|n| mesh.nodes[n])) < 0.0 {
+                    element.swap(1, 3);
+                    element.swap(5, 7);
+                }
+                mesh.elements.push(element);
+                mesh.element_blocks.push(leaf.label);
+            }
+        }
+        mesh
+    }
+}
+
+/// Returns the corner `c` of the `leaf`, numbered `x + 2 y + 4 z`.
+fn corner(leaf: &Leaf, c: usize) -> [usize; 3] {
+    [0, 1, 2].map(|a| leaf.origin[a] + ((c >> a) & 1) * leaf.size)
+}
+
+/// Returns the corners of the `leaf` that are marked for its size, as bits
+/// `x + 2 y + 4 z`.
+fn marked(leaf: &Leaf, corners: &HashSet<([usize; 3], usize)>) -> u8 {
+    (0..8)
+        .filter(|&c| corners.contains(&(corner(leaf, c), leaf.size)))
+        .fold(0, |bits, c| bits | 1 << c)
+}
+
+/// Returns, for the marked corners of every leaf, the fewest marked corners with
+/// all of them that have a template.
+fn promotions() -> [u8; 256] {
+    let valid: Vec<bool> = (0..=u8::MAX).map(|bits| template(bits).is_some()).collect();
+    std::array::from_fn(|marked| {
+        (0..=u8::MAX)
+            .filter(|&bits| bits as usize & marked == marked && valid[bits as usize])
+            .min_by_key(|bits| bits.count_ones())
+            .unwrap_or(u8::MAX)
+    })
+}
+
+/// A symmetry of the cube, which takes axis `axes[a]` of a template to axis `a`
+/// of a leaf, reversed where `flip[a]`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Symmetry {
+    axes: [usize; 3],
+    flip: [bool; 3],
+}
+
+impl Symmetry {
+    /// Returns the 48 symmetries of the cube, starting with the identity.
+    fn all() -> impl Iterator<Item = Self> {
+        [
+            [0, 1, 2],
+            [0, 2, 1],
+            [1, 0, 2],
+            [1, 2, 0],
+            [2, 0, 1],
+            [2, 1, 0],
+        ]
+        .into_iter()
+        .flat_map(|axes| {
+            (0..8).map(move |f| Symmetry {
+                axes,
+                flip: [0, 1, 2].map(|a| (f >> a) & 1 == 1),
+            })
+        })
+    }
+
+    /// Takes a point of a template, in parts of a leaf side.
+    fn point(&self, point: [usize; 3]) -> [usize; 3] {
+        [0, 1, 2].map(|a| match self.flip[a] {
+            true => PARTS - point[self.axes[a]],
+            false => point[self.axes[a]],
+        })
+    }
+
+    /// Takes the bits of the marked corners of a template.
+    fn corners(&self, bits: u8) -> u8 {
+        (0..8)
+            .filter(|&c| (bits >> c) & 1 == 1)
+            .map(|c| {
+                let point = [c & 1, (c >> 1) & 1, c >> 2];
+                let image = [0, 1, 2].map(|a| point[self.axes[a]] ^ usize::from(self.flip[a]));
+                image[0] + 2 * image[1] + 4 * image[2]
+            })
+            .fold(0, |bits, c| bits | 1 << c)
+    }
+}
+
+/// Returns the template for the marked corners of a leaf, with the symmetry that
+/// takes it onto the leaf, or `None` if no template has those marked corners.
+fn template(marked: u8) -> Option<(usize, Symmetry)> {
+    TEMPLATES
+        .iter()
+        .enumerate()
+        .filter(|(_, bits)| bits.count_ones() == marked.count_ones())
+        .find_map(|(k, &bits)| {
+            Symmetry::all()
+                .find(|symmetry| symmetry.corners(bits) == marked)
+                .map(|symmetry| (k, symmetry))
+        })
+}
+
+/// Returns the hexahedra of each of the `TEMPLATES`, as points in parts of a leaf
+/// side.
+///
+/// A face with one marked corner has a square of a third of its side at that
+/// corner, and two quads from the square to the far corner.  A face marked along
+/// an edge has three squares along it, and the `TRANSITION` quads from those to
+/// the opposite edge.  A face marked at all of its corners has nine squares.
+fn template_hexahedra() -> [Vec<[[usize; 3]; 8]>; 5] {
+    // The corner template joins each face of the cube at the marked corner to the
+    // face of the leaf beyond it.
+    let mut corner = vec![cube([0, 0, 0], 9)];
+    for a in 0..3 {
+        let point = |at: usize, side: usize, n: usize| {
+            let mut point = [0; 3];
+            point[a] = at;
+            point[(a + 1) % 3] = CORNERS[n][0] * side;
+            point[(a + 2) % 3] = CORNERS[n][1] * side;
+            point
+        };
+        corner.push(std::array::from_fn(|n| match n < 4 {
+            true => point(9, 9, n),
+            false => point(PARTS, PARTS, n - 4),
+        }));
+    }
+
+    // The edge template extrudes the faces of the corner template along the edge,
+    // with the cubes at the edge split in three, and transitions beside them.
+    let along_x = |x: usize, [y, z]: [usize; 2]| [x, y, z];
+    let mut edge: Vec<_> = [0, 9, 18].map(|x| cube([x, 0, 0], 9)).to_vec();
+    edge.extend(transition_block(
+        [[9, 0], [9, 9], [27, 27], [27, 0]],
+        along_x,
+    ));
+    edge.extend(transition_block(
+        [[0, 9], [9, 9], [27, 27], [0, 27]],
+        along_x,
+    ));
+
+    // The face template extrudes a face marked along an edge across the face, with
+    // the cubes at the face split in three, and transitions above them.
+    let along_y = |y: usize, [x, z]: [usize; 2]| [x, y, z];
+    let mut face: Vec<_> = (0..9)
+        .map(|n| cube([9 * (n % 3), 9 * (n / 3), 0], 9))
+        .collect();
+    face.extend(transition_block(
+        [[0, 9], [9, 9], [9, 21], [0, 27]],
+        along_y,
+    ));
+    face.extend(transition_block(
+        [[9, 9], [18, 9], [18, 21], [9, 21]],
+        along_y,
+    ));
+    face.extend(transition_block(
+        [[18, 9], [27, 9], [27, 27], [18, 21]],
+        along_y,
+    ));
+    let top = [[9, 21], [18, 21], [27, 27], [0, 27]];
+    face.push(std::array::from_fn(|n| match n < 4 {
+        true => along_y(0, top[n]),
+        false => along_y(PARTS, top[n - 4]),
+    }));
+
+    // The full template splits the leaf into twenty-seven cubes.
+    let full = (0..27)
+        .map(|n| cube(child_offset(n).map(|o| 9 * o), 9))
+        .collect();
+
+    [vec![cube([0, 0, 0], PARTS)], corner, edge, face, full]
+}
+
+/// Returns the cube with the lower corner `origin` and `side` parts per side.
+fn cube(origin: [usize; 3], side: usize) -> [[usize; 3]; 8] {
+    CORNERS.map(|corner| [0, 1, 2].map(|a| origin[a] + corner[a] * side))
+}
+
+/// Returns the hexahedra that extrude the `TRANSITION` quads along the axis of
+/// `place`, over a `quad` of the other two axes.
+///
+/// The `quad` is `[q00, q10, q11, q01]` in bilinear coordinates `[s, t]`, and its
+/// edge at `t = 0` takes the three edges of the transition.
+fn transition_block(
+    quad: [[usize; 2]; 4],
+    place: impl Fn(usize, [usize; 2]) -> [usize; 3],
+) -> Vec<[[usize; 3]; 8]> {
+    let at = |s: usize, t: usize| {
+        [0, 1].map(|i| {
+            ((PARTS - s) * (PARTS - t) * quad[0][i]
+                + s * (PARTS - t) * quad[1][i]
+                + s * t * quad[2][i]
+                + (PARTS - s) * t * quad[3][i])
+                / (PARTS * PARTS)
+        })
+    };
+    TRANSITION
+        .iter()
+        .map(|transition| {
+            std::array::from_fn(|n| {
+                let [x, t] = transition[n % 4];
+                place(x, at(if n < 4 { 0 } else { PARTS }, t))
+            })
+        })
+        .collect()
+}
+
+/// Returns the signed volume of a hexahedron, with each face split into four
+/// triangles about its centroid.
+fn volume(p: &[[f64; 3]; 8]) -> f64 {
+    let mut volume = 0.0;
+    for face in FACES {
+        let c = [0, 1, 2].map(|a| face.iter().map(|&n| p[n][a]).sum::<f64>() / 4.0);
+        for k in 0..4 {
+            let (u, v) = (p[face[k]], p[face[(k + 1) % 4]]);
+            // The signed volume of the tetrahedron with the origin, over 6.
+            volume += (u[1] * v[2] - u[2] * v[1]) * c[0]
+                + (u[2] * v[0] - u[0] * v[2]) * c[1]
+                + (u[0] * v[1] - u[1] * v[0]) * c[2];
+        }
+    }
+    volume / 6.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxels::VoxelData;
+
+    /// Returns the volume of an element of a mesh.
+    fn hex_volume(nodes: &[[f64; 3]], element: &[usize; 8]) -> f64 {
+        volume(&element.map(|n| nodes[n]))
+    }
+
+    #[test]
+    fn test_uniform_segmentation() {
+        // A single label gives the root as the only leaf, and one element.
+        let voxels = Voxels::from(VoxelData::from_elem((9, 9, 9), 3));
+        let mut tree = Octree::from_voxels(&voxels);
+        assert_eq!(tree.leaves().len(), 1);
+        assert_eq!(tree.balance(), 0);
+        let mesh = tree.hex_mesh(&[]);
+        assert_eq!(mesh.elements.len(), 1);
+        assert_eq!(mesh.element_blocks, vec![3]);
+        assert!((hex_volume(&mesh.nodes, &mesh.elements[0]) - 729.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_templates() {
+        // Each template fills its leaf, and has the symmetries of its marked corners.
+        let templates = template_hexahedra();
+        for (k, hexahedra) in templates.iter().enumerate() {
+            assert_eq!(hexahedra.len(), [1, 4, 11, 22, 27][k]);
+            let volume: f64 = hexahedra
+                .iter()
+                .map(|h| volume(&h.map(|p| p.map(|c| c as f64))).abs())
+                .sum();
+            assert!((volume - (PARTS as f64).powi(3)).abs() < 1e-9);
+        }
+        assert_eq!(template(0b0100_0000).map(|(k, _)| k), Some(1));
+        assert_eq!(template(0b1010_0000).map(|(k, _)| k), Some(2));
+        assert_eq!(template(0b0101_0101).map(|(k, _)| k), Some(3));
+        assert_eq!(template(0b1111_1111).map(|(k, _)| k), Some(4));
+        assert_eq!(template(0b1000_0001), None);
+        assert_eq!(template(0b0000_0111), None);
+
+        // Other marked corners are promoted to the fewest that have a template.
+        let promotions = promotions();
+        assert_eq!(promotions[0b0100_0000], 0b0100_0000);
+        assert_eq!(promotions[0b0000_0111], 0b0000_1111);
+        assert_eq!(promotions[0b0000_1001], 0b0000_1111);
+        assert_eq!(promotions[0b1000_0001], 0b1111_1111);
+    }
+
+    #[test]
+    fn test_refine_balance_and_mesh() {
+        // A sphere of label 2 in a box of label 1, which is not a power of three.
+        let (nx, ny, nz) = (30, 28, 27);
+        let data = VoxelData::from_shape_fn((nz, ny, nx), |(z, y, x)| {
+            let d = [x as f64 - 14.5, y as f64 - 13.5, z as f64 - 13.0];
+            if d.iter().map(|d| d * d).sum::<f64>() < 36.0 {
+                2
+            } else {
+                1
+            }
+        });
+        let voxels = Voxels::from(data);
+        let mut tree = Octree::from_voxels(&voxels);
+
+        // Every leaf holds one label, and every leaf along an interface is a voxel.
+        let leaves = tree.leaves();
+        let volume: usize = leaves.iter().map(|leaf| leaf.size.pow(3)).sum();
+        assert_eq!(volume, nx * ny * nz);
+        for leaf in &leaves {
+            let region = voxels.get_data().slice(s![
+                leaf.origin[2]..leaf.origin[2] + leaf.size,
+                leaf.origin[1]..leaf.origin[1] + leaf.size,
+                leaf.origin[0]..leaf.origin[0] + leaf.size
+            ]);
+            assert!(region.iter().all(|&l| l == leaf.label));
+        }
+        assert!(leaves.len() < nx * ny * nz);
+
+        // After balancing, no neighbor is more than three times the size of a leaf.
+        tree.balance();
+        assert_eq!(tree.balance(), 0);
+        for leaf in tree.leaves() {
+            for a in 0..3 {
+                let mut probe = leaf.origin;
+                probe[a] += leaf.size;
+                if probe[a] < voxels.nel()[a] {
+                    assert!(tree.leaf_at(probe).size <= 3 * leaf.size);
+                }
+            }
+        }
+
+        // The elements fill the segmentation, with fewer of them than voxels, and
+        // every one is a hexahedron that is neither inverted nor degenerate.
+        let mesh = tree.hex_mesh(&[]);
+        let volume: f64 = mesh
+            .elements
+            .iter()
+            .map(|e| hex_volume(&mesh.nodes, e))
+            .sum();
+        assert!((volume - (nx * ny * nz) as f64).abs() < 1e-6);
+        assert!(mesh.elements.len() < nx * ny * nz);
+        assert!(mesh.elements.iter().all(|element| {
+            let mut nodes = element.to_vec();
+            nodes.sort_unstable();
+            nodes.dedup();
+            nodes.len() == 8
+        }));
+        let metrics = mesh.metrics();
+        assert!(metrics.inverted.is_empty());
+        assert!(metrics.degenerate.is_empty());
+        assert_eq!(mesh.blocks(), vec![1, 2]);
+
+        // The interface lies on the voxel faces, so each label keeps its volume.
+        let sphere_volume: f64 = mesh
+            .elements
+            .iter()
+            .zip(&mesh.element_blocks)
+            .filter(|&(_, &block)| block == 2)
+            .map(|(e, _)| hex_volume(&mesh.nodes, e))
+            .sum();
+        let sphere_voxels = voxels.get_data().iter().filter(|&&l| l == 2).count();
+        assert!((sphere_volume - sphere_voxels as f64).abs() < 1e-6);
+
+        // The mesh is conforming: every face is shared whole by two elements, or
+        // lies on the boundary of the segmentation.
+        let mut faces: HashMap<Vec<usize>, usize> = HashMap::new();
+        for element in &mesh.elements {
+            for face in FACES {
+                let mut nodes: Vec<usize> = face.iter().map(|&k| element[k]).collect();
+                nodes.sort_unstable();
+                *faces.entry(nodes).or_default() += 1;
+            }
+        }
+        let nel = voxels.nel();
+        for (nodes, count) in faces {
+            let on_boundary = (0..3).any(|a| {
+                [0.0, nel[a] as f64]
+                    .iter()
+                    .any(|&side| nodes.iter().all(|&n| mesh.nodes[n][a] == side))
+            });
+            assert_eq!(count, if on_boundary { 1 } else { 2 }, "face {:?}", nodes);
+        }
+
+        // Removing the box leaves the elements of the sphere.
+        let sphere = tree.hex_mesh(&[1]);
+        assert_eq!(sphere.blocks(), vec![2]);
+        assert_eq!(
+            sphere.elements.len(),
+            mesh.element_blocks.iter().filter(|&&b| b == 2).count()
+        );
+    }
+}
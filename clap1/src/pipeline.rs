@@ -0,0 +1,507 @@
+//! The steps of each `clap1` command, with their terminal output.
+//!
+//! The command line only parses its arguments into these functions, so other
+//! crates can run the same steps, and print the same progress, without it.
+//! Each command checks its file extensions and values before it reads anything.
+
+use crate::edit::Edit;
+use crate::error::Error;
+use crate::io::{
+    FileFormat, MeshFormat, MeshReader, MeshWriter, MetricsFormat, SegmentationFormat,
+};
+use crate::mesh::{HexMesh, Mesh, Transform, TriMesh};
+use crate::metrics::Metrics;
+use crate::npy;
+use crate::octree::Octree;
+use crate::smooth::SmoothingParams;
+use crate::voxels::Voxels;
+use std::time::Instant;
+
+/// The options of meshing a segmentation, applied in the order of the fields.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MeshOptions {
+    /// Defeatures clusters with fewer voxels than this before meshing.
+    pub defeature: Option<usize>,
+    /// The labels of voxels to leave out of the mesh.
+    pub remove: Vec<u8>,
+    /// Whether hexahedra come from the transition templates of a balanced
+    /// octree, rather than one per voxel.  There is no octree for triangles.
+    pub dual: bool,
+    /// The scaling and translation from voxel units.
+    pub transform: Transform,
+    /// The smoothing of the mesh, if any.
+    pub smoothing: Option<SmoothingParams>,
+    /// The quality metrics output file (csv | npy), if any.
+    pub metrics: Option<String>,
+}
+
+impl MeshOptions {
+    /// Checks the transform, the smoothing parameters and the extension of the
+    /// quality metrics output file.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.transform.validate()?;
+        if let Some(params) = &self.smoothing {
+            params.validate()?;
+        }
+        if let Some(metrics) = &self.metrics {
+            MetricsFormat::from_path(metrics)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns a bold, cyan version of the input string.
+pub(crate) fn bold_cyan(input: &str) -> String {
+    format!("\x1b[1;96m{}\x1b[0m", input)
+}
+
+/// Returns a bold, yellow version of the input string.
+pub(crate) fn bold_yellow(input: &str) -> String {
+    format!("\x1b[1;93m{}\x1b[0m", input)
+}
+
+/// Reads a segmentation from a `.npy` file, or from a `.spn` file with the
+/// number of voxels in each direction.
+pub fn read_segmentation(
+    input: &str,
+    nel: [Option<usize>; 3],
+    quiet: bool,
+) -> Result<Voxels, Error> {
+    if !quiet {
+        println!("     {} {}", bold_cyan("Reading"), input);
+    }
+    let voxels = Voxels::from_file(input, nel)?;
+    if !quiet {
+        let [nelx, nely, nelz] = voxels.nel();
+        println!(
+            "             {} voxels as (nelx, nely, nelz) = ({}, {}, {})",
+            nelx * nely * nelz,
+            nelx,
+            nely,
+            nelz
+        );
+    }
+    Ok(voxels)
+}
+
+/// Writes a segmentation to a `.npy` or `.spn` file.
+pub fn write_segmentation(voxels: &Voxels, output: &str, quiet: bool) -> Result<(), Error> {
+    if !quiet {
+        println!("     {} {}", bold_cyan("Writing"), output);
+    }
+    voxels.write_file(output)
+}
+
+/// Reads a hexahedral or triangular mesh in the format given by the extension of
+/// the input file.
+pub fn read_mesh(input: &str, quiet: bool) -> Result<Mesh, Error> {
+    if !quiet {
+        println!("     {} {}", bold_cyan("Reading"), input);
+    }
+    let mesh = Mesh::read(input)?;
+    if !quiet {
+        let (kind, elements, nodes) = match &mesh {
+            Mesh::Hex(mesh) => ("hexahedral", mesh.elements.len(), mesh.nodes.len()),
+            Mesh::Tri(mesh) => ("triangular", mesh.elements.len(), mesh.nodes.len()),
+        };
+        println!(
+            "             {} {} elements and {} nodes",
+            elements, kind, nodes
+        );
+    }
+    Ok(mesh)
+}
+
+/// Writes a mesh in the format given by the extension of the output file.
+pub fn write_mesh(mesh: &impl MeshWriter, output: &str, quiet: bool) -> Result<(), Error> {
+    if !quiet {
+        println!("     {} {}", bold_cyan("Writing"), output);
+    }
+    mesh.write(output)
+}
+
+/// Relabels the clusters with fewer than `min` voxels.
+pub fn defeature_segmentation(voxels: &mut Voxels, min: usize, quiet: bool) {
+    let time = Instant::now();
+    let relabeled = voxels.defeature(min);
+    if !quiet {
+        println!(
+            "  {} {} clusters with less than {} voxels in {:?}",
+            bold_cyan("Defeaturing"),
+            relabeled,
+            min,
+            time.elapsed()
+        );
+    }
+}
+
+/// Smooths a mesh, and prints the method and iterations, and the time they took.
+pub fn smooth_mesh(mesh: &mut Mesh, params: &SmoothingParams, quiet: bool) {
+    let time = Instant::now();
+    mesh.smooth(params);
+    if !quiet {
+        println!(
+            "   {} {} {:?} iterations{} in {:?}",
+            bold_cyan("Smoothing"),
+            params.iterations,
+            params.method,
+            if params.hierarchical {
+                " with hierarchical control"
+            } else {
+                ""
+            },
+            time.elapsed()
+        );
+    }
+}
+
+/// Computes the quality metrics of a mesh, prints their range and mean, and
+/// writes them in the format given by the extension of the output file.
+///
+/// Inverted and degenerate elements are warned of even when quiet.
+pub fn write_metrics(mesh: &Mesh, output: &str, quiet: bool) -> Result<(), Error> {
+    let time = Instant::now();
+    let metrics = mesh.metrics();
+    if !quiet {
+        println!(
+            "     {} {} elements in {:?}",
+            bold_cyan("Metrics"),
+            metrics.values.len(),
+            time.elapsed()
+        );
+        for summary in metrics.summaries() {
+            println!(
+                "             {}: min {:.4e}, max {:.4e}, mean {:.4e}",
+                summary.name, summary.min, summary.max, summary.mean
+            );
+        }
+    }
+    for (flagged, kind) in [
+        (&metrics.inverted, "inverted"),
+        (&metrics.degenerate, "degenerate"),
+    ] {
+        if let Some(first) = flagged.first() {
+            eprintln!(
+                "     {} {} {} elements, the first is element {}",
+                bold_yellow("Warning"),
+                flagged.len(),
+                kind,
+                first
+            );
+        }
+    }
+    if !quiet {
+        println!("     {} {}", bold_cyan("Writing"), output);
+    }
+    write_metrics_file(&metrics, output)
+}
+
+/// Writes the metrics in the format given by the extension of the output file.
+fn write_metrics_file(metrics: &Metrics, output: &str) -> Result<(), Error> {
+    match MetricsFormat::from_path(output)? {
+        MetricsFormat::Csv => metrics.write_csv(output),
+        MetricsFormat::Npy => npy::write_npy_f64(output, &metrics.to_array()),
+    }
+    .map_err(Error::from)
+}
+
+/// Writes a mesh in the format given by the extension of the output file, and
+/// warns of anything the format cannot hold.
+pub fn write_converted_mesh(
+    mesh: &impl MeshWriter,
+    output: &str,
+    quiet: bool,
+) -> Result<(), Error> {
+    for dropped in mesh.dropped(output) {
+        eprintln!(
+            "     {} {} are not written to {}",
+            bold_yellow("Warning"),
+            dropped,
+            output
+        );
+    }
+    write_mesh(mesh, output, quiet)
+}
+
+/// Converts a mesh between the formats given by the file extensions.
+pub fn convert_mesh(input: &str, output: &str, quiet: bool) -> Result<(), Error> {
+    MeshFormat::from_path_in(input, &MeshFormat::INPUTS)?;
+    MeshFormat::from_path(output)?;
+    let mesh = read_mesh(input, quiet)?;
+    write_converted_mesh(&mesh, output, quiet)
+}
+
+/// Converts a segmentation between the `.npy` and `.spn` formats.
+pub fn convert_segmentation(
+    input: &str,
+    output: &str,
+    nel: [Option<usize>; 3],
+    quiet: bool,
+) -> Result<(), Error> {
+    SegmentationFormat::from_input(input, nel)?;
+    SegmentationFormat::from_path(output)?;
+    let voxels = read_segmentation(input, nel, quiet)?;
+    write_segmentation(&voxels, output, quiet)
+}
+
+/// Relabels the clusters of a segmentation with fewer than `min` voxels.
+pub fn defeature(
+    input: &str,
+    output: &str,
+    min: usize,
+    nel: [Option<usize>; 3],
+    quiet: bool,
+) -> Result<(), Error> {
+    SegmentationFormat::from_input(input, nel)?;
+    SegmentationFormat::from_path(output)?;
+    let mut voxels = read_segmentation(input, nel, quiet)?;
+    defeature_segmentation(&mut voxels, min, quiet);
+    write_segmentation(&voxels, output, quiet)
+}
+
+/// Edits a segmentation file, and prints the number of voxels it leaves.
+pub fn edit_segmentation(
+    input: &str,
+    output: &str,
+    nel: [Option<usize>; 3],
+    edit: &Edit,
+    quiet: bool,
+) -> Result<(), Error> {
+    // The input of a permutation is read in the order it is stored in.
+    let nel = match edit {
+        Edit::Permute(order) => order.stored_nel(nel),
+        _ => nel,
+    };
+    SegmentationFormat::from_input(input, nel)?;
+    SegmentationFormat::from_path(output)?;
+    edit.validate()?;
+    let voxels = read_segmentation(input, nel, quiet)?;
+    let time = Instant::now();
+    let voxels = edit.apply(&voxels)?;
+    if !quiet {
+        let [nelx, nely, nelz] = voxels.nel();
+        println!(
+            "{} in {:?}",
+            bold_cyan(&format!("{:>12}", edit.verb())),
+            time.elapsed()
+        );
+        println!(
+            "             {} voxels as (nelx, nely, nelz) = ({}, {}, {})",
+            nelx * nely * nelz,
+            nelx,
+            nely,
+            nelz
+        );
+    }
+    write_segmentation(&voxels, output, quiet)
+}
+
+/// Meshes a segmentation file with hexahedra.
+pub fn mesh_hex(
+    input: &str,
+    output: &str,
+    nel: [Option<usize>; 3],
+    options: &MeshOptions,
+    quiet: bool,
+) -> Result<(), Error> {
+    SegmentationFormat::from_input(input, nel)?;
+    MeshFormat::from_path_in(output, &MeshFormat::HEX_OUTPUTS)?;
+    options.validate()?;
+    let voxels = prepare_segmentation(input, nel, options, quiet)?;
+    let mesh = hex_mesh(&voxels, &options.remove, options.dual, quiet);
+    finish_mesh(Mesh::Hex(mesh), output, options, quiet)
+}
+
+/// Meshes the surfaces of a segmentation file with triangles.
+pub fn mesh_tri(
+    input: &str,
+    output: &str,
+    nel: [Option<usize>; 3],
+    options: &MeshOptions,
+    quiet: bool,
+) -> Result<(), Error> {
+    SegmentationFormat::from_input(input, nel)?;
+    MeshFormat::from_path(output)?;
+    options.validate()?;
+    let voxels = prepare_segmentation(input, nel, options, quiet)?;
+    let mesh = tri_mesh(&voxels, &options.remove, quiet);
+    finish_mesh(Mesh::Tri(mesh), output, options, quiet)
+}
+
+/// Meshes a segmentation with one hexahedron per voxel, or with the transition
+/// templates of a balanced octree, in voxel units.
+pub fn hex_mesh(voxels: &Voxels, remove: &[u8], dual: bool, quiet: bool) -> HexMesh {
+    let time = Instant::now();
+    if dual {
+        let mut tree = Octree::from_voxels(voxels);
+        let leaves = tree.leaves().len();
+        tree.balance();
+        let mesh = tree.hex_mesh(remove);
+        if !quiet {
+            let kept = voxels
+                .get_data()
+                .iter()
+                .filter(|label| !remove.contains(label))
+                .count();
+            println!(
+                "     {} octree with {} leaves, {} after balancing",
+                bold_cyan("Meshing"),
+                leaves,
+                tree.leaves().len()
+            );
+            println!(
+                "             {} elements, for {} voxels",
+                mesh.elements.len(),
+                kept
+            );
+        }
+        mesh
+    } else {
+        let mesh = HexMesh::from_voxels(voxels, remove);
+        if !quiet {
+            println!(
+                "     {} {} elements and {} nodes in {:?}",
+                bold_cyan("Meshing"),
+                mesh.elements.len(),
+                mesh.nodes.len(),
+                time.elapsed()
+            );
+        }
+        mesh
+    }
+}
+
+/// Meshes the surfaces of a segmentation with triangles, in voxel units.
+pub fn tri_mesh(voxels: &Voxels, remove: &[u8], quiet: bool) -> TriMesh {
+    let time = Instant::now();
+    let mesh = TriMesh::from_voxels(voxels, remove);
+    if !quiet {
+        println!(
+            "     {} {} triangles and {} nodes in {:?}",
+            bold_cyan("Meshing"),
+            mesh.elements.len(),
+            mesh.nodes.len(),
+            time.elapsed()
+        );
+    }
+    mesh
+}
+
+/// Reads and defeatures the segmentation to mesh.
+fn prepare_segmentation(
+    input: &str,
+    nel: [Option<usize>; 3],
+    options: &MeshOptions,
+    quiet: bool,
+) -> Result<Voxels, Error> {
+    let mut voxels = read_segmentation(input, nel, quiet)?;
+    if let Some(min) = options.defeature {
+        defeature_segmentation(&mut voxels, min, quiet);
+    }
+    Ok(voxels)
+}
+
+/// Transforms, smooths and writes a new mesh, with its metrics.
+fn finish_mesh(
+    mut mesh: Mesh,
+    output: &str,
+    options: &MeshOptions,
+    quiet: bool,
+) -> Result<(), Error> {
+    mesh.transform(&options.transform);
+    if let Some(params) = &options.smoothing {
+        smooth_mesh(&mut mesh, params, quiet);
+    }
+    if let Some(metrics) = &options.metrics {
+        write_metrics(&mesh, metrics, quiet)?;
+    }
+    write_mesh(&mesh, output, quiet)
+}
+
+/// Computes the quality metrics of a mesh file.
+pub fn metrics(input: &str, output: &str, quiet: bool) -> Result<(), Error> {
+    MeshFormat::from_path_in(input, &MeshFormat::INPUTS)?;
+    MetricsFormat::from_path(output)?;
+    let mesh = read_mesh(input, quiet)?;
+    write_metrics(&mesh, output, quiet)
+}
+
+/// Smooths a mesh file.
+pub fn smooth(
+    input: &str,
+    output: &str,
+    params: &SmoothingParams,
+    metrics: Option<&str>,
+    quiet: bool,
+) -> Result<(), Error> {
+    MeshFormat::from_path_in(input, &MeshFormat::INPUTS)?;
+    MeshFormat::from_path(output)?;
+    params.validate()?;
+    if let Some(metrics) = metrics {
+        MetricsFormat::from_path(metrics)?;
+    }
+    let mut mesh = read_mesh(input, quiet)?;
+    // The element type of the input is known once it is read, and before it is
+    // smoothed.
+    MeshFormat::from_path_in(output, MeshFormat::outputs(&mesh))?;
+    smooth_mesh(&mut mesh, params, quiet);
+    if let Some(metrics) = metrics {
+        write_metrics(&mesh, metrics, quiet)?;
+    }
+    write_mesh(&mesh, output, quiet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxels::VoxelData;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("clap1_{}_{}", std::process::id(), name))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_tri_to_stl() {
+        let voxels = Voxels::from(VoxelData::from_elem((1, 1, 2), 1));
+        let tri = TriMesh::from_voxels(&voxels, &[]);
+        // Exodus II and Abaqus files hold triangles as well as hexahedra.
+        for extension in ["exo", "inp"] {
+            let input = temp_path(&format!("pipeline_tri_to_stl.{}", extension));
+            tri.write(&input).unwrap();
+            let (converted, smoothed) = (
+                temp_path(&format!("pipeline_tri_to_stl_{}.stl", extension)),
+                temp_path(&format!("pipeline_tri_to_stl_{}_smooth.stl", extension)),
+            );
+            convert_mesh(&input, &converted, true).unwrap();
+            let read = TriMesh::read(&converted).unwrap();
+            assert_eq!(read.elements.len(), tri.elements.len());
+            assert_eq!(read.nodes.len(), tri.nodes.len());
+            smooth(&input, &smoothed, &SmoothingParams::default(), None, true).unwrap();
+            assert_eq!(
+                TriMesh::read(&smoothed).unwrap().elements.len(),
+                tri.elements.len()
+            );
+        }
+
+        // A hexahedral mesh is rejected once it is read, before it is smoothed.
+        let input = temp_path("pipeline_tri_to_stl_hex.exo");
+        HexMesh::from_voxels(&voxels, &[]).write(&input).unwrap();
+        let output = temp_path("pipeline_tri_to_stl_hex.stl");
+        for error in [
+            convert_mesh(&input, &output, true).unwrap_err(),
+            smooth(&input, &output, &SmoothingParams::default(), None, true).unwrap_err(),
+        ] {
+            assert_eq!(
+                error.to_string(),
+                format!(
+                    "Unsupported file extension for {}, use exo, inp, mesh or vtk",
+                    output
+                )
+            );
+        }
+        assert!(!std::path::Path::new(&output).exists());
+    }
+}
@@ -0,0 +1,640 @@
+//! Recipes, which run the steps of several commands from one YAML file.
+//!
+//! The keys at the top of a recipe are those of an Autotwin recipe, such as
+//! `npy_input`, `scale_x` and `translate_x`, and any others, such as
+//! `sculpt_binary`, are ignored.  The `steps` then run in order, each on the
+//! segmentation or mesh left by the one before:
+//!
+//! ```yaml
+//! npy_input: letter_f.npy
+//! scale_x: 0.1
+//! steps:
+//!   - defeature: {min: 2}
+//!   - mesh: {type: hex, remove: [0], output: letter_f.exo}
+//!   - smooth: {method: Taubin, iterations: 10, output: letter_f_smooth.exo}
+//!   - metrics: {output: letter_f_metrics.csv}
+//! ```
+//!
+//! Relative paths in a recipe file are relative to the directory of the file,
+//! and may start with `~`.  The whole recipe is checked before the first step
+//! runs, so that a misspelled extension or a smooth step before the mesh step
+//! fails at once, rather than after the steps before it.
+
+use crate::edit::AxisOrder;
+use crate::error::Error;
+use crate::io::{FileFormat, MeshFormat, MetricsFormat, SegmentationFormat};
+use crate::mesh::{Mesh, Transform};
+use crate::pipeline::{self, bold_cyan};
+use crate::smooth::{self, SmoothingMethod, SmoothingParams};
+use crate::voxels::Voxels;
+use serde::Deserialize;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+/// The Autotwin versions of the recipe keys that recipes may have.
+pub const YML_SCHEMA_VERSIONS: RangeInclusive<f64> = 1.0..=1.8;
+
+/// The pipeline of a recipe file.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct Recipe {
+    /// The segmentation (npy | spn) or mesh (exo | inp | stl) input file.
+    #[serde(alias = "npy_input")]
+    pub input: String,
+    /// The number of voxels in the x-direction, for a `.spn` input file.
+    pub nelx: Option<usize>,
+    /// The number of voxels in the y-direction, for a `.spn` input file.
+    pub nely: Option<usize>,
+    /// The number of voxels in the z-direction, for a `.spn` input file.
+    pub nelz: Option<usize>,
+    /// The scaling of a new mesh in the x-direction, applied before translation.
+    pub scale_x: Option<f64>,
+    /// The scaling of a new mesh in the y-direction, applied before translation.
+    pub scale_y: Option<f64>,
+    /// The scaling of a new mesh in the z-direction, applied before translation.
+    pub scale_z: Option<f64>,
+    /// The translation of a new mesh in the x-direction.
+    pub translate_x: Option<f64>,
+    /// The translation of a new mesh in the y-direction.
+    pub translate_y: Option<f64>,
+    /// The translation of a new mesh in the z-direction.
+    pub translate_z: Option<f64>,
+    /// The Autotwin axis order of the segmentation input file, numbered as an
+    /// `AxisOrder`, from which it is permuted to have `x` changing fastest.
+    pub spn_xyz_order: Option<usize>,
+    /// The Autotwin version of the recipe keys.
+    pub yml_schema_version: Option<f64>,
+    /// The steps, in the order they run, each a map from its name to its
+    /// parameters.
+    #[serde(default, with = "serde_yaml::with::singleton_map_recursive")]
+    pub steps: Vec<Step>,
+}
+
+/// The element type of a `mesh` step.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MeshKind {
+    Hex,
+    Tri,
+}
+
+/// A step of a recipe, with the parameters of the command of the same name.
+///
+/// A step with an `output` also writes the segmentation or mesh it leaves.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "lowercase")]
+pub enum Step {
+    /// Writes the segmentation or mesh in the format of the output file.
+    Convert { output: String },
+    /// Relabels the clusters of the segmentation with fewer than `min` voxels.
+    Defeature { min: usize, output: Option<String> },
+    /// Meshes the segmentation, and then scales and translates the mesh.
+    Mesh {
+        #[serde(rename = "type")]
+        kind: MeshKind,
+        #[serde(default)]
+        remove: Vec<u8>,
+        #[serde(default)]
+        dual: bool,
+        output: Option<String>,
+    },
+    /// Smooths the mesh, with the defaults of the `smooth` command.
+    Smooth {
+        #[serde(default)]
+        hierarchical: bool,
+        iterations: Option<usize>,
+        method: Option<String>,
+        pass_band: Option<f64>,
+        scale: Option<f64>,
+        output: Option<String>,
+    },
+    /// Writes the quality metrics of the mesh.
+    Metrics { output: String },
+}
+
+impl Step {
+    /// Returns the name of the step, as in the recipe.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Step::Convert { .. } => "convert",
+            Step::Defeature { .. } => "defeature",
+            Step::Mesh { .. } => "mesh",
+            Step::Smooth { .. } => "smooth",
+            Step::Metrics { .. } => "metrics",
+        }
+    }
+
+    /// Returns the error of running the step on a segmentation, when it needs a
+    /// mesh, or the other way around.
+    fn out_of_order(&self, meshed: bool) -> Error {
+        Error::Invalid(if meshed {
+            format!(
+                "The {} step needs a segmentation, which is already meshed",
+                self.name()
+            )
+        } else {
+            format!(
+                "The {} step needs a mesh, so a mesh step must come before it",
+                self.name()
+            )
+        })
+    }
+}
+
+/// What the steps of a recipe work on.
+enum State {
+    Segmentation(Voxels),
+    Mesh(Mesh),
+}
+
+/// What the steps of a recipe will work on, with the element type of the mesh
+/// when a step makes it, or once it is read.
+#[derive(Clone, Copy)]
+enum Stage {
+    Segmentation,
+    Mesh(Option<MeshKind>),
+}
+
+/// Returns the smoothing parameters of a `smooth` step, with the defaults of
+/// the `smooth` command.
+fn smoothing_params(
+    hierarchical: bool,
+    iterations: Option<usize>,
+    method: Option<&str>,
+    pass_band: Option<f64>,
+    scale: Option<f64>,
+) -> Result<SmoothingParams, Error> {
+    Ok(SmoothingParams {
+        method: match method {
+            Some(name) => name.parse()?,
+            None => SmoothingMethod::Taubin,
+        },
+        iterations: iterations.unwrap_or(smooth::ITERATIONS_DEFAULT),
+        scale: scale.unwrap_or(smooth::SCALE_DEFAULT),
+        pass_band: pass_band.unwrap_or(smooth::PASS_BAND_DEFAULT),
+        hierarchical,
+    })
+}
+
+/// Checks the extension of a mesh output file, which for hexahedra cannot be
+/// STL.
+fn check_mesh_output(output: &str, kind: Option<MeshKind>) -> Result<(), Error> {
+    match kind {
+        Some(MeshKind::Hex) => MeshFormat::from_path_in(output, &MeshFormat::HEX_OUTPUTS)?,
+        _ => MeshFormat::from_path(output)?,
+    };
+    Ok(())
+}
+
+/// Expands a leading `~` to the home directory, as a shell would.
+fn expand_tilde(path: &str) -> String {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{}", home, rest),
+        _ => path.to_string(),
+    }
+}
+
+/// Expands a leading `~` of a path in a recipe file, and makes a relative path
+/// relative to the directory of the file.
+fn resolve(path: &mut String, directory: &Path) {
+    let expanded = expand_tilde(path);
+    *path = match Path::new(&expanded).is_relative() {
+        true => directory.join(expanded).to_string_lossy().into_owned(),
+        false => expanded,
+    };
+}
+
+impl Recipe {
+    /// Reads a recipe from a YAML file, with its relative paths made relative to
+    /// the directory of the file.
+    pub fn from_file(file_path: &str) -> Result<Self, Error> {
+        let contents = fs::read_to_string(file_path)
+            .map_err(|e| format!("Failed to read {}: {}", file_path, e))?;
+        let mut recipe = Self::from_yaml(&contents)
+            .map_err(|e| Error::Invalid(format!("{}: {}", file_path, e)))?;
+        let directory = Path::new(file_path).parent().unwrap_or(Path::new(""));
+        resolve(&mut recipe.input, directory);
+        for step in &mut recipe.steps {
+            match step {
+                Step::Convert { output } | Step::Metrics { output } => resolve(output, directory),
+                Step::Defeature { output, .. }
+                | Step::Mesh { output, .. }
+                | Step::Smooth { output, .. } => {
+                    if let Some(output) = output {
+                        resolve(output, directory);
+                    }
+                }
+            }
+        }
+        Ok(recipe)
+    }
+
+    /// Parses a recipe from YAML text.
+    pub fn from_yaml(contents: &str) -> Result<Self, Error> {
+        serde_yaml::from_str(contents).map_err(|e| Error::Invalid(e.to_string()))
+    }
+
+    /// Returns the number of voxels in each direction, for a `.spn` input file.
+    pub fn nel(&self) -> [Option<usize>; 3] {
+        [self.nelx, self.nely, self.nelz]
+    }
+
+    /// Returns the axis order of the segmentation input file, if it is not the
+    /// `zyx` order of a segmentation.
+    fn axis_order(&self) -> Result<Option<AxisOrder>, Error> {
+        match self.spn_xyz_order.map(AxisOrder::from_spn_xyz_order) {
+            Some(Ok(AxisOrder::Zyx)) | None => Ok(None),
+            Some(order) => order.map(Some),
+        }
+    }
+
+    /// Returns the scaling and translation of new meshes, which is the identity
+    /// in the directions the recipe leaves out.
+    pub fn transform(&self) -> Transform {
+        Transform {
+            scale: [self.scale_x, self.scale_y, self.scale_z].map(|s| s.unwrap_or(1.0)),
+            translate: [self.translate_x, self.translate_y, self.translate_z]
+                .map(|t| t.unwrap_or(0.0)),
+        }
+    }
+
+    /// Checks the schema version, the input file, the transform, and each step in
+    /// order, with the extensions of its output files and whether it has what it
+    /// needs.
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Some(version) = self
+            .yml_schema_version
+            .filter(|version| !YML_SCHEMA_VERSIONS.contains(version))
+        {
+            return Err(Error::Value {
+                name: "yml_schema_version",
+                value: version.to_string(),
+                expected: "a version from 1.0 to 1.8",
+            });
+        }
+        let input = expand_tilde(&self.input);
+        let order = self.axis_order()?;
+        let stage = if SegmentationFormat::from_path(&input).is_ok() {
+            let nel = self.nel();
+            SegmentationFormat::from_input(&input, order.map_or(nel, |o| o.stored_nel(nel)))?;
+            Stage::Segmentation
+        } else if MeshFormat::from_path_in(&input, &MeshFormat::INPUTS).is_ok() {
+            // An Exodus II or Abaqus file may hold either element type.
+            Stage::Mesh(None)
+        } else {
+            return Err(Error::Format {
+                file_path: input,
+                expected: "npy, spn, exo, inp or stl".to_string(),
+            });
+        };
+        self.transform().validate()?;
+        self.validate_steps(stage)
+    }
+
+    /// Checks each step in order, from the `stage` of the input.
+    fn validate_steps(&self, mut stage: Stage) -> Result<(), Error> {
+        for step in &self.steps {
+            stage = match (step, stage) {
+                (Step::Convert { output }, Stage::Segmentation) => {
+                    SegmentationFormat::from_path(output)?;
+                    stage
+                }
+                (Step::Convert { output }, Stage::Mesh(kind)) => {
+                    check_mesh_output(output, kind)?;
+                    stage
+                }
+                (Step::Defeature { output, .. }, Stage::Segmentation) => {
+                    if let Some(output) = output {
+                        SegmentationFormat::from_path(output)?;
+                    }
+                    stage
+                }
+                (Step::Mesh { kind, output, .. }, Stage::Segmentation) => {
+                    if let Some(output) = output {
+                        check_mesh_output(output, Some(*kind))?;
+                    }
+                    Stage::Mesh(Some(*kind))
+                }
+                (
+                    Step::Smooth {
+                        hierarchical,
+                        iterations,
+                        method,
+                        pass_band,
+                        scale,
+                        output,
+                    },
+                    Stage::Mesh(kind),
+                ) => {
+                    smoothing_params(
+                        *hierarchical,
+                        *iterations,
+                        method.as_deref(),
+                        *pass_band,
+                        *scale,
+                    )?
+                    .validate()?;
+                    if let Some(output) = output {
+                        check_mesh_output(output, kind)?;
+                    }
+                    stage
+                }
+                (Step::Metrics { output }, Stage::Mesh(_)) => {
+                    MetricsFormat::from_path(output)?;
+                    stage
+                }
+                (step, Stage::Segmentation) => return Err(step.out_of_order(false)),
+                (step, Stage::Mesh(_)) => return Err(step.out_of_order(true)),
+            };
+        }
+        Ok(())
+    }
+
+    /// Runs the steps in order, from the input file, once the recipe is valid.
+    pub fn run(&self, quiet: bool) -> Result<(), Error> {
+        self.validate()?;
+        let input = expand_tilde(&self.input);
+        let mut state = match SegmentationFormat::from_path(&input) {
+            Ok(_) => match self.axis_order()? {
+                Some(order) => {
+                    let nel = order.stored_nel(self.nel());
+                    let voxels = pipeline::read_segmentation(&input, nel, quiet)?;
+                    State::Segmentation(voxels.permute(order))
+                }
+                None => {
+                    State::Segmentation(pipeline::read_segmentation(&input, self.nel(), quiet)?)
+                }
+            },
+            _ => {
+                let mesh = pipeline::read_mesh(&input, quiet)?;
+                // The outputs are checked against the element type of the mesh
+                // read, before the first step runs.
+                self.validate_steps(Stage::Mesh(Some(match mesh {
+                    Mesh::Hex(_) => MeshKind::Hex,
+                    Mesh::Tri(_) => MeshKind::Tri,
+                })))?;
+                State::Mesh(mesh)
+            }
+        };
+        for (k, step) in self.steps.iter().enumerate() {
+            if !quiet {
+                println!(
+                    "        {} {} of {}, {}",
+                    bold_cyan("Step"),
+                    k + 1,
+                    self.steps.len(),
+                    step.name()
+                );
+            }
+            state = self.run_step(step, state, quiet)?;
+        }
+        Ok(())
+    }
+
+    /// Runs one step, and returns what it leaves for the next.
+    fn run_step(&self, step: &Step, state: State, quiet: bool) -> Result<State, Error> {
+        match (step, state) {
+            (Step::Convert { output }, State::Segmentation(voxels)) => {
+                pipeline::write_segmentation(&voxels, &expand_tilde(output), quiet)?;
+                Ok(State::Segmentation(voxels))
+            }
+            (Step::Convert { output }, State::Mesh(mesh)) => {
+                pipeline::write_converted_mesh(&mesh, &expand_tilde(output), quiet)?;
+                Ok(State::Mesh(mesh))
+            }
+            (Step::Defeature { min, output }, State::Segmentation(mut voxels)) => {
+                pipeline::defeature_segmentation(&mut voxels, *min, quiet);
+                if let Some(output) = output {
+                    pipeline::write_segmentation(&voxels, &expand_tilde(output), quiet)?;
+                }
+                Ok(State::Segmentation(voxels))
+            }
+            (
+                Step::Mesh {
+                    kind,
+                    remove,
+                    dual,
+                    output,
+                },
+                State::Segmentation(voxels),
+            ) => {
+                let mut mesh = match kind {
+                    MeshKind::Hex => Mesh::Hex(pipeline::hex_mesh(&voxels, remove, *dual, quiet)),
+                    MeshKind::Tri => Mesh::Tri(pipeline::tri_mesh(&voxels, remove, quiet)),
+                };
+                mesh.transform(&self.transform());
+                if let Some(output) = output {
+                    pipeline::write_mesh(&mesh, &expand_tilde(output), quiet)?;
+                }
+                Ok(State::Mesh(mesh))
+            }
+            (
+                Step::Smooth {
+                    hierarchical,
+                    iterations,
+                    method,
+                    pass_band,
+                    scale,
+                    output,
+                },
+                State::Mesh(mut mesh),
+            ) => {
+                let params = smoothing_params(
+                    *hierarchical,
+                    *iterations,
+                    method.as_deref(),
+                    *pass_band,
+                    *scale,
+                )?;
+                pipeline::smooth_mesh(&mut mesh, &params, quiet);
+                if let Some(output) = output {
+                    pipeline::write_mesh(&mesh, &expand_tilde(output), quiet)?;
+                }
+                Ok(State::Mesh(mesh))
+            }
+            (Step::Metrics { output }, State::Mesh(mesh)) => {
+                pipeline::write_metrics(&mesh, &expand_tilde(output), quiet)?;
+                Ok(State::Mesh(mesh))
+            }
+            (step, State::Segmentation(_)) => Err(step.out_of_order(false)),
+            (step, State::Mesh(_)) => Err(step.out_of_order(true)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::MeshReader;
+    use crate::mesh::HexMesh;
+
+    #[test]
+    fn test_from_yaml() {
+        let recipe = Recipe::from_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/input/letter_f_recipe.yml"
+        ))
+        .unwrap();
+        // The input is next to the recipe, rather than in the current directory.
+        let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/input");
+        assert_eq!(recipe.input, format!("{}/letter_f.npy", directory));
+        assert_eq!(recipe.validate(), Ok(()));
+        assert_eq!(
+            recipe.steps[1],
+            Step::Mesh {
+                kind: MeshKind::Hex,
+                remove: vec![0],
+                dual: false,
+                output: Some(format!("{}/letter_f.exo", directory)),
+            }
+        );
+        assert_eq!(recipe.transform().scale, [0.1, 0.1, 0.1]);
+        assert_eq!(recipe.transform().translate, [-0.15, -0.25, -0.2]);
+        assert_eq!(recipe.yml_schema_version, Some(1.8));
+        assert_eq!(
+            recipe.steps.iter().map(Step::name).collect::<Vec<_>>(),
+            vec!["defeature", "mesh", "smooth", "metrics"]
+        );
+
+        let error = Recipe::from_yaml("input: a.npy\nsteps:\n  - mesh: {type: hex, lables: [0]}")
+            .unwrap_err();
+        assert!(error.to_string().contains("unknown field `lables`"));
+        assert!(Recipe::from_yaml("input: a.npy\nsteps:\n  - mesh: {type: quad}").is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        let temp = |name: &str| {
+            std::env::temp_dir()
+                .join(format!("clap1_{}_{}", std::process::id(), name))
+                .to_string_lossy()
+                .into_owned()
+        };
+        let input = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/input/letter_f.npy");
+        let (exo, csv) = (temp("recipe_run.exo"), temp("recipe_run.csv"));
+        let recipe = Recipe::from_yaml(&format!(
+            "npy_input: {}\nscale_x: 2.0\nsteps:\n  - mesh: {{type: hex, remove: [0]}}\n  \
+             - smooth: {{iterations: 2, output: {}}}\n  - metrics: {{output: {}}}\n",
+            input, exo, csv
+        ))
+        .unwrap();
+        recipe.run(true).unwrap();
+        let mesh = HexMesh::read(&exo).unwrap();
+        assert_eq!(mesh.elements.len(), 39);
+        let width = mesh.nodes.iter().map(|node| node[0]).fold(0.0, f64::max);
+        assert!(width > 5.0);
+        assert_eq!(fs::read_to_string(&csv).unwrap().lines().count(), 40);
+
+        let recipe = Recipe {
+            input: input.to_string(),
+            steps: vec![Step::Metrics { output: csv }],
+            ..Default::default()
+        };
+        assert!(
+            recipe
+                .run(true)
+                .unwrap_err()
+                .to_string()
+                .contains("needs a mesh")
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let recipe = |steps: &str| Recipe::from_yaml(&format!("input: a.npy\nsteps:\n{}", steps));
+        assert_eq!(
+            recipe("  - mesh: {type: tri, output: a.stl}")
+                .unwrap()
+                .validate(),
+            Ok(())
+        );
+        let invalid = [
+            (
+                "  - mesh: {type: hex, output: a.stl}",
+                "use exo, inp, mesh or vtk",
+            ),
+            (
+                "  - mesh: {type: hex}\n  - smooth: {scale: 2.0}",
+                "smoothing scale 2",
+            ),
+            (
+                "  - mesh: {type: hex}\n  - smooth: {method: Gauss}",
+                "use Laplace or Taubin",
+            ),
+            (
+                "  - mesh: {type: hex}\n  - metrics: {output: a.txt}",
+                "use csv or npy",
+            ),
+            (
+                "  - mesh: {type: hex}\n  - defeature: {min: 2}",
+                "already meshed",
+            ),
+        ];
+        for (steps, message) in invalid {
+            let error = recipe(steps).unwrap().validate().unwrap_err();
+            assert!(error.to_string().contains(message), "{}", error);
+        }
+        // An Exodus II input may be a triangle mesh, which can be written to STL.
+        let exo = Recipe::from_yaml("input: a.exo\nsteps:\n  - smooth: {output: a.stl}").unwrap();
+        assert_eq!(exo.validate(), Ok(()));
+        let newer = Recipe {
+            input: "a.npy".to_string(),
+            yml_schema_version: Some(2.0),
+            ..Default::default()
+        };
+        let error = newer.validate().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid yml_schema_version 2, use a version from 1.0 to 1.8"
+        );
+        let spn = Recipe {
+            input: "a.spn".to_string(),
+            nelx: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(spn.validate().unwrap_err().exit_code(), 2);
+        let spn = Recipe {
+            nely: Some(5),
+            nelz: Some(3),
+            spn_xyz_order: Some(6),
+            ..spn
+        };
+        assert!(
+            spn.validate()
+                .unwrap_err()
+                .to_string()
+                .contains("spn_xyz_order 6")
+        );
+    }
+
+    #[test]
+    fn test_spn_xyz_order() {
+        let temp = |name: &str| {
+            std::env::temp_dir()
+                .join(format!("clap1_{}_{}", std::process::id(), name))
+                .to_string_lossy()
+                .into_owned()
+        };
+        let input = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/input/letter_f.npy");
+        let voxels = Voxels::from_npy(input).unwrap();
+        // The letter F stored with x changing slowest, as spn_xyz_order 0.
+        let (spn, npy) = (
+            temp("recipe_spn_xyz_order.spn"),
+            temp("recipe_spn_xyz_order.npy"),
+        );
+        voxels.permute(AxisOrder::Xyz).write_spn(&spn).unwrap();
+        let recipe = Recipe {
+            input: spn,
+            nelx: Some(4),
+            nely: Some(5),
+            nelz: Some(3),
+            spn_xyz_order: Some(0),
+            steps: vec![Step::Convert {
+                output: npy.clone(),
+            }],
+            ..Default::default()
+        };
+        recipe.run(true).unwrap();
+        assert_eq!(Voxels::from_npy(&npy).unwrap(), voxels);
+    }
+}
@@ -0,0 +1,489 @@
+//! Laplacian and Taubin smoothing of hexahedral and triangular meshes.
+//!
+//! The neighbors of a node are the nodes it shares an element edge with.  A
+//! Laplacian step moves every node toward the average of its neighbors, and a
+//! Taubin iteration follows it with an inflating step, so that the mesh is
+//! smoothed without shrinking.
+//!
+//! Under hierarchical control, each node is a boundary, interface or interior
+//! node, and only uses the neighbors of its own kind or of a kind before it.
+//! Boundary nodes then follow only the boundary, and interface nodes only the
+//! interfaces and the boundary, so both keep their shape while the interior
+//! relaxes.
+
+use crate::error::Error;
+use crate::mesh::{HexMesh, Mesh, TriMesh};
+use std::collections::HashMap;
+
+/// The default scaling parameter (lambda) for all smoothing methods.
+pub const SCALE_DEFAULT: f64 = 0.6307;
+
+/// The default pass-band frequency (k) for Taubin smoothing.
+pub const PASS_BAND_DEFAULT: f64 = 0.1;
+
+/// The default number of smoothing iterations.
+pub const ITERATIONS_DEFAULT: usize = 20;
+
+/// The twelve edges of a hexahedron, as pairs of element nodes.
+const HEX_EDGES: [[usize; 2]; 12] = [
+    [0, 1],
+    [1, 2],
+    [2, 3],
+    [3, 0],
+    [4, 5],
+    [5, 6],
+    [6, 7],
+    [7, 4],
+    [0, 4],
+    [1, 5],
+    [2, 6],
+    [3, 7],
+];
+
+/// The six faces of a hexahedron, as element nodes ordered counter-clockwise
+/// about the outward normal, in the Exodus II order of the sides.
+const HEX_FACES: [[usize; 4]; 6] = [
+    [0, 1, 5, 4],
+    [1, 2, 6, 5],
+    [2, 3, 7, 6],
+    [0, 4, 7, 3],
+    [0, 3, 2, 1],
+    [4, 5, 6, 7],
+];
+
+/// The three edges of a triangle, as pairs of element nodes.
+const TRI_EDGES: [[usize; 2]; 3] = [[0, 1], [1, 2], [2, 0]];
+
+/// The smoothing algorithm applied to the nodes.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum SmoothingMethod {
+    /// Laplacian smoothing, which moves each node by `scale` toward the average
+    /// of its neighbors.  Repeated application shrinks the mesh.
+    #[value(name = "Laplace", help = "Laplacian smoothing, which shrinks the mesh")]
+    Laplace,
+    /// Taubin (lambda/mu) smoothing, which follows each Laplacian step with an
+    /// inflating step so that the mesh does not shrink.
+    #[value(
+        name = "Taubin",
+        help = "Taubin smoothing, which does not shrink the mesh"
+    )]
+    Taubin,
+}
+
+impl std::str::FromStr for SmoothingMethod {
+    type Err = Error;
+
+    /// Parses a method name, ignoring case.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "laplace" => Ok(Self::Laplace),
+            "taubin" => Ok(Self::Taubin),
+            _ => Err(Error::Value {
+                name: "smoothing method",
+                value: name.to_string(),
+                expected: "Laplace or Taubin",
+            }),
+        }
+    }
+}
+
+/// The kind of a node under hierarchical control, from the most constrained.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum NodeKind {
+    /// A node on the exterior boundary of the mesh.
+    Boundary,
+    /// A node between element blocks, which is not on the boundary.
+    Interface,
+    /// Any other node.
+    Interior,
+}
+
+/// Parameters that control the smoothing of a mesh.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SmoothingParams {
+    /// The smoothing algorithm.
+    pub method: SmoothingMethod,
+    /// The number of smoothing iterations.
+    pub iterations: usize,
+    /// The scaling parameter (lambda), with `0.0 < scale < 1.0`.
+    pub scale: f64,
+    /// The pass-band frequency (k) used to derive mu, for Taubin only.
+    pub pass_band: f64,
+    /// Whether each node only uses the neighbors of its own kind, or of a more
+    /// constrained kind.
+    pub hierarchical: bool,
+}
+
+impl Default for SmoothingParams {
+    /// Taubin smoothing with the same defaults as the `smooth` command.
+    fn default() -> Self {
+        Self {
+            method: SmoothingMethod::Taubin,
+            iterations: ITERATIONS_DEFAULT,
+            scale: SCALE_DEFAULT,
+            pass_band: PASS_BAND_DEFAULT,
+            hierarchical: false,
+        }
+    }
+}
+
+impl SmoothingParams {
+    /// Returns the Taubin inflation parameter, mu, from the relationship
+    /// `k = 1 / lambda + 1 / mu`, where `k` is the pass-band frequency.
+    ///
+    /// For the defaults, `lambda = 0.6307` and `k = 0.1`, mu is about `-0.6732`.
+    pub fn mu(&self) -> f64 {
+        1.0 / (self.pass_band - 1.0 / self.scale)
+    }
+
+    /// Checks that there is at least one iteration, and that the scale and the
+    /// pass-band frequency are each between zero and one, so that mu is a
+    /// larger inflation than lambda is a shrinkage.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.iterations == 0 {
+            return Err(Error::Value {
+                name: "number of smoothing iterations",
+                value: self.iterations.to_string(),
+                expected: "at least 1",
+            });
+        }
+        for (name, value) in [
+            ("smoothing scale", self.scale),
+            ("pass-band frequency", self.pass_band),
+        ] {
+            if !(value > 0.0 && value < 1.0) {
+                return Err(Error::Value {
+                    name,
+                    value: value.to_string(),
+                    expected: "a value between 0 and 1",
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Mesh {
+    /// Smooths the node coordinates in place.
+    pub fn smooth(&mut self, params: &SmoothingParams) {
+        match self {
+            Mesh::Hex(mesh) => mesh.smooth(params),
+            Mesh::Tri(mesh) => mesh.smooth(params),
+        }
+    }
+}
+
+impl HexMesh {
+    /// Smooths the node coordinates in place.
+    pub fn smooth(&mut self, params: &SmoothingParams) {
+        let mut neighbors = neighbors(self.nodes.len(), &self.elements, &HEX_EDGES);
+        if params.hierarchical {
+            restrict(&mut neighbors, &self.node_kinds());
+        }
+        smooth(&mut self.nodes, &neighbors, params);
+    }
+
+    /// Returns the kind of every node.
+    ///
+    /// Boundary nodes are on an element face that no other element shares, and
+    /// interface nodes are on elements of more than one block.
+    pub fn node_kinds(&self) -> Vec<NodeKind> {
+        let mut faces: HashMap<Vec<usize>, usize> = HashMap::new();
+        for element in &self.elements {
+            for face in HEX_FACES {
+                let mut key: Vec<usize> = face.iter().map(|&a| element[a]).collect();
+                key.sort_unstable();
+                key.dedup();
+                // A face of a collapsed element may be an edge or a node.
+                if key.len() > 2 {
+                    *faces.entry(key).or_default() += 1;
+                }
+            }
+        }
+        let mut kinds = vec![NodeKind::Interior; self.nodes.len()];
+        let mut first_block: Vec<Option<u8>> = vec![None; self.nodes.len()];
+        for (element, &block) in self.elements.iter().zip(&self.element_blocks) {
+            for &node in element {
+                match first_block[node] {
+                    None => first_block[node] = Some(block),
+                    Some(first) if first != block => kinds[node] = NodeKind::Interface,
+                    _ => (),
+                }
+            }
+        }
+        for (face, count) in faces {
+            if count == 1 {
+                for node in face {
+                    kinds[node] = NodeKind::Boundary;
+                }
+            }
+        }
+        kinds
+    }
+}
+
+impl TriMesh {
+    /// Smooths the node coordinates in place.
+    pub fn smooth(&mut self, params: &SmoothingParams) {
+        let mut neighbors = neighbors(self.nodes.len(), &self.elements, &TRI_EDGES);
+        if params.hierarchical {
+            restrict(&mut neighbors, &self.node_kinds());
+        }
+        smooth(&mut self.nodes, &neighbors, params);
+    }
+
+    /// Returns the kind of every node.
+    ///
+    /// A surface is made of patches, each separating one pair of blocks, or a
+    /// block and void.  Boundary nodes are on an element edge that no other
+    /// element shares, and interface nodes are where patches meet.
+    pub fn node_kinds(&self) -> Vec<NodeKind> {
+        let mut edges: HashMap<[usize; 2], usize> = HashMap::new();
+        for element in &self.elements {
+            for [a, b] in TRI_EDGES {
+                let (i, j) = (element[a], element[b]);
+                *edges.entry([i.min(j), i.max(j)]).or_default() += 1;
+            }
+        }
+        let mut kinds = vec![NodeKind::Interior; self.nodes.len()];
+        let mut first_patch = vec![None; self.nodes.len()];
+        for (k, element) in self.elements.iter().enumerate() {
+            let patch = (
+                self.element_blocks[k],
+                self.neighbor_blocks.get(k).copied().flatten(),
+            );
+            for &node in element {
+                match first_patch[node] {
+                    None => first_patch[node] = Some(patch),
+                    Some(first) if first != patch => kinds[node] = NodeKind::Interface,
+                    _ => (),
+                }
+            }
+        }
+        for (edge, count) in edges {
+            if count == 1 {
+                for node in edge {
+                    kinds[node] = NodeKind::Boundary;
+                }
+            }
+        }
+        kinds
+    }
+}
+
+/// Keeps only the neighbors of each node that are of its kind or of a more
+/// constrained kind.
+fn restrict(neighbors: &mut [Vec<usize>], kinds: &[NodeKind]) {
+    for (node, adjacent) in neighbors.iter_mut().enumerate() {
+        adjacent.retain(|&j| kinds[j] <= kinds[node]);
+    }
+}
+
+/// Returns the distinct neighbors of every node, through the element `edges`.
+///
+/// A collapsed edge, with the same node at both ends, is skipped.
+fn neighbors<const N: usize>(
+    count: usize,
+    elements: &[[usize; N]],
+    edges: &[[usize; 2]],
+) -> Vec<Vec<usize>> {
+    let mut neighbors = vec![Vec::new(); count];
+    for element in elements {
+        for &[a, b] in edges {
+            let (i, j) = (element[a], element[b]);
+            if i != j {
+                neighbors[i].push(j);
+                neighbors[j].push(i);
+            }
+        }
+    }
+    for adjacent in &mut neighbors {
+        adjacent.sort_unstable();
+        adjacent.dedup();
+    }
+    neighbors
+}
+
+/// Applies the smoothing iterations to the `nodes`.
+///
+/// Each iteration applies one Laplacian step scaled by `params.scale`; for Taubin
+/// smoothing, it is followed by a second step scaled by `params.mu()`.
+fn smooth(nodes: &mut [[f64; 3]], neighbors: &[Vec<usize>], params: &SmoothingParams) {
+    for _ in 0..params.iterations {
+        laplace_step(nodes, neighbors, params.scale);
+        if params.method == SmoothingMethod::Taubin {
+            laplace_step(nodes, neighbors, params.mu());
+        }
+    }
+}
+
+/// Moves every node by `factor` times its Laplacian, which is the vector from the
+/// node to the average of its neighbors.
+fn laplace_step(nodes: &mut [[f64; 3]], neighbors: &[Vec<usize>], factor: f64) {
+    // Compute all of the updates from the current positions before applying any.
+    let updates: Vec<[f64; 3]> = nodes
+        .iter()
+        .zip(neighbors)
+        .map(|(node, adjacent)| {
+            if adjacent.is_empty() {
+                return [0.0; 3];
+            }
+            let n = adjacent.len() as f64;
+            let mut sum = [0.0; 3];
+            for &j in adjacent {
+                for axis in 0..3 {
+                    sum[axis] += nodes[j][axis];
+                }
+            }
+            [0, 1, 2].map(|axis| factor * (sum[axis] / n - node[axis]))
+        })
+        .collect();
+
+    for (node, update) in nodes.iter_mut().zip(updates) {
+        for axis in 0..3 {
+            node[axis] += update[axis];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxels::{VoxelData, Voxels};
+
+    /// Returns a voxelized sphere of `radius` voxels, centered in its grid.
+    fn sphere(radius: f64) -> Voxels {
+        let n = 2 * radius.ceil() as usize + 2;
+        let center = n as f64 / 2.0;
+        Voxels::from(VoxelData::from_shape_fn((n, n, n), |(z, y, x)| {
+            let d = [x, y, z].map(|i| i as f64 + 0.5 - center);
+            (d.iter().map(|d| d * d).sum::<f64>() < radius * radius) as u8
+        }))
+    }
+
+    /// Returns the smallest and largest distance of the `nodes` from the center.
+    fn radii(nodes: &[[f64; 3]], center: f64) -> (f64, f64) {
+        nodes
+            .iter()
+            .map(|node| {
+                node.map(|x| (x - center).powi(2))
+                    .iter()
+                    .sum::<f64>()
+                    .sqrt()
+            })
+            .fold((f64::MAX, 0.0), |(min, max), r| (min.min(r), max.max(r)))
+    }
+
+    #[test]
+    fn test_smoothing_params() {
+        let params = SmoothingParams::default();
+        assert!((params.mu() - (-0.6732)).abs() < 1.0e-4);
+        assert_eq!("laplace".parse(), Ok(SmoothingMethod::Laplace));
+        assert_eq!("Taubin".parse(), Ok(SmoothingMethod::Taubin));
+        assert!("Gauss".parse::<SmoothingMethod>().is_err());
+
+        assert_eq!(params.validate(), Ok(()));
+        let invalid = [
+            SmoothingParams {
+                iterations: 0,
+                ..Default::default()
+            },
+            SmoothingParams {
+                scale: 1.5,
+                ..Default::default()
+            },
+            SmoothingParams {
+                pass_band: -0.1,
+                ..Default::default()
+            },
+            SmoothingParams {
+                scale: f64::NAN,
+                ..Default::default()
+            },
+        ];
+        for params in invalid {
+            assert_eq!(params.validate().unwrap_err().exit_code(), 2);
+        }
+    }
+
+    #[test]
+    fn test_hierarchical() {
+        // A 4x4x4 block of label 1 in the first layer of x, and 2 in the rest.
+        let voxels = Voxels::from(VoxelData::from_shape_fn((4, 4, 4), |(_, _, x)| {
+            1 + (x > 0) as u8
+        }));
+        let mesh = HexMesh::from_voxels(&voxels, &[]);
+        let kinds = mesh.node_kinds();
+        let count = |kind| kinds.iter().filter(|&&k| k == kind).count();
+        assert_eq!(count(NodeKind::Boundary), 125 - 27);
+        assert_eq!(count(NodeKind::Interface), 9);
+        assert_eq!(count(NodeKind::Interior), 18);
+
+        // One full Laplace step moves a node to the average of its neighbors,
+        // and an interface node then leaves out its interior neighbor.
+        let node = |p: [f64; 3]| mesh.nodes.iter().position(|&n| n == p).unwrap();
+        let interface = node([1.0, 1.0, 1.0]);
+        let step = |hierarchical| {
+            let mut smoothed = mesh.clone();
+            smoothed.smooth(&SmoothingParams {
+                method: SmoothingMethod::Laplace,
+                iterations: 1,
+                scale: 1.0,
+                hierarchical,
+                ..Default::default()
+            });
+            smoothed.nodes[interface]
+        };
+        assert_eq!(step(false), [1.0, 1.0, 1.0]);
+        assert_eq!(step(true), [0.8, 1.0, 1.0]);
+
+        // The surface of the same block has interfaces where its patches meet.
+        let surface = TriMesh::from_voxels(&voxels, &[]);
+        let kinds = surface.node_kinds();
+        let ring = kinds.iter().filter(|&&k| k == NodeKind::Interface).count();
+        assert_eq!(ring, 16);
+        assert!(!kinds.contains(&NodeKind::Boundary));
+    }
+
+    #[test]
+    fn test_smooth_sphere() {
+        let radius = 6.0;
+        let voxels = sphere(radius);
+        let center = voxels.nel()[0] as f64 / 2.0;
+        let surface = TriMesh::from_voxels(&voxels, &[0]);
+        let (min, max) = radii(&surface.nodes, center);
+
+        // Taubin smoothing rounds off the voxel steps, and keeps the size.
+        let mut taubin = surface.clone();
+        taubin.smooth(&SmoothingParams::default());
+        let (taubin_min, taubin_max) = radii(&taubin.nodes, center);
+        assert!(taubin_max - taubin_min < (max - min) / 2.0);
+        let mean = |nodes: &[[f64; 3]]| {
+            let (min, max) = radii(nodes, center);
+            (min + max) / 2.0
+        };
+        assert!((mean(&taubin.nodes) - radius).abs() < 0.5);
+
+        // Laplace smoothing shrinks the sphere.
+        let mut laplace = surface.clone();
+        laplace.smooth(&SmoothingParams {
+            method: SmoothingMethod::Laplace,
+            ..Default::default()
+        });
+        assert!(mean(&laplace.nodes) < mean(&taubin.nodes) - 0.5);
+
+        // Zero iterations leave the mesh alone.
+        let mut unchanged = surface.clone();
+        unchanged.smooth(&SmoothingParams {
+            iterations: 0,
+            ..Default::default()
+        });
+        assert_eq!(unchanged, surface);
+
+        // A hexahedral mesh keeps its inside nodes inside.
+        let mut hex = HexMesh::from_voxels(&voxels, &[0]);
+        hex.smooth(&SmoothingParams::default());
+        let (_, hex_max) = radii(&hex.nodes, center);
+        assert!(hex_max < max);
+    }
+}
@@ -0,0 +1,331 @@
+//! Reading and writing of ASCII and binary STL files.
+//!
+//! An STL file is a list of facets, each with a normal and three vertices, so a
+//! vertex shared by several facets is repeated.  Reading merges vertices with
+//! the same coordinates into an indexed triangle mesh, and writing computes the
+//! normals from the counter-clockwise order of the vertices.
+//!
+//! A binary file is an 80 byte header, the number of facets, and 50 bytes per
+//! facet: the normal and the vertices as twelve little-endian 32-bit floats, and
+//! a 16-bit attribute.  A file is read as binary when its size matches the number
+//! of facets, since some binary files also start with `solid`.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+
+/// The size of the header of a binary file.
+const HEADER: usize = 80;
+
+/// The size of one facet of a binary file.
+const FACET: usize = 50;
+
+/// An indexed triangle mesh, as read from or written to an STL file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StlMesh {
+    /// The vertex coordinates, each one only once.
+    pub vertices: Vec<[f64; 3]>,
+    /// The facets, as three vertex indices ordered counter-clockwise about the
+    /// outward normal.
+    pub facets: Vec<[usize; 3]>,
+}
+
+impl StlMesh {
+    /// Reads an ASCII or binary STL file.
+    pub fn read(file_path: &str) -> Result<Self, String> {
+        let bytes =
+            fs::read(file_path).map_err(|e| format!("Failed to read {}: {}", file_path, e))?;
+        Self::from_bytes(&bytes).map_err(|e| format!("{}: {}", file_path, e))
+    }
+
+    /// Parses the contents of an ASCII or binary STL file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let count = bytes
+            .get(HEADER..HEADER + 4)
+            .map(|count| u32::from_le_bytes(count.try_into().unwrap()) as usize);
+        let binary = count.is_some_and(|count| bytes.len() == HEADER + 4 + FACET * count);
+        if binary || !bytes.trim_ascii_start().starts_with(b"solid") {
+            from_binary(bytes)
+        } else {
+            let text = std::str::from_utf8(bytes).map_err(|e| format!("invalid text: {}", e))?;
+            from_ascii(text)
+        }
+    }
+
+    /// Returns the unit normal of each facet, or zero for a degenerate facet.
+    pub fn normals(&self) -> Vec<[f64; 3]> {
+        self.facets
+            .iter()
+            .map(|facet| {
+                let [a, b, c] = facet.map(|v| self.vertices[v]);
+                let (u, v) = (sub(b, a), sub(c, a));
+                let n = [
+                    u[1] * v[2] - u[2] * v[1],
+                    u[2] * v[0] - u[0] * v[2],
+                    u[0] * v[1] - u[1] * v[0],
+                ];
+                let length = n.iter().map(|x| x * x).sum::<f64>().sqrt();
+                match length > 0.0 {
+                    true => n.map(|x| x / length),
+                    false => [0.0; 3],
+                }
+            })
+            .collect()
+    }
+
+    /// Writes the mesh to an ASCII STL file.
+    pub fn write_ascii(&self, file_path: &str) -> Result<(), String> {
+        let file = File::create(file_path)
+            .map_err(|e| format!("Failed to create {}: {}", file_path, e))?;
+        let mut writer = BufWriter::new(file);
+        self.write_ascii_to(&mut writer)
+            .and_then(|_| writer.flush())
+            .map_err(|e| format!("Failed to write {}: {}", file_path, e))
+    }
+
+    /// Writes the mesh as ASCII STL, with one solid named after the package.
+    pub fn write_ascii_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let name = env!("CARGO_PKG_NAME");
+        writeln!(writer, "solid {}", name)?;
+        for (facet, [nx, ny, nz]) in self.facets.iter().zip(self.normals()) {
+            writeln!(writer, "  facet normal {:e} {:e} {:e}", nx, ny, nz)?;
+            writeln!(writer, "    outer loop")?;
+            for [x, y, z] in facet.map(|v| self.vertices[v]) {
+                writeln!(writer, "      vertex {:e} {:e} {:e}", x, y, z)?;
+            }
+            writeln!(writer, "    endloop")?;
+            writeln!(writer, "  endfacet")?;
+        }
+        writeln!(writer, "endsolid {}", name)
+    }
+
+    /// Writes the mesh to a binary STL file, in single precision.
+    pub fn write_binary(&self, file_path: &str) -> Result<(), String> {
+        let mut bytes = Vec::with_capacity(HEADER + 4 + FACET * self.facets.len());
+        let mut header = format!("{} binary STL", env!("CARGO_PKG_NAME")).into_bytes();
+        header.resize(HEADER, b' ');
+        bytes.extend(header);
+        bytes.extend((self.facets.len() as u32).to_le_bytes());
+        for (facet, normal) in self.facets.iter().zip(self.normals()) {
+            for point in [normal].into_iter().chain(facet.map(|v| self.vertices[v])) {
+                for x in point {
+                    bytes.extend((x as f32).to_le_bytes());
+                }
+            }
+            bytes.extend(0u16.to_le_bytes());
+        }
+        fs::write(file_path, bytes).map_err(|e| format!("Failed to write {}: {}", file_path, e))
+    }
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+/// Collects facets into an indexed mesh, merging vertices with equal coordinates.
+#[derive(Default)]
+struct Merger {
+    mesh: StlMesh,
+    index_of: HashMap<[u64; 3], usize>,
+}
+
+impl Merger {
+    fn push(&mut self, facet: [[f64; 3]; 3]) {
+        let facet = facet.map(|vertex| {
+            // Adding zero makes negative zero equal to zero.
+            let key = vertex.map(|x| (x + 0.0).to_bits());
+            *self.index_of.entry(key).or_insert_with(|| {
+                self.mesh.vertices.push(vertex);
+                self.mesh.vertices.len() - 1
+            })
+        });
+        self.mesh.facets.push(facet);
+    }
+}
+
+/// Parses a binary STL file, with errors at byte offsets.
+fn from_binary(bytes: &[u8]) -> Result<StlMesh, String> {
+    if bytes.len() < HEADER + 4 {
+        return Err(format!(
+            "byte {}: expected an {} byte header and a facet count",
+            bytes.len(),
+            HEADER
+        ));
+    }
+    let count = u32::from_le_bytes(bytes[HEADER..HEADER + 4].try_into().unwrap()) as usize;
+    let expected = HEADER + 4 + FACET * count;
+    if bytes.len() != expected {
+        let offset = bytes.len().min(expected);
+        return Err(format!(
+            "byte {}: {} facets need {} bytes, but the file has {}",
+            offset,
+            count,
+            expected,
+            bytes.len()
+        ));
+    }
+    let mut merger = Merger::default();
+    for (k, facet) in bytes[HEADER + 4..].chunks_exact(FACET).enumerate() {
+        let value = |i: usize| f32::from_le_bytes(facet[4 * i..4 * i + 4].try_into().unwrap());
+        // The stored normal is skipped, and recomputed when writing.
+        let vertex = |v: usize| [0, 1, 2].map(|i| value(3 + 3 * v + i) as f64);
+        let vertices = [vertex(0), vertex(1), vertex(2)];
+        if let Some(x) = vertices.iter().flatten().find(|x| !x.is_finite()) {
+            return Err(format!(
+                "byte {}: facet {} has a coordinate {}",
+                HEADER + 4 + FACET * k,
+                k + 1,
+                x
+            ));
+        }
+        merger.push(vertices);
+    }
+    Ok(merger.mesh)
+}
+
+/// Parses an ASCII STL file, with errors at line numbers.
+fn from_ascii(text: &str) -> Result<StlMesh, String> {
+    let mut tokens = text
+        .lines()
+        .enumerate()
+        .flat_map(|(k, line)| line.split_whitespace().map(move |token| (k + 1, token)))
+        .peekable();
+    let mut last_line = 1;
+    let mut next = |expected: &str| match tokens.next() {
+        Some((line, token)) => {
+            last_line = line;
+            Ok((line, token))
+        }
+        None => Err(format!(
+            "line {}: expected {}, found the end of the file",
+            last_line, expected
+        )),
+    };
+    let mut merger = Merger::default();
+
+    let (line, token) = next("solid")?;
+    if token != "solid" {
+        return Err(format!("line {}: expected solid, found {}", line, token));
+    }
+    // The name of the solid is the rest of its line.
+    let mut keyword = loop {
+        let (next_line, token) = next("facet or endsolid")?;
+        if next_line != line {
+            break token;
+        }
+    };
+    loop {
+        match keyword {
+            "endsolid" => break,
+            "facet" => (),
+            _ => {
+                return Err(format!(
+                    "line {}: expected facet or endsolid, found {}",
+                    last_line, keyword
+                ));
+            }
+        }
+        expect(&mut next, "normal")?;
+        for _ in 0..3 {
+            number(&mut next)?;
+        }
+        expect(&mut next, "outer")?;
+        expect(&mut next, "loop")?;
+        let mut facet = [[0.0; 3]; 3];
+        for vertex in &mut facet {
+            expect(&mut next, "vertex")?;
+            for x in vertex.iter_mut() {
+                *x = number(&mut next)?;
+            }
+        }
+        expect(&mut next, "endloop")?;
+        expect(&mut next, "endfacet")?;
+        merger.push(facet);
+        keyword = next("facet or endsolid")?.1;
+    }
+    Ok(merger.mesh)
+}
+
+/// Takes the next token, which must be the `word`.
+fn expect<'a>(
+    next: &mut impl FnMut(&str) -> Result<(usize, &'a str), String>,
+    word: &str,
+) -> Result<(), String> {
+    let (line, token) = next(word)?;
+    match token == word {
+        true => Ok(()),
+        false => Err(format!("line {}: expected {}, found {}", line, word, token)),
+    }
+}
+
+/// Takes the next token, which must be a finite number.
+fn number<'a>(
+    next: &mut impl FnMut(&str) -> Result<(usize, &'a str), String>,
+) -> Result<f64, String> {
+    let (line, token) = next("a number")?;
+    match token.parse::<f64>() {
+        Ok(x) if x.is_finite() => Ok(x),
+        _ => Err(format!("line {}: expected a number, found {}", line, token)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("clap1_{}_{}", std::process::id(), name))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// A tetrahedron with outward normals, and vertices in the order they are read.
+    fn tetrahedron() -> StlMesh {
+        StlMesh {
+            vertices: vec![[0.0; 3], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 0.5]],
+            facets: vec![[0, 1, 2], [0, 2, 3], [2, 1, 3], [0, 3, 1]],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mesh = tetrahedron();
+        let (ascii, binary) = (
+            temp_path("stl_round_trip_ascii.stl"),
+            temp_path("stl_round_trip_binary.stl"),
+        );
+        mesh.write_ascii(&ascii).unwrap();
+        mesh.write_binary(&binary).unwrap();
+        assert_eq!(StlMesh::read(&ascii).unwrap(), mesh);
+        assert_eq!(StlMesh::read(&binary).unwrap(), mesh);
+        assert_eq!(fs::read(&binary).unwrap().len(), 84 + 4 * 50);
+        assert_eq!(mesh.normals()[0], [0.0, 0.0, -1.0]);
+
+        // A binary file with a header that starts with solid.
+        let mut bytes = fs::read(&binary).unwrap();
+        bytes[..6].copy_from_slice(b"solid ");
+        assert_eq!(StlMesh::from_bytes(&bytes).unwrap(), mesh);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mesh = tetrahedron();
+        let binary = temp_path("stl_errors.stl");
+        mesh.write_binary(&binary).unwrap();
+        let bytes = fs::read(&binary).unwrap();
+        let error = StlMesh::from_bytes(&bytes[..200]).unwrap_err();
+        assert_eq!(
+            error,
+            "byte 200: 4 facets need 284 bytes, but the file has 200"
+        );
+
+        let text = "solid x\n facet normal 0 0 1\n  outer loop\n   vertex 0 0 0\n   vertex 1 0 x\n";
+        let error = StlMesh::from_bytes(text.as_bytes()).unwrap_err();
+        assert_eq!(error, "line 5: expected a number, found x");
+        let error = StlMesh::from_bytes(b"solid x\n facet normal 0 0 1\n").unwrap_err();
+        assert_eq!(error, "line 2: expected outer, found the end of the file");
+        let error = StlMesh::from_bytes(b"solid\nfacets\n").unwrap_err();
+        assert_eq!(error, "line 2: expected facet or endsolid, found facets");
+    }
+}
@@ -0,0 +1,171 @@
+//! Triangulated surfaces of the interfaces in a segmentation.
+//!
+//! Every voxel face between two different labels, or between a label and void,
+//! is split into two triangles.  Void is outside the segmentation, or a removed
+//! label.  A face between two labels is made once, with its normal pointing from
+//! the smaller label to the larger one, and a face on void has its normal
+//! pointing into the void.
+
+use crate::mesh::TriMesh;
+use crate::voxels::Voxels;
+
+impl TriMesh {
+    /// Builds the surface mesh of the interfaces of a segmentation, in voxel units.
+    ///
+    /// Nodes are the voxel corners used by a face, numbered in the order of the
+    /// grid of voxel corners, with `x` changing fastest, then `y`, then `z`.
+    ///
+    /// # Arguments
+    ///
+    /// * `voxels` - The segmentation.
+    /// * `remove` - The labels of voxels that are treated as void.
+    pub fn from_voxels(voxels: &Voxels, remove: &[u8]) -> Self {
+        let nel = voxels.nel();
+        let [nelx, nely, _] = nel;
+        let corner = |p: [usize; 3]| p[0] + (nelx + 1) * (p[1] + (nely + 1) * p[2]);
+        let label_at = |voxel: [usize; 3]| {
+            let label = voxels.label(voxel);
+            (!remove.contains(&label)).then_some(label)
+        };
+
+        let mut faces = Vec::new();
+        let mut element_blocks = Vec::new();
+        let mut neighbor_blocks = Vec::new();
+        for ((z, y, x), &label) in voxels.get_data().indexed_iter() {
+            if remove.contains(&label) {
+                continue;
+            }
+            let voxel = [x, y, z];
+            for axis in 0..3 {
+                for positive in [false, true] {
+                    let mut neighbor = voxel;
+                    let inside = if positive {
+                        neighbor[axis] += 1;
+                        neighbor[axis] < nel[axis]
+                    } else {
+                        neighbor[axis] = neighbor[axis].wrapping_sub(1);
+                        voxel[axis] > 0
+                    };
+                    let neighbor_label = if inside { label_at(neighbor) } else { None };
+                    if neighbor_label.is_some_and(|n| n <= label) {
+                        continue;
+                    }
+                    // The face corners, counter-clockwise about the axis, which is
+                    // the normal direction of the positive face.
+                    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+                    let mut base = voxel;
+                    if positive {
+                        base[axis] += 1;
+                    }
+                    let mut quad = [base; 4];
+                    quad[1][u] += 1;
+                    quad[2][u] += 1;
+                    quad[2][v] += 1;
+                    quad[3][v] += 1;
+                    if !positive {
+                        quad.swap(1, 3);
+                    }
+                    let quad = quad.map(corner);
+                    for triangle in [[quad[0], quad[1], quad[2]], [quad[0], quad[2], quad[3]]] {
+                        faces.push(triangle);
+                        element_blocks.push(label);
+                        neighbor_blocks.push(neighbor_label);
+                    }
+                }
+            }
+        }
+
+        // Number only the corners used by a face, in the order of the grid.
+        let count = (nel[0] + 1) * (nel[1] + 1) * (nel[2] + 1);
+        let mut used = vec![false; count];
+        for &corner in faces.iter().flatten() {
+            used[corner] = true;
+        }
+        let mut index_of = vec![0; count];
+        let mut nodes = Vec::new();
+        for corner in 0..count {
+            if used[corner] {
+                index_of[corner] = nodes.len();
+                let x = corner % (nelx + 1);
+                let y = (corner / (nelx + 1)) % (nely + 1);
+                let z = corner / ((nelx + 1) * (nely + 1));
+                nodes.push([x as f64, y as f64, z as f64]);
+            }
+        }
+        let elements = faces
+            .iter()
+            .map(|face| face.map(|corner| index_of[corner]))
+            .collect();
+
+        Self {
+            nodes,
+            elements,
+            element_blocks,
+            neighbor_blocks,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxels::VoxelData;
+
+    /// Returns the volume enclosed by the triangles, positive for outward normals.
+    fn enclosed_volume(mesh: &TriMesh, triangles: impl Iterator<Item = (usize, bool)>) -> f64 {
+        triangles
+            .map(|(k, flip)| {
+                let [a, b, c] = mesh.elements[k].map(|n| mesh.nodes[n]);
+                let volume = (a[0] * (b[1] * c[2] - b[2] * c[1])
+                    + a[1] * (b[2] * c[0] - b[0] * c[2])
+                    + a[2] * (b[0] * c[1] - b[1] * c[0]))
+                    / 6.0;
+                if flip { -volume } else { volume }
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_from_voxels() {
+        // A ball of label 2 in a box of label 1, with a void corner of label 0.
+        let (nx, ny, nz) = (7, 6, 5);
+        let data = VoxelData::from_shape_fn((nz, ny, nx), |(z, y, x)| {
+            let d = [x as f64 - 3.0, y as f64 - 2.5, z as f64 - 2.0];
+            if x == 0 && y == 0 {
+                0
+            } else if d.iter().map(|d| d * d).sum::<f64>() < 4.0 {
+                2
+            } else {
+                1
+            }
+        });
+        let voxels = Voxels::from(data);
+        let mesh = TriMesh::from_voxels(&voxels, &[0]);
+        let count = |label| voxels.get_data().iter().filter(|&&l| l == label).count();
+
+        // The ball is closed, and the triangles of both labels enclose each one.
+        let ball = (0..mesh.elements.len()).filter_map(|k| match mesh.element_blocks[k] {
+            2 => Some((k, false)),
+            _ => (mesh.neighbor_blocks[k] == Some(2)).then_some((k, true)),
+        });
+        assert!((enclosed_volume(&mesh, ball) - count(2) as f64).abs() < 1e-12);
+        let boundary = (0..mesh.elements.len())
+            .filter(|&k| mesh.neighbor_blocks[k].is_none())
+            .map(|k| (k, false));
+        let total = (count(1) + count(2)) as f64;
+        assert!((enclosed_volume(&mesh, boundary) - total).abs() < 1e-12);
+
+        // Faces between labels point from the smaller label to the larger one.
+        for (block, neighbor) in mesh.element_blocks.iter().zip(&mesh.neighbor_blocks) {
+            assert!(neighbor.is_none_or(|n| n > *block));
+        }
+        // The ball is inside the box, so all of its faces belong to the box.
+        assert_eq!(mesh.blocks(), vec![1]);
+
+        // Without removal, the void corner is a label of its own.
+        let mesh = TriMesh::from_voxels(&voxels, &[]);
+        assert_eq!(mesh.blocks(), vec![0, 1]);
+        assert!(mesh.neighbor_blocks.contains(&Some(1)));
+    }
+}
@@ -0,0 +1,248 @@
+//! Voxel segmentations, read from and written to `.npy` or `.spn` files.
+//!
+//! The voxel data is indexed as `[[z, y, x]]`, which is the row-major order of a
+//! `.npy` file with shape `(nelz, nely, nelx)`, and the line order of a `.spn`
+//! file, with `x` changing fastest.
+
+use crate::error::Error;
+use crate::io::{FileFormat, SegmentationFormat};
+use crate::npy::{read_npy_u8, write_npy_u8};
+use ndarray::Array3;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+
+/// The voxel data type.
+pub type VoxelData = Array3<u8>;
+
+/// A segmentation, with one material label per voxel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Voxels {
+    data: VoxelData,
+}
+
+impl From<VoxelData> for Voxels {
+    /// Constructs a segmentation from voxel data indexed as `[[z, y, x]]`.
+    fn from(data: VoxelData) -> Self {
+        Self { data }
+    }
+}
+
+impl Voxels {
+    /// Reads a segmentation from a `.npy` or `.spn` file, chosen by its extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - The `.npy` or `.spn` file path.
+    /// * `nel` - The number of voxels in the x, y and z directions, which are
+    ///   required for a `.spn` file, and must match the shape of a `.npy` file.
+    pub fn from_file(file_path: &str, nel: [Option<usize>; 3]) -> Result<Self, Error> {
+        match SegmentationFormat::from_input(file_path, nel)? {
+            SegmentationFormat::Npy => {
+                let voxels = Self::from_npy(file_path)?;
+                let shape = voxels.nel();
+                for (axis, (given, found)) in ["x", "y", "z"].iter().zip(nel.iter().zip(shape)) {
+                    if let Some(given) = given
+                        && *given != found
+                    {
+                        return Err(Error::Failed(format!(
+                            "{} has {} voxels in the {}-direction, but nel{} is {}",
+                            file_path, found, axis, axis, given
+                        )));
+                    }
+                }
+                Ok(voxels)
+            }
+            // Each number of voxels is given, as `from_input` checks for `.spn`.
+            SegmentationFormat::Spn => Ok(Self::from_spn(
+                file_path,
+                nel.map(Option::unwrap_or_default),
+            )?),
+        }
+    }
+
+    /// Reads a segmentation from a `.npy` file of shape `(nelz, nely, nelx)`.
+    pub fn from_npy(file_path: &str) -> Result<Self, String> {
+        Ok(Self {
+            data: read_npy_u8(file_path)?,
+        })
+    }
+
+    /// Reads a segmentation from a `.spn` file, which has one label per line and
+    /// carries no shape, so the number of voxels in each direction is required.
+    pub fn from_spn(file_path: &str, nel: [usize; 3]) -> Result<Self, String> {
+        let contents = fs::read_to_string(file_path)
+            .map_err(|e| format!("Failed to read {}: {}", file_path, e))?;
+        let values = contents
+            .split_whitespace()
+            .enumerate()
+            .map(|(k, value)| {
+                value.parse::<u8>().map_err(|e| {
+                    format!("{}: invalid voxel {} '{}': {}", file_path, k + 1, value, e)
+                })
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+        let [nelx, nely, nelz] = nel;
+        if values.len() != nelx * nely * nelz {
+            return Err(format!(
+                "{}: found {} voxels, but nelx * nely * nelz = {} * {} * {} = {}",
+                file_path,
+                values.len(),
+                nelx,
+                nely,
+                nelz,
+                nelx * nely * nelz
+            ));
+        }
+        let data =
+            VoxelData::from_shape_vec((nelz, nely, nelx), values).map_err(|e| e.to_string())?;
+        Ok(Self { data })
+    }
+
+    /// Writes the segmentation to a `.npy` or `.spn` file, chosen by its extension.
+    pub fn write_file(&self, file_path: &str) -> Result<(), Error> {
+        match SegmentationFormat::from_path(file_path)? {
+            SegmentationFormat::Npy => self.write_npy(file_path),
+            SegmentationFormat::Spn => self.write_spn(file_path),
+        }
+        .map_err(Error::from)
+    }
+
+    /// Writes the segmentation to a `.npy` file of shape `(nelz, nely, nelx)`.
+    pub fn write_npy(&self, file_path: &str) -> Result<(), String> {
+        write_npy_u8(file_path, &self.data)
+    }
+
+    /// Writes the segmentation to a `.spn` file, with one label per line.
+    pub fn write_spn(&self, file_path: &str) -> Result<(), String> {
+        let file = File::create(file_path)
+            .map_err(|e| format!("Failed to create {}: {}", file_path, e))?;
+        let mut writer = BufWriter::new(file);
+        self.data
+            .iter()
+            .try_for_each(|label| writeln!(writer, "{}", label))
+            .and_then(|_| writer.flush())
+            .map_err(|e| format!("Failed to write {}: {}", file_path, e))
+    }
+
+    /// Returns a reference to the voxel data.
+    pub fn get_data(&self) -> &VoxelData {
+        &self.data
+    }
+
+    /// Returns a mutable reference to the voxel data.
+    pub fn get_data_mut(&mut self) -> &mut VoxelData {
+        &mut self.data
+    }
+
+    /// Returns the number of voxels in the x, y and z directions.
+    pub fn nel(&self) -> [usize; 3] {
+        let (nelz, nely, nelx) = self.data.dim();
+        [nelx, nely, nelz]
+    }
+
+    /// Returns the label of the voxel at `[x, y, z]`.
+    pub fn label(&self, [x, y, z]: [usize; 3]) -> u8 {
+        self.data[[z, y, x]]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("clap1_{}_{}", std::process::id(), name))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn segmentation() -> Voxels {
+        Voxels::from(VoxelData::from_shape_fn((3, 5, 4), |(z, y, x)| {
+            (x * y + z) as u8 % 3
+        }))
+    }
+
+    #[test]
+    fn test_npy_to_spn_round_trip() {
+        let voxels = segmentation();
+        let (npy, spn) = (
+            temp_path("voxels_npy_to_spn_round_trip.npy"),
+            temp_path("voxels_npy_to_spn_round_trip.spn"),
+        );
+        voxels.write_file(&npy).unwrap();
+
+        let read = Voxels::from_file(&npy, [None, None, None]).unwrap();
+        assert_eq!(read.nel(), [4, 5, 3]);
+        read.write_file(&spn).unwrap();
+        let lines = fs::read_to_string(&spn).unwrap();
+        assert_eq!(lines.lines().count(), 60);
+        // The x-direction changes fastest.
+        assert_eq!(
+            lines.lines().nth(1),
+            Some(voxels.label([1, 0, 0]).to_string().as_str())
+        );
+
+        assert_eq!(
+            Voxels::from_file(&spn, [Some(4), Some(5), Some(3)]).unwrap(),
+            voxels
+        );
+    }
+
+    #[test]
+    fn test_spn_to_npy_round_trip() {
+        let voxels = segmentation();
+        let (spn, npy) = (
+            temp_path("voxels_spn_to_npy_round_trip.spn"),
+            temp_path("voxels_spn_to_npy_round_trip.npy"),
+        );
+        voxels.write_file(&spn).unwrap();
+
+        let read = Voxels::from_file(&spn, [Some(4), Some(5), Some(3)]).unwrap();
+        read.write_file(&npy).unwrap();
+        let read = Voxels::from_file(&npy, [Some(4), None, Some(3)]).unwrap();
+        assert_eq!(read, voxels);
+        read.write_file(&spn).unwrap();
+        assert_eq!(
+            Voxels::from_file(&spn, [Some(4), Some(5), Some(3)]).unwrap(),
+            voxels
+        );
+    }
+
+    #[test]
+    fn test_segmentation_errors() {
+        let voxels = segmentation();
+        let (npy, spn) = (
+            temp_path("voxels_segmentation_errors.npy"),
+            temp_path("voxels_segmentation_errors.spn"),
+        );
+        voxels.write_file(&npy).unwrap();
+        voxels.write_file(&spn).unwrap();
+
+        let error = Voxels::from_file(&npy, [Some(4), Some(3), None]).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("5 voxels in the y-direction, but nely is 3")
+        );
+        // The file is read before its shape is checked, as for a `.spn` file.
+        assert_eq!(error.exit_code(), 1);
+        let error = Voxels::from_file(&spn, [Some(4), Some(5), None]).unwrap_err();
+        assert_eq!(
+            error,
+            Error::Nel {
+                file_path: spn.clone()
+            }
+        );
+        let error = Voxels::from_file(&spn, [Some(4), Some(5), Some(4)]).unwrap_err();
+        assert!(error.to_string().contains("found 60 voxels"));
+        assert!(
+            Voxels::from_file(&temp_path("voxels_segmentation_errors.txt"), [None; 3]).is_err()
+        );
+        assert!(
+            voxels
+                .write_file(&temp_path("voxels_segmentation_errors.vtk"))
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! Export of meshes to the legacy VTK format.
+//!
+//! A mesh is written as an ASCII unstructured grid, with the points, the cells
+//! and their types, and the element block of each cell as integer cell data
+//! named `block`.
+
+use crate::mesh::{HexMesh, TriMesh};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// The VTK cell type of an eight-node hexahedron.
+const VTK_HEXAHEDRON: u8 = 12;
+
+/// The VTK cell type of a three-node triangle.
+const VTK_TRIANGLE: u8 = 5;
+
+impl HexMesh {
+    /// Writes the mesh to a legacy `.vtk` file, with the elements in their order.
+    pub fn write_vtk(&self, file_path: &str) -> Result<(), String> {
+        write_vtk(
+            file_path,
+            &self.nodes,
+            &self.elements,
+            &self.element_blocks,
+            VTK_HEXAHEDRON,
+        )
+    }
+}
+
+impl TriMesh {
+    /// Writes the mesh to a legacy `.vtk` file, with the elements in their order.
+    ///
+    /// The blocks in front of the elements are not written.
+    pub fn write_vtk(&self, file_path: &str) -> Result<(), String> {
+        write_vtk(
+            file_path,
+            &self.nodes,
+            &self.elements,
+            &self.element_blocks,
+            VTK_TRIANGLE,
+        )
+    }
+}
+
+/// Writes nodes and elements of one cell type to a legacy `.vtk` file.
+fn write_vtk<const N: usize>(
+    file_path: &str,
+    nodes: &[[f64; 3]],
+    elements: &[[usize; N]],
+    element_blocks: &[u8],
+    cell_type: u8,
+) -> Result<(), String> {
+    let file =
+        File::create(file_path).map_err(|e| format!("Failed to create {}: {}", file_path, e))?;
+    let mut writer = BufWriter::new(file);
+    write_vtk_sections(&mut writer, nodes, elements, element_blocks, cell_type)
+        .and_then(|_| writer.flush())
+        .map_err(|e| format!("Failed to write {}: {}", file_path, e))
+}
+
+/// Writes the sections of the `.vtk` file.
+fn write_vtk_sections<W: Write, const N: usize>(
+    writer: &mut W,
+    nodes: &[[f64; 3]],
+    elements: &[[usize; N]],
+    element_blocks: &[u8],
+    cell_type: u8,
+) -> std::io::Result<()> {
+    writeln!(writer, "# vtk DataFile Version 3.0")?;
+    writeln!(
+        writer,
+        "{} version {}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(writer, "ASCII")?;
+    writeln!(writer, "DATASET UNSTRUCTURED_GRID")?;
+
+    writeln!(writer, "POINTS {} double", nodes.len())?;
+    for [x, y, z] in nodes {
+        writeln!(writer, "{} {} {}", x, y, z)?;
+    }
+
+    writeln!(
+        writer,
+        "CELLS {} {}",
+        elements.len(),
+        elements.len() * (N + 1)
+    )?;
+    for element in elements {
+        write!(writer, "{}", N)?;
+        for node in element {
+            write!(writer, " {}", node)?;
+        }
+        writeln!(writer)?;
+    }
+    writeln!(writer, "CELL_TYPES {}", elements.len())?;
+    for _ in elements {
+        writeln!(writer, "{}", cell_type)?;
+    }
+
+    writeln!(writer, "CELL_DATA {}", elements.len())?;
+    writeln!(writer, "SCALARS block int 1")?;
+    writeln!(writer, "LOOKUP_TABLE default")?;
+    for block in element_blocks {
+        writeln!(writer, "{}", block)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxels::{VoxelData, Voxels};
+    use std::fs;
+
+    #[test]
+    fn test_write_vtk() {
+        let data = VoxelData::from_shape_fn((1, 1, 2), |(_, _, x)| x as u8 + 1);
+        let mesh = HexMesh::from_voxels(&Voxels::from(data), &[]);
+        let output =
+            std::env::temp_dir().join(format!("clap1_{}_vtk_write_vtk.vtk", std::process::id()));
+        let output = output.to_string_lossy();
+        mesh.write_vtk(&output).unwrap();
+
+        let contents = fs::read_to_string(output.as_ref()).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines[4], "POINTS 12 double");
+        assert_eq!(lines[5], "0 0 0");
+        assert_eq!(lines[17], "CELLS 2 18");
+        assert_eq!(lines[18], "8 0 1 4 3 6 7 10 9");
+        assert_eq!(lines[20..23], ["CELL_TYPES 2", "12", "12"]);
+        assert_eq!(
+            lines[23..],
+            [
+                "CELL_DATA 2",
+                "SCALARS block int 1",
+                "LOOKUP_TABLE default",
+                "1",
+                "2"
+            ]
+        );
+    }
+}
@@ -1,114 +1,26 @@
-use std::fmt;
-use std::fs::File;
-use std::io::{self, BufRead};
-use std::path::Path;
+use clap1::stl::StlMesh;
+use std::env;
+use std::io;
 
 const NSD: usize = 3; // For example, 3 dimensions (x, y, z)
 
-#[derive(Debug)]
-struct Vertex {
-    x: f64,
-    y: f64,
-    z: f64,
-}
-
-impl fmt::Display for Vertex {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "vertex {:.6e} {:.6e} {:.6e}\n", self.x, self.y, self.z)
-    }
-}
-
-#[derive(Debug)]
-struct OuterLoop {
-    vertices: Vec<Vertex>,
-}
-
-impl fmt::Display for OuterLoop {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let vertices_str: Vec<String> = self.vertices.iter().map(|v| v.to_string()).collect();
-        write!(f, "\nouter loop\n{}\nendloop", vertices_str.join(""))
-    }
-}
-
-#[derive(Debug)]
-struct Facet {
-    normal: Vertex,
-    outer_loop: OuterLoop,
-}
-
-impl fmt::Display for Facet {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "facet normal {}\n    {}", self.normal, self.outer_loop)
-    }
-}
-
-fn read_stl<P: AsRef<Path>>(path: P) -> io::Result<Vec<Facet>> {
-    let file = File::open(path)?;
-    let reader = io::BufReader::new(file);
-    let mut facets = Vec::new();
-    let mut current_facet: Option<Facet> = None;
-
-    for line in reader.lines() {
-        let line = line?;
-        let trimmed = line.trim();
-
-        if trimmed.starts_with("facet normal") {
-            // Parse the normal vector
-            // example:
-            // facet normal 0.000000e+00 -1.000000e+00 0.000000e+00
-            let parts: Vec<&str> = trimmed.split_whitespace().collect();
-            let normal = Vertex {
-                x: parts[2].parse().unwrap(), // 2 -> x-index
-                y: parts[3].parse().unwrap(), // 3 -> y-index
-                z: parts[4].parse().unwrap(), // 4 -> z-index
-            };
-            current_facet = Some(Facet {
-                normal,
-                outer_loop: OuterLoop {
-                    vertices: Vec::new(),
-                },
-            });
-        } else if trimmed.starts_with("vertex") {
-            if let Some(ref mut facet) = current_facet {
-                // Parse the vertex
-                // example
-                // vertex 0.000000e+00 0.000000e+00 1.000000e+00
-                let parts: Vec<&str> = trimmed.split_whitespace().collect();
-                let vertex = Vertex {
-                    x: parts[1].parse().unwrap(), // 1 -> x-index
-                    y: parts[2].parse().unwrap(), // 2 -> y-index
-                    z: parts[3].parse().unwrap(), // 3 -> z-index
-                };
-                facet.outer_loop.vertices.push(vertex);
-            }
-        } else if trimmed == "endfacet" {
-            if let Some(facet) = current_facet.take() {
-                facets.push(facet);
-            }
-        }
-    }
-
-    Ok(facets)
-}
-
-fn main() -> io::Result<()> {
+#[allow(clippy::vec_init_then_push)]
+fn main() -> Result<(), String> {
     println!("Hello, world!");
 
-    let vertex = Vertex {
-        x: 1.0,
-        y: 0.0,
-        z: 1.0,
-    };
-
-    println!("{}", vertex);
-
-
-    let facets = read_stl("/Users/chovey/autotwin/automesh/tests/input/single.stl")?;
-
-    for facet in facets {
-        // println!("{:?}", facet);
-        println!("{}", facet);
-    }
+    // example:
+    // cargo run -- ../../autotwin/automesh/tests/input/single.stl
+    let path = env::args()
+        .nth(1)
+        .ok_or("Usage: iter <FILE.stl>".to_string())?;
+    let mesh = StlMesh::read(&path)?;
+    println!(
+        "{} facets and {} vertices",
+        mesh.facets.len(),
+        mesh.vertices.len()
+    );
+    mesh.write_ascii_to(&mut io::stdout())
+        .map_err(|e| e.to_string())?;
 
     let aa: Vec<f64> = vec![1.0, 2.0, 3.0];
     println!("aa {:?}", aa);
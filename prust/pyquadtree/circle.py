@@ -0,0 +1,48 @@
+"""Refines a quadtree for points on a unit circle, and plots the leaves and the
+dual mesh, with the Rust library called directly through pyquadtree.
+
+Build and install the module first, with `maturin develop` in this directory.
+"""
+
+import matplotlib.pyplot as plt
+from matplotlib.collections import LineCollection, PolyCollection
+import numpy as np
+
+import pyquadtree as pq
+
+angles = np.linspace(0.0, 2.0 * np.pi, 100, endpoint=False)
+points = np.column_stack((np.cos(angles), np.sin(angles)))
+
+# The closed boundary keeps the point at (1, 0) on the right edge of the root.
+tree = pq.Quadtree((-1.0, -1.0), 2.0, 2.0, level_max=5, boundary="closed")
+inserted = tree.insert(points)
+print(f"Inserted {inserted.sum()} of {len(points)} points")
+
+tree.refine()
+tree.weak_balance()
+print(tree)
+
+leaves = tree.leaves()
+x, y, w, h = leaves.T
+outlines = np.stack(
+    [
+        np.column_stack((x, y)),
+        np.column_stack((x + w, y)),
+        np.column_stack((x + w, y + h)),
+        np.column_stack((x, y + h)),
+    ],
+    axis=1,
+)
+
+fig, ax = plt.subplots(figsize=(8, 8))
+ax.add_collection(
+    PolyCollection(outlines, facecolors="none", edgecolors="black", linewidths=0.5)
+)
+ax.add_collection(LineCollection(tree.dual_edges(), colors="tab:blue", linewidths=0.75))
+vertices = tree.dual_vertices()
+ax.plot(vertices[:, 0], vertices[:, 1], ".", color="tab:blue", markersize=2)
+ax.plot(points[:, 0], points[:, 1], ".", color="tab:red", markersize=3)
+ax.set_aspect("equal")
+ax.set_xlim(-1.05, 1.05)
+ax.set_ylim(-1.05, 1.05)
+plt.show()
@@ -0,0 +1,247 @@
+use numpy::ndarray::{Array1, Array2, Array3};
+use numpy::{IntoPyArray, PyArray1, PyArray2, PyArray3, PyReadonlyArray2};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use quadtree::{BoundaryPolicy, Node, Point, Quadtree, Rectangle, RefinementCriterion};
+
+/// Returns the (n, 4) array of leaves as rows of `x, y, width, height`.
+fn leaf_array(tree: &Quadtree) -> Array2<f64> {
+    let leaves = tree.get_all_leaves();
+    let values = leaves
+        .iter()
+        .flat_map(|leaf| {
+            let b = &leaf.boundary;
+            [b.origin.x, b.origin.y, b.width, b.height]
+        })
+        .collect();
+    Array2::from_shape_vec((leaves.len(), 4), values).expect("four values per leaf")
+}
+
+/// Returns the (n, 2) array of the points held by the leaves, in leaf order.
+fn point_array(tree: &Quadtree) -> Array2<f64> {
+    let values: Vec<f64> = tree
+        .get_all_leaves()
+        .iter()
+        .flat_map(|leaf| match &leaf.node {
+            Node::Leaf { points } => points.iter().flat_map(|p| [p.x, p.y]).collect(),
+            Node::Children { .. } => Vec::new(),
+        })
+        .collect();
+    Array2::from_shape_vec((values.len() / 2, 2), values).expect("two values per point")
+}
+
+/// Returns the (n, 2) array of dual vertices, the leaf centers.
+fn dual_vertex_array(tree: &Quadtree) -> Array2<f64> {
+    let vertices = tree.dual_vertices();
+    let values = vertices.iter().flat_map(|p| [p.x, p.y]).collect();
+    Array2::from_shape_vec((vertices.len(), 2), values).expect("two values per vertex")
+}
+
+/// Returns the (m, 2, 2) array of dual edges, as pairs of end points.
+fn dual_edge_array(tree: &Quadtree) -> Array3<f64> {
+    let edges = tree.dual_edges();
+    let values = edges
+        .iter()
+        .flat_map(|(p1, p2)| [p1.x, p1.y, p2.x, p2.y])
+        .collect();
+    Array3::from_shape_vec((edges.len(), 2, 2), values).expect("four values per edge")
+}
+
+/// A quadtree over a rectangular domain, wrapping the Rust `quadtree::Quadtree`.
+#[pyclass(name = "Quadtree")]
+struct PyQuadtree {
+    tree: Quadtree,
+}
+
+#[pymethods]
+impl PyQuadtree {
+    /// Creates a single leaf quadtree over the rectangle with lower left corner
+    /// `origin = (x, y)`, and the given width and height.
+    ///
+    /// The `boundary` policy is one of "half_open", "closed", or "clamp", where
+    /// "clamp" moves points within `tolerance` of the root onto its edges.
+    #[new]
+    #[pyo3(signature = (origin, width, height, level_max, boundary = "half_open", tolerance = 0.0))]
+    fn new(
+        origin: (f64, f64),
+        width: f64,
+        height: f64,
+        level_max: usize,
+        boundary: &str,
+        tolerance: f64,
+    ) -> PyResult<Self> {
+        if !(width > 0.0 && height > 0.0) {
+            return Err(PyValueError::new_err("width and height must be positive"));
+        }
+        let boundary_policy = match boundary {
+            "half_open" => BoundaryPolicy::HalfOpen,
+            "closed" => BoundaryPolicy::ClosedAtRoot,
+            "clamp" => BoundaryPolicy::Clamp { tolerance },
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "unknown boundary policy '{}', use half_open, closed, or clamp",
+                    boundary
+                )))
+            }
+        };
+        let mut tree = Quadtree::new(
+            Rectangle {
+                origin: Point {
+                    x: origin.0,
+                    y: origin.1,
+                },
+                width,
+                height,
+            },
+            level_max,
+        );
+        tree.boundary_policy = boundary_policy;
+        Ok(Self { tree })
+    }
+
+    /// Creates a quadtree from the YAML written by `to_yaml`, by the `quadtree`
+    /// command line, or by `Quadtree::visualize`.
+    #[staticmethod]
+    fn from_yaml(yaml: &str) -> PyResult<Self> {
+        Quadtree::from_yaml(yaml)
+            .map(|tree| Self { tree })
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Inserts the rows of an (n, 2) array of points.
+    ///
+    /// Returns an (n,) boolean array that is `True` where the point was inserted.
+    /// Points are rejected if they are out of bounds or duplicates.
+    fn insert<'py>(
+        &mut self,
+        py: Python<'py>,
+        points: PyReadonlyArray2<'py, f64>,
+    ) -> PyResult<Bound<'py, PyArray1<bool>>> {
+        let points = points.as_array();
+        if points.ncols() != 2 {
+            return Err(PyValueError::new_err("points must have shape (n, 2)"));
+        }
+        let inserted: Array1<bool> = points
+            .rows()
+            .into_iter()
+            .map(|row| {
+                self.tree
+                    .insert(Point {
+                        x: row[0],
+                        y: row[1],
+                    })
+                    .is_ok()
+            })
+            .collect();
+        Ok(inserted.into_pyarray(py))
+    }
+
+    /// Subdivides the leaves that hold points, up to `level_max`.
+    ///
+    /// With `max_points`, only the leaves holding more than `max_points` points
+    /// are subdivided.
+    #[pyo3(signature = (max_points = None))]
+    fn refine(&mut self, max_points: Option<usize>) {
+        match max_points {
+            Some(count) => self.tree.refine_by(RefinementCriterion::MaxPoints(count)),
+            None => self.tree.refine_by(RefinementCriterion::AnyPoint),
+        }
+    }
+
+    /// Subdivides leaves until edge-adjacent leaves differ by at most one level.
+    fn weak_balance(&mut self) {
+        self.tree.weak_balance();
+    }
+
+    /// Returns the (n, 4) array of leaves as rows of `x, y, width, height`.
+    fn leaves<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray2<f64>> {
+        leaf_array(&self.tree).into_pyarray(py)
+    }
+
+    /// Returns the (n, 2) array of the inserted points, in the order of `leaves`.
+    fn points<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray2<f64>> {
+        point_array(&self.tree).into_pyarray(py)
+    }
+
+    /// Returns the (n,) array of leaf levels, in the order of `leaves`.
+    fn levels<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<usize>> {
+        let levels: Array1<usize> = self
+            .tree
+            .get_all_leaves()
+            .iter()
+            .map(|leaf| leaf.level)
+            .collect();
+        levels.into_pyarray(py)
+    }
+
+    /// Returns the (n, 2) array of dual vertices, the leaf centers.
+    fn dual_vertices<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray2<f64>> {
+        dual_vertex_array(&self.tree).into_pyarray(py)
+    }
+
+    /// Returns the (m, 2, 2) array of dual edges, as pairs of end points.
+    fn dual_edges<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray3<f64>> {
+        dual_edge_array(&self.tree).into_pyarray(py)
+    }
+
+    /// Returns the quadtree serialized as YAML, as written by `Quadtree::to_yaml`.
+    fn to_yaml(&self) -> PyResult<String> {
+        self.tree
+            .to_yaml()
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    fn __len__(&self) -> usize {
+        self.tree.get_all_leaves().len()
+    }
+
+    fn __repr__(&self) -> String {
+        let b = &self.tree.boundary;
+        format!(
+            "Quadtree(origin=({}, {}), width={}, height={}, level_max={}, leaves={})",
+            b.origin.x,
+            b.origin.y,
+            b.width,
+            b.height,
+            self.tree.level_max,
+            self.__len__()
+        )
+    }
+}
+
+/// A Python module implemented in Rust. The name of this function must match
+/// the `lib.name` setting in the `Cargo.toml`, else Python will not be able to
+/// import the module.
+#[pymodule]
+fn pyquadtree(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyQuadtree>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arrays() {
+        // The tree of the README example, refined at two opposite corners.
+        let mut tree = Quadtree::new(
+            Rectangle {
+                origin: Point { x: 0.0, y: 0.0 },
+                width: 1.0,
+                height: 1.0,
+            },
+            3,
+        );
+        tree.boundary_policy = BoundaryPolicy::ClosedAtRoot;
+        tree.insert(Point { x: 0.1, y: 0.2 }).unwrap();
+        tree.insert(Point { x: 1.0, y: 1.0 }).unwrap();
+        tree.refine();
+        tree.weak_balance();
+
+        assert_eq!(leaf_array(&tree).dim(), (16, 4));
+        assert_eq!(point_array(&tree).dim(), (2, 2));
+        assert_eq!(dual_vertex_array(&tree).dim(), (16, 2));
+        assert_eq!(dual_edge_array(&tree).dim(), (20, 2, 2));
+    }
+}
@@ -0,0 +1,57 @@
+"""Tests of the pyquadtree bindings.
+
+Build and install the module first, with `maturin develop` in the parent
+directory, then run `pytest tests`.
+"""
+
+import numpy as np
+import pytest
+
+import pyquadtree as pq
+
+
+def refined_tree():
+    """Returns the tree of the README, refined at two opposite corners."""
+    tree = pq.Quadtree((0.0, 0.0), 1.0, 1.0, level_max=3, boundary="closed")
+    inserted = tree.insert(np.array([[0.1, 0.2], [1.0, 1.0]]))
+    assert inserted.tolist() == [True, True]
+    tree.refine()
+    tree.weak_balance()
+    return tree
+
+
+def test_shapes():
+    tree = refined_tree()
+    assert len(tree) == 16
+    assert tree.leaves().shape == (16, 4)
+    assert tree.levels().shape == (16,)
+    assert tree.points().shape == (2, 2)
+    assert tree.dual_vertices().shape == (16, 2)
+    assert tree.dual_edges().shape == (20, 2, 2)
+
+    # The leaves tile the unit square.
+    leaves = tree.leaves()
+    assert np.isclose((leaves[:, 2] * leaves[:, 3]).sum(), 1.0)
+    assert tree.levels().max() == 3
+
+
+def test_insert():
+    tree = pq.Quadtree((0.0, 0.0), 1.0, 1.0, level_max=3)
+    points = np.array([[0.5, 0.5], [0.5, 0.5], [1.0, 0.5], [0.25, 0.75]])
+    # The duplicate, and the point on the right edge of the half-open root,
+    # are rejected.
+    assert tree.insert(points).tolist() == [True, False, False, True]
+
+    with pytest.raises(ValueError):
+        tree.insert(np.zeros((2, 3)))
+    with pytest.raises(ValueError):
+        pq.Quadtree((0.0, 0.0), 0.0, 1.0, level_max=3)
+    with pytest.raises(ValueError):
+        pq.Quadtree((0.0, 0.0), 1.0, 1.0, level_max=3, boundary="open")
+
+
+def test_from_yaml():
+    tree = refined_tree()
+    copy = pq.Quadtree.from_yaml(tree.to_yaml())
+    assert np.array_equal(copy.leaves(), tree.leaves())
+    assert np.array_equal(copy.points(), tree.points())
@@ -0,0 +1,241 @@
+//! Export of quadrilateral meshes to the Gmsh `.msh` version 4.1 ASCII format.
+//!
+//! The boundary edges are written as line elements on one curve per side of the
+//! root, in physical groups named `north`, `east`, `south` and `west`.  The
+//! elements are written on one surface per label, in physical groups named
+//! `label_<n>`, or on a single `domain` surface without labels.  Dual mesh
+//! transition elements, which repeat a node, are written as triangles.
+
+use crate::{Point, QuadMesh, Side};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// The Gmsh element type of a 2-node line.
+const GMSH_LINE: usize = 1;
+/// The Gmsh element type of a 3-node triangle.
+const GMSH_TRIANGLE: usize = 2;
+/// The Gmsh element type of a 4-node quadrilateral.
+const GMSH_QUADRANGLE: usize = 3;
+
+/// Returns the bounding box of the nodes, as `min_x min_y 0 max_x max_y 0`.
+fn bounding_box<'a>(nodes: impl Iterator<Item = &'a Point>) -> String {
+    let (mut min_x, mut min_y) = (f64::INFINITY, f64::INFINITY);
+    let (mut max_x, mut max_y) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for p in nodes {
+        min_x = min_x.min(p.x);
+        min_y = min_y.min(p.y);
+        max_x = max_x.max(p.x);
+        max_y = max_y.max(p.y);
+    }
+    format!("{} {} 0 {} {} 0", min_x, min_y, max_x, max_y)
+}
+
+impl QuadMesh {
+    /// Writes the mesh to a Gmsh `.msh` version 4.1 ASCII file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The output file path.
+    /// * `labels` - An optional label per element, such as a material of each leaf
+    ///   of a primal mesh, which are in the order of `get_all_leaves`.
+    pub fn write_msh(&self, path: &str, labels: Option<&[usize]>) -> Result<(), String> {
+        if let Some(labels) = labels {
+            if labels.len() != self.elements.len() {
+                return Err(format!(
+                    "Expected {} element labels, found {}",
+                    self.elements.len(),
+                    labels.len()
+                ));
+            }
+        }
+
+        // One curve per side that has boundary edges, tagged 1 to 4.
+        let curves: Vec<(usize, Side, Vec<[usize; 2]>)> = Side::ALL
+            .iter()
+            .enumerate()
+            .map(|(k, side)| {
+                let edges: Vec<[usize; 2]> = self
+                    .boundary
+                    .iter()
+                    .filter(|(s, _)| s == side)
+                    .map(|(_, edge)| *edge)
+                    .collect();
+                (k + 1, *side, edges)
+            })
+            .filter(|(_, _, edges)| !edges.is_empty())
+            .collect();
+
+        // One surface per distinct label, tagged from 1.
+        let mut surface_labels: Vec<usize> = labels.map(|l| l.to_vec()).unwrap_or_default();
+        surface_labels.sort_unstable();
+        surface_labels.dedup();
+
+        let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+        let mut writer = BufWriter::new(file);
+        self.write_msh_sections(&mut writer, &curves, labels, &surface_labels)
+            .and_then(|_| writer.flush())
+            .map_err(|e| format!("Failed to write {}: {}", path, e))
+    }
+
+    /// Writes the sections of the `.msh` file, with the boundary `curves` and the
+    /// distinct `surface_labels` already collected.
+    fn write_msh_sections<W: Write>(
+        &self,
+        writer: &mut W,
+        curves: &[(usize, Side, Vec<[usize; 2]>)],
+        labels: Option<&[usize]>,
+        surface_labels: &[usize],
+    ) -> std::io::Result<()> {
+        let surface_of = |element: usize| match labels {
+            Some(labels) => surface_labels.binary_search(&labels[element]).unwrap() + 1,
+            None => 1,
+        };
+        let surface_count = surface_labels.len().max(1);
+
+        writeln!(writer, "$MeshFormat\n4.1 0 8\n$EndMeshFormat")?;
+
+        writeln!(writer, "$PhysicalNames")?;
+        writeln!(writer, "{}", curves.len() + surface_count)?;
+        for (tag, side, _) in curves {
+            writeln!(writer, "1 {} \"{}\"", tag, side.name())?;
+        }
+        if labels.is_some() {
+            for (k, label) in surface_labels.iter().enumerate() {
+                writeln!(writer, "2 {} \"label_{}\"", k + 1, label)?;
+            }
+        } else {
+            writeln!(writer, "2 1 \"domain\"")?;
+        }
+        writeln!(writer, "$EndPhysicalNames")?;
+
+        // Each entity carries the physical group with the same tag.
+        writeln!(writer, "$Entities")?;
+        writeln!(writer, "0 {} {} 0", curves.len(), surface_count)?;
+        for (tag, _, edges) in curves {
+            let nodes = edges.iter().flatten().map(|&n| &self.nodes[n]);
+            writeln!(writer, "{} {} 1 {} 0", tag, bounding_box(nodes), tag)?;
+        }
+        for surface in 1..=surface_count {
+            let nodes = self
+                .elements
+                .iter()
+                .enumerate()
+                .filter(|(k, _)| surface_of(*k) == surface)
+                .flat_map(|(_, element)| element.iter().map(|&n| &self.nodes[n]));
+            writeln!(
+                writer,
+                "{} {} 1 {} 0",
+                surface,
+                bounding_box(nodes),
+                surface
+            )?;
+        }
+        writeln!(writer, "$EndEntities")?;
+
+        // All nodes are placed in a single block on the first surface.
+        let count = self.nodes.len();
+        writeln!(writer, "$Nodes")?;
+        writeln!(writer, "1 {} 1 {}", count, count)?;
+        writeln!(writer, "2 1 0 {}", count)?;
+        for tag in 1..=count {
+            writeln!(writer, "{}", tag)?;
+        }
+        for p in &self.nodes {
+            writeln!(writer, "{} {} 0", p.x, p.y)?;
+        }
+        writeln!(writer, "$EndNodes")?;
+
+        // Blocks of lines per curve, then quadrilaterals and triangles per surface.
+        let mut blocks: Vec<(usize, usize, usize, Vec<&[usize]>)> = Vec::new();
+        for (tag, _, edges) in curves {
+            let lines = edges.iter().map(|edge| &edge[..]).collect();
+            blocks.push((1, *tag, GMSH_LINE, lines));
+        }
+        for surface in 1..=surface_count {
+            let (mut quads, mut triangles) = (Vec::new(), Vec::new());
+            for (k, element) in self.elements.iter().enumerate() {
+                if surface_of(k) != surface {
+                    continue;
+                }
+                if element[2] == element[3] {
+                    triangles.push(&element[..3]);
+                } else {
+                    quads.push(&element[..]);
+                }
+            }
+            blocks.push((2, surface, GMSH_QUADRANGLE, quads));
+            blocks.push((2, surface, GMSH_TRIANGLE, triangles));
+        }
+        blocks.retain(|(_, _, _, elements)| !elements.is_empty());
+
+        let count: usize = blocks.iter().map(|block| block.3.len()).sum();
+        writeln!(writer, "$Elements")?;
+        writeln!(writer, "{} {} 1 {}", blocks.len(), count, count)?;
+        let mut tag = 0;
+        for (dim, entity, element_type, elements) in &blocks {
+            writeln!(
+                writer,
+                "{} {} {} {}",
+                dim,
+                entity,
+                element_type,
+                elements.len()
+            )?;
+            for nodes in elements {
+                tag += 1;
+                let nodes: Vec<String> = nodes.iter().map(|n| (n + 1).to_string()).collect();
+                writeln!(writer, "{} {}", tag, nodes.join(" "))?;
+            }
+        }
+        writeln!(writer, "$EndElements")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Node, Point, Quadtree, Rectangle};
+
+    #[test]
+    fn test_write_msh() {
+        let mut tree = Quadtree::new(
+            Rectangle {
+                origin: Point { x: 0.0, y: 0.0 },
+                width: 4.0,
+                height: 4.0,
+            },
+            2,
+        );
+        tree.subdivide();
+        if let Node::Children { ne, .. } = &mut tree.node {
+            ne.subdivide();
+        }
+
+        let path = std::env::temp_dir().join(format!(
+            "quadtree_test_write_msh_primal_{}.msh",
+            std::process::id()
+        ));
+        let path = path.to_string_lossy();
+        let primal = tree.primal_mesh();
+        let labels = [1, 1, 2, 2, 1, 1, 1];
+        primal.write_msh(&path, Some(&labels)).unwrap();
+        let contents = std::fs::read_to_string(path.as_ref()).unwrap();
+        assert!(contents.starts_with("$MeshFormat\n4.1 0 8\n"));
+        assert!(contents.contains("1 2 \"east\""));
+        assert!(contents.contains("2 2 \"label_2\""));
+        // 10 boundary lines and 7 quadrilaterals.
+        assert!(contents.contains("$Elements\n6 17 1 17\n"));
+        assert!(primal.write_msh(&path, Some(&[1])).is_err());
+
+        let path = std::env::temp_dir().join(format!(
+            "quadtree_test_write_msh_dual_{}.msh",
+            std::process::id()
+        ));
+        let path = path.to_string_lossy();
+        tree.dual_quad_mesh().write_msh(&path, None).unwrap();
+        let contents = std::fs::read_to_string(path.as_ref()).unwrap();
+        assert!(contents.contains("2 1 \"domain\""));
+        // 6 boundary lines, 2 quadrilaterals, and 2 triangles.
+        assert!(contents.contains("$Elements\n6 10 1 10\n"));
+        assert!(contents.contains("2 1 2 2\n"));
+    }
+}
@@ -1,19 +1,31 @@
-// use dirs;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::env; // Needed for env::current_dir()
-use std::fs::File;
+use std::fmt;
 use std::io::Write;
-use std::process::Command;
+use std::process::{Command, Stdio};
+
+mod gmsh;
+mod mesh;
+mod metrics;
+mod partition;
+mod smooth;
+mod trace;
+pub use mesh::*;
+pub use metrics::*;
+pub use partition::*;
+pub use smooth::*;
+pub use trace::*;
 
 /// Represents a point in 2D space.
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Point {
     pub x: f64,
     pub y: f64,
 }
 
 /// Represents an axis-aligned rectangular boundary.
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Rectangle {
     pub origin: Point,
     pub width: f64,
@@ -27,7 +39,7 @@ pub struct Rectangle {
 // a leaf node with points, or an internal node with children.  To avoid a recursive
 // type with infinite size, the children are usually stored in a Box, which allocates
 // them on the heap.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub enum Node {
     /// A leaf node that stores a list of points.
     Leaf { points: Vec<Point> },
@@ -41,12 +53,112 @@ pub enum Node {
 }
 
 /// A Quadtree data structure.
-#[derive(Debug, Serialize)]
+///
+/// Each node is addressed exactly by its `cell`, and all adjacency tests are made
+/// with the integer cell coordinates.  The `f64` `boundary` is derived from the
+/// cell and the root boundary, and is used only for output and point insertion.
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Quadtree {
     pub boundary: Rectangle,
+    pub cell: DyadicCell,
     pub level: usize,
     pub level_max: usize,
     pub node: Node,
+    /// How `insert` treats points on, or near, the boundary of the root.
+    #[serde(default)]
+    pub boundary_policy: BoundaryPolicy,
+    /// The boundary of the root, from which the `f64` coordinates are produced.
+    #[serde(skip)]
+    domain: Rectangle,
+    /// The keys of the points of a leaf, so that `insert` finds a duplicate
+    /// without comparing it to every point of the leaf.
+    #[serde(skip)]
+    point_keys: HashSet<(u64, u64)>,
+}
+
+/// How `Quadtree::insert` treats points on, or near, the root boundary.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum BoundaryPolicy {
+    /// Accept points inside the half-open root, as in `Rectangle::contains`,
+    /// which rejects points on the top and right edges.
+    #[default]
+    HalfOpen,
+    /// Accept points inside the closed root, including all four edges.
+    ClosedAtRoot,
+    /// Accept points within `tolerance` of the closed root, and move any point
+    /// outside of it onto the nearest root edge.
+    Clamp { tolerance: f64 },
+}
+
+/// The reason `Quadtree::insert` rejected a point.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InsertError {
+    /// The point is outside the root, under the boundary policy.
+    OutOfBounds(Point),
+    /// The leaf already holds a point with the same coordinates.
+    Duplicate(Point),
+    /// The leaf is at `level_max` and already holds a point, so it cannot be
+    /// split to separate the new point.
+    LevelLimit(Point),
+}
+
+impl fmt::Display for InsertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InsertError::OutOfBounds(p) => {
+                write!(f, "point ({}, {}) is out of bounds", p.x, p.y)
+            }
+            InsertError::Duplicate(p) => write!(f, "point ({}, {}) is a duplicate", p.x, p.y),
+            InsertError::LevelLimit(p) => write!(
+                f,
+                "point ({}, {}) falls in an occupied leaf at level_max",
+                p.x, p.y
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InsertError {}
+
+/// The rule `Quadtree::refine_by` uses to select the leaves to subdivide.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RefinementCriterion {
+    /// Subdivide every leaf that holds at least one point.
+    AnyPoint,
+    /// Subdivide every leaf that holds more than the given number of points.
+    MaxPoints(usize),
+}
+
+/// Returns a hashable key for a point that compares coordinates exactly.
+///
+/// Negative zero has the key of zero, so that the keys of two points are equal
+/// exactly when the points are.
+pub(crate) fn point_key(point: &Point) -> (u64, u64) {
+    ((point.x + 0.0).to_bits(), (point.y + 0.0).to_bits())
+}
+
+/// The finest level a cell can have, so that the corners and centers of all cells
+/// fit in a `u64` at `VERTEX_LEVEL`.
+pub const LEVEL_LIMIT: usize = 62;
+
+/// The level of the integer grid on which the corners and centers of all cells lie.
+const VERTEX_LEVEL: usize = LEVEL_LIMIT + 1;
+
+/// The exact location of a corner or center point, in units of the root size
+/// divided by `2^VERTEX_LEVEL`.
+type VertexKey = (u64, u64);
+
+/// The exact dyadic address of a quadtree cell.
+///
+/// At `level`, the root is divided into `2^level` columns and `2^level` rows of
+/// equal cells, counted from the root origin.  The cell is in column `i` and row `j`,
+/// so its west and east edges are at the dyadic rationals `i / 2^level` and
+/// `(i + 1) / 2^level` of the root width, and likewise for `j` and the root height.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct DyadicCell {
+    pub i: u64,
+    pub j: u64,
+    pub level: usize,
 }
 
 /// A cardinal direction to search for face neighbors, to the north, east, south, west
@@ -64,14 +176,17 @@ enum Direction {
 /// of adjacent, smaller cells lying on it.
 #[derive(Debug, PartialEq, Serialize, Clone)]
 pub struct HangingEdge {
+    /// The coarse cell that this edge belongs to.
+    pub coarse_cell: DyadicCell,
     /// The center of the coarse cell that this edge belongs to.
     pub coarse_cell_center: Point,
     /// The first vertex of the coarse edge.
     pub v1: Point,
     /// The second vertex of the coarse edge.
     pub v2: Point,
-    /// The list of hanging nodes that lie on this edge.
-    /// For a 2:1 balanced tree, this will contain exactly one point.
+    /// The list of hanging nodes that lie on this edge, ordered from `v1` to `v2`.
+    /// For a 2:1 balanced tree, this will contain exactly one point, and for
+    /// finer neighbors, all of their corners inside the edge.
     pub hanging_nodes: Vec<Point>,
 }
 
@@ -95,18 +210,104 @@ impl Rectangle {
     //         || self.origin.y + self.height < other.origin.y)
     // }
 
-    /// Gets the vertices of an edge given a direction.
-    fn edge_vertices(&self, direction: Direction) -> (Point, Point) {
-        let x0 = self.origin.x;
-        let y0 = self.origin.y;
-        let x1 = x0 + self.width;
-        let y1 = y0 + self.height;
+    /// Returns the point at the dyadic fractions `x / 2^level` of the width and
+    /// `y / 2^level` of the height, measured from the origin.
+    ///
+    /// Scaling by a power of two is exact, so the same dyadic rational gives the
+    /// same `f64` at every level.
+    pub fn dyadic_point(&self, x: u64, y: u64, level: usize) -> Point {
+        let n = (level as f64).exp2();
+        Point {
+            x: self.origin.x + self.width * x as f64 / n,
+            y: self.origin.y + self.height * y as f64 / n,
+        }
+    }
+
+    /// Returns the boundary of a cell when this rectangle is the root boundary.
+    pub fn cell_rectangle(&self, cell: &DyadicCell) -> Rectangle {
+        let n = (cell.level as f64).exp2();
+        Rectangle {
+            origin: self.dyadic_point(cell.i, cell.j, cell.level),
+            width: self.width / n,
+            height: self.height / n,
+        }
+    }
+
+    /// Returns the point at an exact vertex location.
+    fn vertex_point(&self, key: VertexKey) -> Point {
+        self.dyadic_point(key.0, key.1, VERTEX_LEVEL)
+    }
+}
+
+impl DyadicCell {
+    /// Returns the cell of the root, at level zero.
+    pub fn root() -> Self {
+        Self {
+            i: 0,
+            j: 0,
+            level: 0,
+        }
+    }
+
+    /// Returns the four children of the cell, in `(nw, ne, sw, se)` order.
+    pub fn children(&self) -> [DyadicCell; 4] {
+        let (i, j, level) = (2 * self.i, 2 * self.j, self.level + 1);
+        [
+            DyadicCell { i, j: j + 1, level },
+            DyadicCell {
+                i: i + 1,
+                j: j + 1,
+                level,
+            },
+            DyadicCell { i, j, level },
+            DyadicCell { i: i + 1, j, level },
+        ]
+    }
+
+    /// Returns the `(west, east, south, north)` edges of the cell, counted in cells
+    /// of `level`, which must be at least the level of this cell.
+    pub fn edges_at(&self, level: usize) -> (u64, u64, u64, u64) {
+        let shift = level - self.level;
+        (
+            self.i << shift,
+            (self.i + 1) << shift,
+            self.j << shift,
+            (self.j + 1) << shift,
+        )
+    }
+
+    /// Returns `true` if the cell touches the `direction` side of the root.
+    fn on_root_side(&self, direction: Direction) -> bool {
+        let n = 1u64 << self.level;
+        match direction {
+            Direction::North => self.j + 1 == n,
+            Direction::East => self.i + 1 == n,
+            Direction::South => self.j == 0,
+            Direction::West => self.i == 0,
+        }
+    }
+
+    /// Returns the exact location of the cell center.
+    fn center_key(&self) -> VertexKey {
+        let shift = VERTEX_LEVEL - self.level - 1;
+        ((2 * self.i + 1) << shift, (2 * self.j + 1) << shift)
+    }
 
+    /// Returns the exact locations of the cell corners, counter-clockwise from
+    /// the south-west corner.
+    fn corner_keys(&self) -> [VertexKey; 4] {
+        let (x0, x1, y0, y1) = self.edges_at(VERTEX_LEVEL);
+        [(x0, y0), (x1, y0), (x1, y1), (x0, y1)]
+    }
+
+    /// Returns the exact locations of the end vertices of the edge in `direction`.
+    fn edge_keys(&self, direction: Direction) -> (VertexKey, VertexKey) {
+        let (x0, x1, y0, y1) = self.edges_at(VERTEX_LEVEL);
         match direction {
-            Direction::North => (Point { x: x0, y: y1 }, Point { x: x1, y: y1 }),
-            Direction::East => (Point { x: x1, y: y0 }, Point { x: x1, y: y1 }),
-            Direction::South => (Point { x: x0, y: y0 }, Point { x: x1, y: y0 }),
-            Direction::West => (Point { x: x0, y: y0 }, Point { x: x0, y: y1 }),
+            Direction::North => ((x0, y1), (x1, y1)),
+            Direction::East => ((x1, y0), (x1, y1)),
+            Direction::South => ((x0, y0), (x1, y0)),
+            Direction::West => ((x0, y0), (x0, y1)),
         }
     }
 }
@@ -117,23 +318,31 @@ impl Quadtree {
     /// # Arguments
     ///
     /// * `boundary` - The axis-aligned boundary of the root node.
-    /// * `level_max` - The maximum number of times the tree can be subdivided.
+    /// * `level_max` - The maximum number of times the tree can be subdivided,
+    ///   limited to `LEVEL_LIMIT`.
     pub fn new(boundary: Rectangle, level_max: usize) -> Self {
-        Self::new_with_level(boundary, 0, level_max)
+        Self::new_with_cell(boundary, DyadicCell::root(), level_max.min(LEVEL_LIMIT))
     }
-    // Internal constructor that includes the level
-    fn new_with_level(boundary: Rectangle, level: usize, level_max: usize) -> Self {
+    // Internal constructor for the node of `cell` within the root `domain`
+    fn new_with_cell(domain: Rectangle, cell: DyadicCell, level_max: usize) -> Self {
         Self {
-            boundary,
-            level,
+            boundary: domain.cell_rectangle(&cell),
+            cell,
+            level: cell.level,
             level_max,
             node: Node::Leaf { points: Vec::new() },
+            boundary_policy: BoundaryPolicy::default(),
+            domain,
+            point_keys: HashSet::new(),
         }
     }
     /// Inserts a point into the quadtree.
     ///
-    /// The point is recursively inserted into the appropriate leaf node.
-    /// This function does not trigger subdivision.
+    /// The point is checked against the boundary of this node under its
+    /// `boundary_policy`, then routed to the appropriate leaf node.
+    /// This function does not trigger subdivision, and a leaf at `level_max`
+    /// takes a point only while it is empty.  Before `InsertError::LevelLimit`,
+    /// such a leaf kept every point it received.
     ///
     /// # Arguments
     ///
@@ -141,42 +350,74 @@ impl Quadtree {
     ///
     /// # Returns
     ///
-    /// `true` if the point is within the quadtree's boundary and was inserted, `false` otherwise.
-    pub fn insert(&mut self, point: Point) -> bool {
-        if !self.boundary.contains(&point) {
-            return false;
+    /// `Ok(())` if the point was inserted, or the `InsertError` that explains why it was not.
+    pub fn insert(&mut self, point: Point) -> Result<(), InsertError> {
+        let point = self.admit(point)?;
+        self.insert_routed(point)
+    }
+
+    /// Applies the boundary policy to a point, and returns the point to insert.
+    fn admit(&self, point: Point) -> Result<Point, InsertError> {
+        let x_min = self.boundary.origin.x;
+        let y_min = self.boundary.origin.y;
+        let x_max = x_min + self.boundary.width;
+        let y_max = y_min + self.boundary.height;
+        let within = |p: &Point, tolerance: f64| {
+            p.x >= x_min - tolerance
+                && p.x <= x_max + tolerance
+                && p.y >= y_min - tolerance
+                && p.y <= y_max + tolerance
+        };
+
+        match self.boundary_policy {
+            BoundaryPolicy::HalfOpen if self.boundary.contains(&point) => Ok(point),
+            BoundaryPolicy::ClosedAtRoot if within(&point, 0.0) => Ok(point),
+            BoundaryPolicy::Clamp { tolerance } if within(&point, tolerance) => Ok(Point {
+                x: point.x.clamp(x_min, x_max),
+                y: point.y.clamp(y_min, y_max),
+            }),
+            _ => Err(InsertError::OutOfBounds(point)),
         }
+    }
 
+    /// Routes an admitted point to its leaf by comparison with the node centers.
+    ///
+    /// Points on a center line go to the east or north child, so that points on the
+    /// top or right edge of a closed root reach the leaves along that edge.
+    fn insert_routed(&mut self, point: Point) -> Result<(), InsertError> {
         match &mut self.node {
             Node::Leaf { points } => {
+                let key = point_key(&point);
+                if self.point_keys.contains(&key) {
+                    return Err(InsertError::Duplicate(point));
+                }
+                if self.level >= self.level_max && !points.is_empty() {
+                    return Err(InsertError::LevelLimit(point));
+                }
+                self.point_keys.insert(key);
                 points.push(point);
-                // if self.level < self.level_max {
-                //     self.subdivide();
-                // }
-                // return true;
-                true
+                Ok(())
             }
             Node::Children { nw, ne, sw, se } => {
-                // Create a performant version to avoid redundant boundary checks.
-                let center_x = self.boundary.origin.x + self.boundary.width / 2.0;
-                let center_y = self.boundary.origin.y + self.boundary.height / 2.0;
+                let center = self.domain.vertex_point(self.cell.center_key());
 
-                if point.x < center_x {
-                    if point.y < center_y {
-                        sw.insert(point)
+                if point.x < center.x {
+                    if point.y < center.y {
+                        sw.insert_routed(point)
                     } else {
-                        nw.insert(point)
+                        nw.insert_routed(point)
                     }
                 } else {
-                    if point.y < center_y {
-                        se.insert(point)
+                    if point.y < center.y {
+                        se.insert_routed(point)
                     } else {
-                        ne.insert(point)
+                        ne.insert_routed(point)
                     }
                 }
             }
         }
     }
+
     // Subdivide a leaf node into four children nodes
     pub fn subdivide(&mut self) {
         // Take the points from the current leaf node, leaving an empty vector in its place.
@@ -187,70 +428,35 @@ impl Quadtree {
             return;
         };
 
-        let half_width = self.boundary.width / 2.0;
-        let half_height = self.boundary.height / 2.0;
-        let x = self.boundary.origin.x;
-        let y = self.boundary.origin.y;
-        let child_level = self.level + 1;
+        // Subdividing past the limit would overflow the integer cell coordinates.
+        if self.level >= LEVEL_LIMIT {
+            if let Node::Leaf {
+                points: leaf_points,
+            } = &mut self.node
+            {
+                *leaf_points = points;
+            }
+            return;
+        }
 
-        let nw_boundary = Rectangle {
-            origin: Point {
-                x,
-                y: y + half_height,
-            },
-            width: half_width,
-            height: half_height,
-        };
-        let ne_boundary = Rectangle {
-            origin: Point {
-                x: x + half_width,
-                y: y + half_height,
-            },
-            width: half_width,
-            height: half_height,
-        };
-        let sw_boundary = Rectangle {
-            origin: Point { x, y },
-            width: half_width,
-            height: half_height,
-        };
-        let se_boundary = Rectangle {
-            origin: Point {
-                x: x + half_width,
-                y,
-            },
-            width: half_width,
-            height: half_height,
+        let [nw_cell, ne_cell, sw_cell, se_cell] = self.cell.children();
+        let child = |cell| {
+            let mut child = Quadtree::new_with_cell(self.domain.clone(), cell, self.level_max);
+            child.boundary_policy = self.boundary_policy;
+            Box::new(child)
         };
+        let mut nw = child(nw_cell);
+        let mut ne = child(ne_cell);
+        let mut sw = child(sw_cell);
+        let mut se = child(se_cell);
 
-        let mut nw = Box::new(Quadtree::new_with_level(
-            nw_boundary,
-            child_level,
-            self.level_max,
-        ));
-        let mut ne = Box::new(Quadtree::new_with_level(
-            ne_boundary,
-            child_level,
-            self.level_max,
-        ));
-        let mut sw = Box::new(Quadtree::new_with_level(
-            sw_boundary,
-            child_level,
-            self.level_max,
-        ));
-        let mut se = Box::new(Quadtree::new_with_level(
-            se_boundary,
-            child_level,
-            self.level_max,
-        ));
         // Distribute the points of the parent leaf to the new children.
         // Create a performant version to avoid redundant boundary checks.
-        let center_x = self.boundary.origin.x + self.boundary.width / 2.0;
-        let center_y = self.boundary.origin.y + self.boundary.height / 2.0;
+        let center = self.center();
 
         for p in points {
-            if p.x < center_x {
-                if p.y < center_y {
+            if p.x < center.x {
+                if p.y < center.y {
                     if let Node::Leaf { points } = &mut sw.node {
                         points.push(p);
                     }
@@ -260,7 +466,7 @@ impl Quadtree {
                     }
                 }
             } else {
-                if p.y < center_y {
+                if p.y < center.y {
                     if let Node::Leaf { points } = &mut se.node {
                         points.push(p);
                     }
@@ -271,8 +477,20 @@ impl Quadtree {
                 }
             }
         }
+        for child in [&mut nw, &mut ne, &mut sw, &mut se] {
+            child.index_points();
+        }
         // Replace the leaf node with the new children nodes
         self.node = Node::Children { nw, ne, sw, se };
+        self.index_points();
+    }
+
+    /// Rebuilds the keys of the points of a leaf, which an internal node does not keep.
+    fn index_points(&mut self) {
+        self.point_keys = match &self.node {
+            Node::Leaf { points } => points.iter().map(point_key).collect(),
+            Node::Children { .. } => HashSet::new(),
+        };
     }
 
     /// Refines the quadtree by subdividing leaves that contain points.
@@ -281,20 +499,47 @@ impl Quadtree {
     /// contains one or more points and has not yet reached `level_max`.
     /// The process is recursive.
     pub fn refine(&mut self) {
-        // If the current node is a leaf that contains points and has not reached the
-        // level_max, then subdivide it
+        self.refine_by(RefinementCriterion::AnyPoint);
+    }
+
+    /// Refines the quadtree by subdividing the leaves selected by a criterion.
+    ///
+    /// This function traverses the tree and subdivides any leaf node that meets
+    /// the `criterion` and has not yet reached `level_max`.
+    /// The process is recursive.
+    ///
+    /// # Arguments
+    ///
+    /// * `criterion` - The rule that selects the leaves to subdivide.
+    pub fn refine_by(&mut self, criterion: RefinementCriterion) {
+        self.refine_traced(criterion, None);
+    }
+
+    /// Refines the quadtree as in `refine_by`, recording each subdivision in the
+    /// `trace`, if there is one.
+    fn refine_traced(&mut self, criterion: RefinementCriterion, mut trace: Option<&mut Trace>) {
+        // If the current node is a leaf that meets the criterion and has not reached
+        // the level_max, then subdivide it
         if let Node::Leaf { points } = &self.node {
-            if !points.is_empty() && self.level < self.level_max {
+            let count = points.len();
+            let selected = match criterion {
+                RefinementCriterion::AnyPoint => count > 0,
+                RefinementCriterion::MaxPoints(count_max) => count > count_max,
+            };
+            if selected && self.level < self.level_max {
+                if let Some(trace) = trace.as_deref_mut() {
+                    trace.record(self.cell, SubdivisionReason::Points { count });
+                }
                 self.subdivide();
             }
         }
         // After potential subdivision, the node might now be a `Children` node.
         // If so, recursively refine each child.
         if let Node::Children { nw, ne, sw, se } = &mut self.node {
-            nw.refine();
-            ne.refine();
-            sw.refine();
-            se.refine();
+            nw.refine_traced(criterion, trace.as_deref_mut());
+            ne.refine_traced(criterion, trace.as_deref_mut());
+            sw.refine_traced(criterion, trace.as_deref_mut());
+            se.refine_traced(criterion, trace);
         }
     }
 
@@ -304,13 +549,116 @@ impl Quadtree {
     /// (sharing a full edge) differ by at most one level of refinement.
     /// The function iteratively subdivides leaves until the tree is balanced.
     pub fn weak_balance(&mut self) {
-        while self.balance_pass_weakly() {
-            // The loop continues as long as a pass makes a change.
+        self.weak_balance_traced(None);
+    }
+
+    /// Balances the quadtree as in `weak_balance`, recording each subdivision in
+    /// the `trace`, if there is one, with one pass number per balancing pass.
+    fn weak_balance_traced(&mut self, mut trace: Option<&mut Trace>) {
+        loop {
+            if let Some(trace) = trace.as_deref_mut() {
+                trace.begin_pass();
+            }
+            if !self.balance_pass_weakly(trace.as_deref_mut()) {
+                // The loop continues as long as a pass makes a change.
+                break;
+            }
+        }
+    }
+
+    /// Subdivides one leaf, then subdivides only the neighbors needed to keep the
+    /// tree weakly balanced.
+    ///
+    /// Instead of the repeated global passes of `weak_balance`, a worklist holds the
+    /// newly created leaves.  Each one is checked against its face neighbors, and a
+    /// neighbor more than one level coarser is subdivided and its children added to
+    /// the worklist, so refinement spreads only as far as it must.  If the tree is
+    /// weakly balanced before the call, it is weakly balanced after it.  Otherwise,
+    /// as after `refine` without `weak_balance`, only the new leaves are balanced
+    /// with their neighbors.
+    ///
+    /// # Arguments
+    ///
+    /// * `cell` - The cell of the leaf to subdivide.
+    ///
+    /// # Returns
+    ///
+    /// The number of leaves subdivided, including the leaf at `cell`, or zero if
+    /// `cell` is not a leaf of the tree or is already at `level_max`.
+    pub fn subdivide_balanced(&mut self, cell: &DyadicCell) -> usize {
+        let mut worklist = match self.node_mut(cell) {
+            Some(leaf) if matches!(leaf.node, Node::Leaf { .. }) && leaf.level < leaf.level_max => {
+                leaf.subdivide();
+                leaf.cell.children().to_vec()
+            }
+            _ => return 0,
+        };
+        let mut count = 1;
+
+        while let Some(fine) = worklist.pop() {
+            // A new leaf may since have been subdivided, as the coarse neighbor of a
+            // much finer leaf of an unbalanced tree.
+            if !self
+                .node_mut(&fine)
+                .is_some_and(|node| matches!(node.node, Node::Leaf { .. }))
+            {
+                continue;
+            }
+            // The neighbors are collected first, so that they can then be subdivided.
+            let coarse: Vec<DyadicCell> = self
+                .face_neighbors(&fine)
+                .iter()
+                .filter(|neighbor| fine.level > neighbor.level + 1)
+                .map(|neighbor| neighbor.cell)
+                .collect();
+            if coarse.is_empty() {
+                continue;
+            }
+            for cell in coarse {
+                // A neighbor may have already been subdivided through another new leaf.
+                if let Some(leaf) = self.node_mut(&cell) {
+                    if matches!(leaf.node, Node::Leaf { .. }) {
+                        leaf.subdivide();
+                        worklist.extend(cell.children());
+                        count += 1;
+                    }
+                }
+            }
+            // In an unbalanced tree, the children of a neighbor may still be too
+            // coarse, so the leaf is checked again.
+            worklist.push(fine);
+        }
+        count
+    }
+
+    /// Returns the node at `cell`, if it exists in the tree.
+    fn node_mut(&mut self, cell: &DyadicCell) -> Option<&mut Quadtree> {
+        if self.cell == *cell {
+            return Some(self);
+        }
+        if cell.level <= self.level {
+            return None;
+        }
+        match &mut self.node {
+            Node::Leaf { .. } => None,
+            Node::Children { nw, ne, sw, se } => {
+                // The bit below the level of the children selects the child.
+                let shift = cell.level - self.level - 1;
+                let east = (cell.i >> shift) & 1 == 1;
+                let north = (cell.j >> shift) & 1 == 1;
+                let child = match (east, north) {
+                    (false, true) => nw,
+                    (true, true) => ne,
+                    (false, false) => sw,
+                    (true, false) => se,
+                };
+                child.node_mut(cell)
+            }
         }
     }
 
     /// Recursively collects all immutable references to the leaf nodes in the quadtree.
-    fn get_all_leaves(&self) -> Vec<&Quadtree> {
+    pub fn get_all_leaves(&self) -> Vec<&Quadtree> {
         let mut leaves = Vec::new();
         match &self.node {
             Node::Leaf { .. } => {
@@ -334,23 +682,24 @@ impl Quadtree {
     /// # Returns
     ///
     /// `true` if any subdivisions were made, `false` otherwise.
-    fn balance_pass_weakly(&mut self) -> bool {
-        use std::collections::HashSet;
+    fn balance_pass_weakly(&mut self, trace: Option<&mut Trace>) -> bool {
+        use std::collections::{HashMap, HashSet};
 
         // We collect immutable leaves first, find neighbors that need subdivision,
         // and then will later re-aquire mutable referneces to subdivide them.
         let leaves = self.get_all_leaves();
         let mut to_subdivide = HashSet::new();
+        // For the trace, the first finer leaf that marked each neighbor.
+        let mut marked_by = HashMap::new();
 
         for leaf in &leaves {
-            // `face_neighbors` is not implemented yet, so this part won't trigger.
-            let neighbors = self.face_neighbors(&leaf.boundary);
+            let neighbors = self.face_neighbors(&leaf.cell);
             for neighbor in neighbors {
                 if leaf.level > neighbor.level + 1 {
                     // This neighbor needs to be subdivided.
-                    // We store a raw pointer to it to avoid borrow checker issues
-                    // and to have a unique identifier for the HashSet.
-                    to_subdivide.insert(neighbor as *const Quadtree);
+                    // Its cell is an exact, unique identifier for the HashSet.
+                    to_subdivide.insert(neighbor.cell);
+                    marked_by.entry(neighbor.cell).or_insert(leaf.cell);
                 }
             }
         }
@@ -359,22 +708,29 @@ impl Quadtree {
             return false;
         }
 
+        if let Some(trace) = trace {
+            let mut marked: Vec<(DyadicCell, DyadicCell)> = marked_by
+                .into_iter()
+                .filter(|(cell, _)| cell.level < self.level_max)
+                .collect();
+            marked.sort_by_key(|(cell, _)| (cell.level, cell.j, cell.i));
+            for (cell, leaf) in marked {
+                trace.record(cell, SubdivisionReason::Neighbor { leaf });
+            }
+        }
+
         // Now, perform the subdivisions.
-        self.subdivide_leaves_by_pointer(&to_subdivide);
+        self.subdivide_leaves_by_cell(&to_subdivide);
 
         // Return true to indicate that the tree was modified.
         true
     }
 
-    /// Recursively finds and subdivides leaves identified by a set of raw pointers.
-    fn subdivide_leaves_by_pointer(
-        &mut self,
-        to_subdivide: &std::collections::HashSet<*const Quadtree>,
-    ) {
+    /// Recursively finds and subdivides leaves identified by a set of cells.
+    fn subdivide_leaves_by_cell(&mut self, to_subdivide: &std::collections::HashSet<DyadicCell>) {
         // If the current node is a leaf, check if it needs to be subdivided.
         if let Node::Leaf { .. } = &self.node {
-            let self_ptr = self as *const Quadtree;
-            if to_subdivide.contains(&self_ptr) && self.level < self.level_max {
+            if to_subdivide.contains(&self.cell) && self.level < self.level_max {
                 self.subdivide();
             }
         }
@@ -382,39 +738,49 @@ impl Quadtree {
         // After potential subdivisions, the node might now be `Children`, so we traverse
         // into them.
         if let Node::Children { nw, ne, sw, se } = &mut self.node {
-            nw.subdivide_leaves_by_pointer(to_subdivide);
-            ne.subdivide_leaves_by_pointer(to_subdivide);
-            sw.subdivide_leaves_by_pointer(to_subdivide);
-            se.subdivide_leaves_by_pointer(to_subdivide);
+            nw.subdivide_leaves_by_cell(to_subdivide);
+            ne.subdivide_leaves_by_cell(to_subdivide);
+            sw.subdivide_leaves_by_cell(to_subdivide);
+            se.subdivide_leaves_by_cell(to_subdivide);
         }
     }
 
-    /// Finds all leaf nodes that share a face (edge) with a given boundary.
+    /// Finds all leaf nodes that share a face (edge) with a given cell.
     /// This is the new, traversal-based implementation.
-    fn face_neighbors<'a>(&'a self, leaf_boundary: &Rectangle) -> Vec<&'a Quadtree> {
+    fn face_neighbors<'a>(&'a self, leaf_cell: &DyadicCell) -> Vec<&'a Quadtree> {
         let mut neighbors = Vec::new();
-        neighbors.extend(self.find_neighbors_recursive(leaf_boundary, Direction::North));
-        neighbors.extend(self.find_neighbors_recursive(leaf_boundary, Direction::East));
-        neighbors.extend(self.find_neighbors_recursive(leaf_boundary, Direction::South));
-        neighbors.extend(self.find_neighbors_recursive(leaf_boundary, Direction::West));
+        neighbors.extend(self.find_neighbors_recursive(leaf_cell, Direction::North));
+        neighbors.extend(self.find_neighbors_recursive(leaf_cell, Direction::East));
+        neighbors.extend(self.find_neighbors_recursive(leaf_cell, Direction::South));
+        neighbors.extend(self.find_neighbors_recursive(leaf_cell, Direction::West));
         neighbors
     }
 
     /// Recursively finds adjacent leaves in a specific direction using tree traversal.
+    ///
+    /// The `target_cell` must be a strict descendant of this node when it has children.
     fn find_neighbors_recursive<'a>(
         &'a self,
-        target_boundary: &Rectangle,
+        target_cell: &DyadicCell,
         direction: Direction,
     ) -> Vec<&'a Quadtree> {
         match &self.node {
             Node::Leaf { .. } => vec![], // Base case, cannot descend further
             Node::Children { nw, ne, sw, se } => {
-                let center_x = self.boundary.origin.x + self.boundary.width / 2.0;
-                let center_y = self.boundary.origin.y + self.boundary.height / 2.0;
+                // The center lines of this node and the edges of the target, all
+                // counted exactly in cells of the target's level.  A target that is
+                // not a strict descendant, such as a node that is no longer a leaf,
+                // has no neighbors found from here.
+                let Some(shift) = target_cell.level.checked_sub(self.level + 1) else {
+                    return vec![];
+                };
+                let center_x = (2 * self.cell.i + 1) << shift;
+                let center_y = (2 * self.cell.j + 1) << shift;
+                let (west, east, south, north) = target_cell.edges_at(target_cell.level);
 
-                // Determine which child the target boundary is in.
-                let target_is_in_north = target_boundary.origin.y >= center_y;
-                let target_is_in_west = target_boundary.origin.x < center_x;
+                // Determine which child the target cell is in.
+                let target_is_in_north = south >= center_y;
+                let target_is_in_west = west < center_x;
 
                 let child_to_search = if target_is_in_north {
                     if target_is_in_west {
@@ -432,39 +798,35 @@ impl Quadtree {
 
                 match direction {
                     Direction::North => {
-                        if !target_is_in_north
-                            && target_boundary.origin.y + target_boundary.height == center_y
-                        {
+                        if !target_is_in_north && north == center_y {
                             let northern_child = if target_is_in_west { nw } else { ne };
-                            northern_child.get_leaves_on_edge(Direction::South, target_boundary)
+                            northern_child.get_leaves_on_edge(Direction::South, target_cell)
                         } else {
-                            child_to_search.find_neighbors_recursive(target_boundary, direction)
+                            child_to_search.find_neighbors_recursive(target_cell, direction)
                         }
                     }
                     Direction::South => {
-                        if target_is_in_north && target_boundary.origin.y == center_y {
+                        if target_is_in_north && south == center_y {
                             let southern_child = if target_is_in_west { sw } else { se };
-                            southern_child.get_leaves_on_edge(Direction::North, target_boundary)
+                            southern_child.get_leaves_on_edge(Direction::North, target_cell)
                         } else {
-                            child_to_search.find_neighbors_recursive(target_boundary, direction)
+                            child_to_search.find_neighbors_recursive(target_cell, direction)
                         }
                     }
                     Direction::East => {
-                        if target_is_in_west
-                            && target_boundary.origin.x + target_boundary.width == center_x
-                        {
+                        if target_is_in_west && east == center_x {
                             let eastern_child = if target_is_in_north { ne } else { se };
-                            eastern_child.get_leaves_on_edge(Direction::West, target_boundary)
+                            eastern_child.get_leaves_on_edge(Direction::West, target_cell)
                         } else {
-                            child_to_search.find_neighbors_recursive(target_boundary, direction)
+                            child_to_search.find_neighbors_recursive(target_cell, direction)
                         }
                     }
                     Direction::West => {
-                        if !target_is_in_west && target_boundary.origin.x == center_x {
+                        if !target_is_in_west && west == center_x {
                             let western_child = if target_is_in_north { nw } else { sw };
-                            western_child.get_leaves_on_edge(Direction::East, target_boundary)
+                            western_child.get_leaves_on_edge(Direction::East, target_cell)
                         } else {
-                            child_to_search.find_neighbors_recursive(target_boundary, direction)
+                            child_to_search.find_neighbors_recursive(target_cell, direction)
                         }
                     }
                 }
@@ -476,21 +838,23 @@ impl Quadtree {
     fn get_leaves_on_edge<'a>(
         &'a self,
         edge: Direction,
-        target_boundary: &Rectangle,
+        target_cell: &DyadicCell,
     ) -> Vec<&'a Quadtree> {
-        // First, check for intersection in the transverse driection.
+        // First, check for intersection in the transverse driection, with both
+        // cells counted exactly at the finer of the two levels.
         // For West/East edge, check for y-overlap.
         // For North/South edge, check x-overlap.
+        let level = self.level.max(target_cell.level);
+        let (west, east, south, north) = self.cell.edges_at(level);
+        let (target_west, target_east, target_south, target_north) = target_cell.edges_at(level);
         let intersects = match edge {
             Direction::East | Direction::West => {
                 // y-overlap check
-                self.boundary.origin.y < target_boundary.origin.y + target_boundary.height
-                    && self.boundary.origin.y + self.boundary.height > target_boundary.origin.y
+                south < target_north && north > target_south
             }
             Direction::North | Direction::South => {
                 // x-overlap check
-                self.boundary.origin.x < target_boundary.origin.x + target_boundary.width
-                    && self.boundary.origin.x + self.boundary.width > target_boundary.origin.x
+                west < target_east && east > target_west
             }
         };
 
@@ -504,26 +868,26 @@ impl Quadtree {
             Node::Children { nw, ne, sw, se } => match edge {
                 Direction::North => {
                     // Collect 'north' from nw and ne
-                    let mut leaves = nw.get_leaves_on_edge(edge, target_boundary);
-                    leaves.extend(ne.get_leaves_on_edge(edge, target_boundary));
+                    let mut leaves = nw.get_leaves_on_edge(edge, target_cell);
+                    leaves.extend(ne.get_leaves_on_edge(edge, target_cell));
                     leaves
                 }
                 Direction::East => {
                     // Collect 'east' from ne and se
-                    let mut leaves = ne.get_leaves_on_edge(edge, target_boundary);
-                    leaves.extend(se.get_leaves_on_edge(edge, target_boundary));
+                    let mut leaves = ne.get_leaves_on_edge(edge, target_cell);
+                    leaves.extend(se.get_leaves_on_edge(edge, target_cell));
                     leaves
                 }
                 Direction::South => {
                     // Collect 'south' from sw and se
-                    let mut leaves = sw.get_leaves_on_edge(edge, target_boundary);
-                    leaves.extend(se.get_leaves_on_edge(edge, target_boundary));
+                    let mut leaves = sw.get_leaves_on_edge(edge, target_cell);
+                    leaves.extend(se.get_leaves_on_edge(edge, target_cell));
                     leaves
                 }
                 Direction::West => {
                     // Collect 'west' from nw and sw
-                    let mut leaves = nw.get_leaves_on_edge(edge, target_boundary);
-                    leaves.extend(sw.get_leaves_on_edge(edge, target_boundary));
+                    let mut leaves = nw.get_leaves_on_edge(edge, target_cell);
+                    leaves.extend(sw.get_leaves_on_edge(edge, target_cell));
                     leaves
                 }
             },
@@ -532,92 +896,75 @@ impl Quadtree {
 
     /// Finds all hanging edges in the quadtree
     ///
-    /// This function traverses the tree and identifies all "hanging edges",
-    /// which are edges of a leaf cell that are adjacent to a more refined
-    /// (subdivided) cell.
+    /// This function visits every leaf and identifies its "hanging edges",
+    /// which are edges of the leaf that are adjacent to more refined leaves
+    /// anywhere in the tree, not only among its siblings.
+    ///
+    /// Each edge is reported once, by its coarse leaf, so the edges are unique
+    /// by `coarse_cell` and side.  The hanging nodes are the corners of all of the
+    /// finer leaves that lie inside the edge, so an edge next to leaves refined by
+    /// more than one additional level has more than one hanging node.
     ///
     /// # Returns
     ///
     /// A `Vec<HangingEdge>` containing all the identified hanging edges.
     pub fn hanging_edges(&self) -> Vec<HangingEdge> {
         let mut hanging_edges = Vec::new();
-        self.hanging_edges_recursive(&mut hanging_edges);
-        // Note: This simple traversal might find the same edge twice from
-        // opposite sides.  For a final implementation, we might consider
-        // using a HashSet to store unique edges before returning a Vec
-        hanging_edges
-    }
-
-    /// Recursive helper function to find hanging edges.
-    fn hanging_edges_recursive(&self, hanging_edges: &mut Vec<HangingEdge>) {
-        if let Node::Children { nw, ne, sw, se } = &self.node {
-            // Check vertical interfaces
-            if let Some(edge) = Self::find_interface_hanging_edge(nw, ne, Direction::East) {
-                hanging_edges.push(edge);
-            }
-            if let Some(edge) = Self::find_interface_hanging_edge(sw, se, Direction::East) {
-                hanging_edges.push(edge);
-            }
-
-            // Check horizontal interfaces
-            if let Some(edge) = Self::find_interface_hanging_edge(nw, sw, Direction::South) {
-                hanging_edges.push(edge);
-            }
-            if let Some(edge) = Self::find_interface_hanging_edge(ne, se, Direction::South) {
-                hanging_edges.push(edge);
+        for leaf in self.get_all_leaves() {
+            for direction in [
+                Direction::North,
+                Direction::East,
+                Direction::South,
+                Direction::West,
+            ] {
+                if let Some(edge) = self.hanging_edge(leaf, direction) {
+                    hanging_edges.push(edge);
+                }
             }
-
-            // Recurse into children
-            nw.hanging_edges_recursive(hanging_edges);
-            ne.hanging_edges_recursive(hanging_edges);
-            sw.hanging_edges_recursive(hanging_edges);
-            se.hanging_edges_recursive(hanging_edges);
         }
+        hanging_edges
     }
 
-    /// Checks for a hanging edge between two adjacent nodes and returns it if found.
-    fn find_interface_hanging_edge(
-        node1: &Quadtree,
-        node2: &Quadtree,
-        direction_from_node1: Direction,
-    ) -> Option<HangingEdge> {
-        match (&node1.node, &node2.node) {
-            // Case 1: node1 is a leaf, node2 is subdivided.
-            (Node::Leaf { .. }, Node::Children { .. }) => {
-                let (v1, v2) = node1.boundary.edge_vertices(direction_from_node1);
-                let hanging_node = Point {
-                    x: (v1.x + v2.x) / 2.0,
-                    y: (v1.y + v2.y) / 2.0,
-                };
-                Some(HangingEdge {
-                    coarse_cell_center: node1.center(),
-                    v1,
-                    v2,
-                    hanging_nodes: vec![hanging_node],
-                })
-            }
-            // Case 2: node2 is a leaf, node1 is subdivided.
-            (Node::Children { .. }, Node::Leaf { .. }) => {
-                let opposite_direction = match direction_from_node1 {
-                    Direction::East => Direction::West,
-                    Direction::South => Direction::North,
-                    _ => unreachable!(),
-                };
-                let (v1, v2) = node2.boundary.edge_vertices(opposite_direction);
-                let hanging_node = Point {
-                    x: (v1.x + v2.x) / 2.0,
-                    y: (v1.y + v2.y) / 2.0,
-                };
-                Some(HangingEdge {
-                    coarse_cell_center: node2.center(),
-                    v1,
-                    v2,
-                    hanging_nodes: vec![hanging_node],
-                })
-            }
-            // No hanging edge at this interface.
-            _ => None,
+    /// Returns the hanging edge on the `direction` side of a `leaf`, if the leaves
+    /// across that side are finer than the leaf.
+    fn hanging_edge(&self, leaf: &Quadtree, direction: Direction) -> Option<HangingEdge> {
+        let neighbors = self.find_neighbors_recursive(&leaf.cell, direction);
+        if !neighbors.iter().any(|neighbor| neighbor.level > leaf.level) {
+            return None;
         }
+
+        // The corners of the finer leaves along the shared edge, other than the
+        // ends of the coarse edge, are the hanging nodes.
+        let (k1, k2) = leaf.cell.edge_keys(direction);
+        let opposite = match direction {
+            Direction::North => Direction::South,
+            Direction::East => Direction::West,
+            Direction::South => Direction::North,
+            Direction::West => Direction::East,
+        };
+        let mut keys: Vec<VertexKey> = neighbors
+            .iter()
+            .flat_map(|neighbor| {
+                let (n1, n2) = neighbor.cell.edge_keys(opposite);
+                [n1, n2]
+            })
+            .filter(|key| *key != k1 && *key != k2)
+            .collect();
+        // Both ends of the edge have the same x or the same y, so the keys sort
+        // along the edge from `v1` to `v2`.
+        keys.sort();
+        keys.dedup();
+
+        Some(HangingEdge {
+            coarse_cell: leaf.cell,
+            coarse_cell_center: leaf.center(),
+            v1: self.domain.vertex_point(k1),
+            v2: self.domain.vertex_point(k2),
+            hanging_nodes: keys
+                .into_iter()
+                .map(|key| self.domain.vertex_point(key))
+                .collect(),
+        })
     }
 
     /// Computes the dual vertices of the quadtree.
@@ -639,11 +986,7 @@ impl Quadtree {
         match &self.node {
             Node::Leaf { .. } => {
                 // For a leaf node, the dual vertes is at its center.
-                let center = Point {
-                    x: self.boundary.origin.x + self.boundary.width / 2.0,
-                    y: self.boundary.origin.y + self.boundary.height / 2.0,
-                };
-                vertices.push(center);
+                vertices.push(self.center());
             }
             Node::Children { nw, ne, sw, se } => {
                 // If it's not a leaf, recurse into the children.
@@ -657,10 +1000,7 @@ impl Quadtree {
 
     /// Returns teh center point of a quadtree's boundary.
     fn center(&self) -> Point {
-        Point {
-            x: self.boundary.origin.x + self.boundary.width / 2.0,
-            y: self.boundary.origin.y + self.boundary.height / 2.0,
-        }
+        self.domain.vertex_point(self.cell.center_key())
     }
 
     /// Computes the dual edges for the entire quadtree, handing both uniform
@@ -676,8 +1016,8 @@ impl Quadtree {
         // 1. Add edges of same-level neighbors.
         for leaf in &leaves {
             // Find neighbors to the East and South to avoid generating duplicate edges.
-            let neighbors_east = self.find_neighbors_recursive(&leaf.boundary, Direction::East);
-            let neighbors_south = self.find_neighbors_recursive(&leaf.boundary, Direction::South);
+            let neighbors_east = self.find_neighbors_recursive(&leaf.cell, Direction::East);
+            let neighbors_south = self.find_neighbors_recursive(&leaf.cell, Direction::South);
 
             for neighbor in neighbors_east.iter().chain(neighbors_south.iter()) {
                 // Only create an edge if the neighbor is a the same refinement level.
@@ -705,55 +1045,87 @@ impl Quadtree {
         serde_yaml::to_string(self)
     }
 
-    /// Given a quadtree (self), writes the structure of the quadtree to a YAML
-    /// file to the `scratch_path`, then calls the Python script `visualize_quadtree.py`
-    /// to read in the YAML file and visualize the quadtree using MATPLOTLIB, saving
-    /// the figure the `scratch_path`.
+    /// Serializes the quadtree to a JSON string.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Deserializes a quadtree from the YAML written by `to_yaml`.
+    pub fn from_yaml(yaml: &str) -> Result<Self, serde_yaml::Error> {
+        let mut tree: Quadtree = serde_yaml::from_str(yaml)?;
+        tree.restore_domain();
+        Ok(tree)
+    }
+
+    /// Deserializes a quadtree from the JSON written by `to_json`.
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let mut tree: Quadtree = serde_json::from_str(json)?;
+        tree.restore_domain();
+        Ok(tree)
+    }
+
+    /// Restores the root boundary and the point keys, which are not serialized,
+    /// to every node of a deserialized tree.
+    fn restore_domain(&mut self) {
+        fn restore(node: &mut Quadtree, domain: &Rectangle) {
+            node.domain = domain.clone();
+            node.index_points();
+            if let Node::Children { nw, ne, sw, se } = &mut node.node {
+                for child in [nw, ne, sw, se] {
+                    restore(child, domain);
+                }
+            }
+        }
+        let domain = self.boundary.clone();
+        restore(self, &domain);
+    }
+
+    /// Given a quadtree (self), calls the Python script `visualize_quadtree.py`
+    /// with the structure of the quadtree as YAML on its standard input, which it
+    /// reads with the pyquadtree bindings to visualize the quadtree using
+    /// MATPLOTLIB, saving the figure to `{file_suffix}.png` in the `scratch_path`.
     pub fn visualize(
         &self,
         scratch_path: &str,
         file_suffix: &str,
         show_dual: bool,
     ) -> Result<(), String> {
-        // Helper struct to hold all data for serialization.
-        #[derive(Serialize)]
-        struct VisualizationData<'a> {
-            quadtree: &'a Quadtree,
-            dual_vertices: Option<Vec<Point>>,
-            dual_edges: Option<Vec<(Point, Point)>>,
-        }
-
-        let mut viz_data = VisualizationData {
-            quadtree: self,
-            dual_vertices: None,
-            dual_edges: None,
-        };
-
-        if show_dual {
-            viz_data.dual_vertices = Some(self.dual_vertices());
-            viz_data.dual_edges = Some(self.dual_edges());
-        }
+        self.visualize_with(scratch_path, file_suffix, show_dual, None)
+    }
 
-        // let yaml_data = self
-        //     .to_yaml()
-        //     .map_err(|e| format!("Failed to serialize visualization data to YAML: {}", e))?;
-        let yaml_data = serde_yaml::to_string(&viz_data)
+    /// Visualizes the quadtree as in `visualize`, with the leaves optionally
+    /// shaded by the part of a `partition`.
+    fn visualize_with(
+        &self,
+        scratch_path: &str,
+        file_suffix: &str,
+        show_dual: bool,
+        partition: Option<&Partition>,
+    ) -> Result<(), String> {
+        // The script reads the quadtree from its standard input through the
+        // pyquadtree bindings, which also compute the dual vertices and edges, so
+        // no scratch copy of the tree is written.
+        let yaml_data = self
+            .to_yaml()
             .map_err(|e| format!("Failed to serialize visualization data to YAML: {}", e))?;
 
         // println!("Generated YAML data:\n{}", yaml_data);
         println!("Generated YAML data for '{}'.", file_suffix);
 
-        // Create a temporary YAML output file with the suffix
-        // let file_name = format!("quadtree_data_{}.yaml", file_suffix);
-        let file_name = format!("{}.yaml", file_suffix);
-        let path_file_name = std::path::Path::new(scratch_path).join(&file_name);
-        println!("Scratch YAML file path: {:?}", path_file_name);
-
-        let mut file = File::create(&path_file_name)
-            .map_err(|e| format!("Failed to create temporary file: {}", e))?;
-
-        file.write_all(yaml_data.as_bytes())
-            .map_err(|e| format!("Failed to write YAML to temporary file: {}", e))?;
+        let image_path = std::path::Path::new(scratch_path).join(format!("{}.png", file_suffix));
+
+        // The part of each leaf, one per line, in the order of `get_all_leaves`.
+        let parts_path =
+            std::path::Path::new(scratch_path).join(format!("{}_parts.txt", file_suffix));
+        if let Some(partition) = partition {
+            let parts: String = partition
+                .parts
+                .iter()
+                .map(|part| format!("{}\n", part))
+                .collect();
+            std::fs::write(&parts_path, parts)
+                .map_err(|e| format!("Failed to write {:?}: {}", parts_path, e))?;
+        }
 
         // Get the current working directory to find the Python script
         let current_dir =
@@ -798,13 +1170,41 @@ impl Quadtree {
 
         // Execute the Python script
         // let output = Command::new("python") // Use "python" or "python3" depending on your system
-        let output = Command::new(python_command)
+        let mut command = Command::new(python_command);
+        // Pass "-" to read the YAML from the standard input
+        command
             .arg(&script_path)
-            .arg(&path_file_name) // Pass the path to the YAML file as an argument
-            .output()
+            .arg("-")
+            .arg("--output")
+            .arg(&image_path);
+        if show_dual {
+            command.arg("--dual");
+        }
+        if partition.is_some() {
+            command.arg("--parts").arg(&parts_path);
+        }
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to execute Python script: {}", e))?;
+        // A script that fails before reading closes its input, which the exit code
+        // below reports better than the broken pipe.
+        let written = child
+            .stdin
+            .take()
+            .map(|mut stdin| stdin.write_all(yaml_data.as_bytes()));
+        let output = child
+            .wait_with_output()
             .map_err(|e| format!("Failed to execute Python script: {}", e))?;
 
-        if output.status.success() {
+        if let (true, Some(Err(e))) = (output.status.success(), written) {
+            Err(format!(
+                "Failed to write YAML to the Python script for '{}': {}",
+                file_suffix, e
+            ))
+        } else if output.status.success() {
             println!("Python script executed successfully for '{}'.", file_suffix);
             println!(
                 "Python stdout:\n{}",
@@ -871,7 +1271,7 @@ mod tests {
         let mut quadtree = Quadtree::new(boundary, 0);
         let point = Point { x: 50.0, y: 60.0 };
 
-        assert!(quadtree.insert(point.clone()));
+        assert_eq!(quadtree.insert(point.clone()), Ok(()));
         if let Node::Leaf { points } = quadtree.node {
             assert_eq!(points.len(), 1);
             assert_eq!(points[0], point);
@@ -890,7 +1290,7 @@ mod tests {
         let mut quadtree = Quadtree::new(boundary, 1);
         let point = Point { x: 50.0, y: 50.0 }; // Point will be in ne quadrant
 
-        assert!(quadtree.insert(point.clone()));
+        assert_eq!(quadtree.insert(point.clone()), Ok(()));
 
         // Refine the quadtree to trigger subdivision
         quadtree.refine();
@@ -931,6 +1331,136 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_insert_boundary_policy() {
+        let boundary = Rectangle {
+            origin: Point { x: -1.0, y: -1.0 },
+            width: 2.0,
+            height: 2.0,
+        };
+        let corner = Point { x: 1.0, y: 0.0 };
+
+        // The half-open root rejects the point on its right edge.
+        let mut tree = Quadtree::new(boundary.clone(), 2);
+        assert_eq!(
+            tree.insert(corner.clone()),
+            Err(InsertError::OutOfBounds(corner.clone()))
+        );
+
+        // The closed root accepts it, and routes it to a leaf on the right edge.
+        let mut tree = Quadtree::new(boundary.clone(), 2);
+        tree.boundary_policy = BoundaryPolicy::ClosedAtRoot;
+        tree.subdivide();
+        assert_eq!(tree.insert(corner.clone()), Ok(()));
+        if let Node::Children { ne, .. } = &tree.node {
+            assert!(matches!(&ne.node, Node::Leaf { points } if points.len() == 1));
+        } else {
+            panic!("Quadtree should have children after subdivision.");
+        }
+
+        // Clamping moves a nearby point onto the root edge.
+        let mut tree = Quadtree::new(boundary, 0);
+        tree.boundary_policy = BoundaryPolicy::Clamp { tolerance: 1.0e-3 };
+        assert_eq!(
+            tree.insert(Point {
+                x: 1.0005,
+                y: -1.0005
+            }),
+            Ok(())
+        );
+        assert_eq!(
+            tree.insert(Point { x: 1.01, y: 0.0 }),
+            Err(InsertError::OutOfBounds(Point { x: 1.01, y: 0.0 }))
+        );
+        if let Node::Leaf { points } = &tree.node {
+            assert_eq!(points, &vec![Point { x: 1.0, y: -1.0 }]);
+        }
+    }
+
+    #[test]
+    fn test_insert_errors() {
+        let boundary = Rectangle {
+            origin: Point { x: 0.0, y: 0.0 },
+            width: 1.0,
+            height: 1.0,
+        };
+        let mut tree = Quadtree::new(boundary, 1);
+        let p = Point { x: 0.25, y: 0.25 };
+        assert_eq!(tree.insert(p.clone()), Ok(()));
+        assert_eq!(
+            tree.insert(p.clone()),
+            Err(InsertError::Duplicate(p.clone()))
+        );
+
+        // Below level_max, a leaf holds any number of points.
+        assert_eq!(tree.insert(Point { x: 0.3, y: 0.3 }), Ok(()));
+        tree.refine();
+
+        // At level_max, an occupied leaf no longer keeps a new point, as it did
+        // before LevelLimit, and a duplicate is still reported as one.
+        let q = Point { x: 0.35, y: 0.35 };
+        assert_eq!(tree.insert(q.clone()), Err(InsertError::LevelLimit(q)));
+        assert_eq!(tree.insert(p.clone()), Err(InsertError::Duplicate(p)));
+        if let Node::Children { sw, ne, .. } = &tree.node {
+            assert!(matches!(&sw.node, Node::Leaf { points } if points.len() == 2));
+            assert!(matches!(&ne.node, Node::Leaf { points } if points.is_empty()));
+        } else {
+            panic!("Quadtree should have children after refinement.");
+        }
+        // An empty leaf at level_max still takes one point.
+        assert_eq!(tree.insert(Point { x: 0.75, y: 0.75 }), Ok(()));
+    }
+
+    #[test]
+    fn test_refine_by_and_round_trip() {
+        let boundary = Rectangle {
+            origin: Point { x: 0.0, y: 0.0 },
+            width: 1.0,
+            height: 1.0,
+        };
+        let mut tree = Quadtree::new(boundary, 3);
+        tree.boundary_policy = BoundaryPolicy::ClosedAtRoot;
+        for point in [
+            Point { x: 0.1, y: 0.1 },
+            Point { x: 0.4, y: 0.4 },
+            Point { x: 0.9, y: 0.9 },
+        ] {
+            tree.insert(point).unwrap();
+        }
+
+        // Only the leaves holding more than one point are subdivided.
+        tree.refine_by(RefinementCriterion::MaxPoints(1));
+        assert_eq!(tree.get_all_leaves().len(), 7);
+
+        // The deserialized trees have the same leaves, with the exact cell coordinates.
+        let cells: Vec<DyadicCell> = tree.get_all_leaves().iter().map(|l| l.cell).collect();
+        let from_yaml = Quadtree::from_yaml(&tree.to_yaml().unwrap()).unwrap();
+        let from_json = Quadtree::from_json(&tree.to_json().unwrap()).unwrap();
+        for copy in [from_yaml, from_json] {
+            let copy_cells: Vec<DyadicCell> =
+                copy.get_all_leaves().iter().map(|l| l.cell).collect();
+            assert_eq!(copy_cells, cells);
+            assert_eq!(copy.dual_edges(), tree.dual_edges());
+            // The boundary policy and the duplicate check survive the round trip.
+            let mut copy = copy;
+            assert_eq!(copy.boundary_policy, BoundaryPolicy::ClosedAtRoot);
+            let p = Point { x: 0.9, y: 0.9 };
+            assert_eq!(copy.insert(p.clone()), Err(InsertError::Duplicate(p)));
+            assert_eq!(copy.insert(Point { x: 1.0, y: 1.0 }), Ok(()));
+        }
+
+        // A tree written before the boundary policy was serialized uses the default.
+        let yaml: String = tree
+            .to_yaml()
+            .unwrap()
+            .lines()
+            .filter(|line| !line.contains("boundary_policy"))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let copy = Quadtree::from_yaml(&yaml).unwrap();
+        assert_eq!(copy.boundary_policy, BoundaryPolicy::HalfOpen);
+    }
+
     #[test]
     fn test_weak_balance() {
         // Manually create an unbalanced tree.
@@ -988,4 +1518,259 @@ mod tests {
             _ => panic!("Three should be a Children node."),
         }
     }
+
+    #[test]
+    fn test_subdivide_balanced() {
+        // A weakly balanced tree, refined toward points on a circle.
+        let balanced_tree = || {
+            let mut tree = Quadtree::new(
+                Rectangle {
+                    origin: Point { x: -1.0, y: -1.0 },
+                    width: 2.0,
+                    height: 2.0,
+                },
+                5,
+            );
+            for k in 0..12 {
+                let angle = k as f64 * std::f64::consts::PI / 6.0 + 0.1;
+                tree.insert(Point {
+                    x: 0.7 * angle.cos(),
+                    y: 0.7 * angle.sin(),
+                })
+                .unwrap();
+            }
+            tree.refine();
+            tree.weak_balance();
+            tree
+        };
+        let sorted_cells = |tree: &Quadtree| {
+            let mut cells: Vec<(usize, u64, u64)> = tree
+                .get_all_leaves()
+                .iter()
+                .map(|leaf| (leaf.level, leaf.cell.i, leaf.cell.j))
+                .collect();
+            cells.sort();
+            cells
+        };
+
+        let cells: Vec<DyadicCell> = balanced_tree()
+            .get_all_leaves()
+            .iter()
+            .filter(|leaf| leaf.level < leaf.level_max)
+            .map(|leaf| leaf.cell)
+            .collect();
+        assert!(!cells.is_empty());
+
+        // Subdividing any leaf locally matches subdividing it and balancing globally.
+        let mut spread = false;
+        for cell in &cells {
+            let mut local = balanced_tree();
+            let count = local.subdivide_balanced(cell);
+            assert!(count >= 1);
+            spread |= count > 1;
+
+            let mut global = balanced_tree();
+            global.node_mut(cell).unwrap().subdivide();
+            global.weak_balance();
+
+            assert_eq!(sorted_cells(&local), sorted_cells(&global));
+        }
+        assert!(
+            spread,
+            "At least one subdivision should spread to a neighbor."
+        );
+
+        // A cell that is not a leaf is not subdivided.
+        let mut tree = balanced_tree();
+        assert_eq!(tree.subdivide_balanced(&DyadicCell::root()), 0);
+    }
+
+    #[test]
+    fn test_subdivide_balanced_unbalanced() {
+        // An unbalanced tree, as written by the refine command without --balance,
+        // refined toward a single point near the north side.
+        let unbalanced_tree = || {
+            let mut tree = Quadtree::new(
+                Rectangle {
+                    origin: Point { x: 0.0, y: 0.0 },
+                    width: 1.0,
+                    height: 1.0,
+                },
+                7,
+            );
+            tree.insert(Point {
+                x: 0.4921875,
+                y: 0.9765625,
+            })
+            .unwrap();
+            tree.refine();
+            tree
+        };
+        let cells: Vec<DyadicCell> = unbalanced_tree()
+            .get_all_leaves()
+            .iter()
+            .filter(|leaf| leaf.level < leaf.level_max)
+            .map(|leaf| leaf.cell)
+            .collect();
+
+        // The new leaves have no face neighbor more than one level coarser, even
+        // when a new leaf is itself subdivided for a much finer neighbor.
+        for cell in &cells {
+            let mut tree = unbalanced_tree();
+            let before: Vec<DyadicCell> = tree.get_all_leaves().iter().map(|l| l.cell).collect();
+            let count = tree.subdivide_balanced(cell);
+            let leaves = tree.get_all_leaves();
+            assert_eq!(leaves.len(), before.len() + 3 * count);
+            for leaf in leaves.iter().filter(|leaf| !before.contains(&leaf.cell)) {
+                for neighbor in tree.face_neighbors(&leaf.cell) {
+                    assert!(neighbor.level + 1 >= leaf.level);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_hanging_edges() {
+        let mut tree = Quadtree::new(
+            Rectangle {
+                origin: Point { x: 0.0, y: 0.0 },
+                width: 1.0,
+                height: 1.0,
+            },
+            4,
+        );
+        tree.subdivide();
+        if let Node::Children { nw, ne, .. } = &mut tree.node {
+            nw.subdivide();
+            ne.subdivide();
+            if let Node::Children { sw, .. } = &mut ne.node {
+                sw.subdivide();
+            }
+        }
+
+        let edges = tree.hanging_edges();
+        // The east side of nw.se faces the children of ne.sw, which are not its
+        // siblings.  The north side of se faces leaves of two finer levels.
+        assert_eq!(edges.len(), 5);
+        for (k, edge) in edges.iter().enumerate() {
+            assert!(edges[k + 1..]
+                .iter()
+                .all(|other| (&other.v1, &other.v2) != (&edge.v1, &edge.v2)));
+        }
+
+        let nw_se = edges
+            .iter()
+            .find(|e| {
+                e.coarse_cell
+                    == DyadicCell {
+                        i: 1,
+                        j: 2,
+                        level: 2,
+                    }
+            })
+            .unwrap();
+        assert_eq!(nw_se.hanging_nodes, vec![Point { x: 0.5, y: 0.625 }]);
+
+        let se = edges
+            .iter()
+            .find(|e| {
+                e.coarse_cell
+                    == DyadicCell {
+                        i: 1,
+                        j: 0,
+                        level: 1,
+                    }
+            })
+            .unwrap();
+        assert_eq!(se.v1, Point { x: 0.5, y: 0.5 });
+        assert_eq!(se.v2, Point { x: 1.0, y: 0.5 });
+        assert_eq!(
+            se.hanging_nodes,
+            vec![Point { x: 0.625, y: 0.5 }, Point { x: 0.75, y: 0.5 }]
+        );
+
+        let count: usize = edges.iter().map(|e| e.hanging_nodes.len()).sum();
+        assert_eq!(count, 6);
+    }
+
+    #[test]
+    fn test_dyadic_cell() {
+        let root = DyadicCell::root();
+        let [nw, ne, sw, se] = root.children();
+        assert_eq!(
+            nw,
+            DyadicCell {
+                i: 0,
+                j: 1,
+                level: 1
+            }
+        );
+        assert_eq!(
+            ne,
+            DyadicCell {
+                i: 1,
+                j: 1,
+                level: 1
+            }
+        );
+        assert_eq!(
+            sw,
+            DyadicCell {
+                i: 0,
+                j: 0,
+                level: 1
+            }
+        );
+        assert_eq!(
+            se,
+            DyadicCell {
+                i: 1,
+                j: 0,
+                level: 1
+            }
+        );
+        assert_eq!(ne.edges_at(3), (4, 8, 4, 8));
+
+        // The same dyadic rational gives the same point at every level.
+        let domain = Rectangle {
+            origin: Point {
+                x: 1.0e8 + 0.1,
+                y: -3.3,
+            },
+            width: 0.3,
+            height: 0.7,
+        };
+        assert_eq!(domain.dyadic_point(3, 1, 2), domain.dyadic_point(12, 4, 4));
+    }
+
+    #[test]
+    fn test_weak_balance_offset_domain() {
+        // The same imbalance as `test_weak_balance`, on a large, offset domain
+        // whose edges are not exactly representable after repeated halving.
+        let mut tree = Quadtree::new(
+            Rectangle {
+                origin: Point {
+                    x: 1.0e8 + 0.1,
+                    y: -3.3,
+                },
+                width: 0.3,
+                height: 0.7,
+            },
+            4,
+        );
+        tree.subdivide();
+        if let Node::Children { ne, .. } = &mut tree.node {
+            ne.subdivide();
+            if let Node::Children { sw, .. } = &mut ne.node {
+                sw.subdivide();
+            }
+        }
+        tree.weak_balance();
+        match &tree.node {
+            Node::Children { nw, .. } => {
+                assert!(matches!(nw.node, Node::Children { .. }));
+            }
+            _ => panic!("Tree should be a Children node."),
+        }
+    }
 }
@@ -1,7 +1,12 @@
-// use dirs;
+use clap::{Parser, Subcommand, ValueEnum};
 use quadtree::*; // Bring all public items from quadtree into scope
+use serde::Serialize;
 use std::f64::consts::PI;
 use std::fs;
+use std::path::Path;
+
+/// An example function that writes its output to a scratch path.
+type Example = fn(&str) -> Result<(), String>;
 
 fn circle_points(num_points: u32) -> Vec<Point> {
     let radius: f64 = 1.0;
@@ -37,7 +42,8 @@ fn point_stimulated_refinement(scratch_path_str: &str) -> Result<(), String> {
 
     println!("Inserting points...");
     // ne_ne_sw_sw_ne quadrant (up to level 5 refinement)
-    tree.insert(Point { x: 2.6, y: 0.6 });
+    tree.insert(Point { x: 2.6, y: 0.6 })
+        .map_err(|e| e.to_string())?;
 
     // println!("\nQuadtree before refinement:");
     // println!("{:#?}", tree);
@@ -50,7 +56,7 @@ fn point_stimulated_refinement(scratch_path_str: &str) -> Result<(), String> {
 
     // Visualize the unbalanced tree
     println!("\nVisualizing quadtree BEFORE balancing...");
-    if let Err(e) = tree.visualize(&scratch_path_str, "example_1_before_balancing", false) {
+    if let Err(e) = tree.visualize(scratch_path_str, "example_1_before_balancing", false) {
         eprintln!("Visualization failed: {}", e);
     }
 
@@ -61,7 +67,7 @@ fn point_stimulated_refinement(scratch_path_str: &str) -> Result<(), String> {
 
     // Visualize the balanced tree
     println!("\nVisualizing quadtree AFTER balancing...");
-    if let Err(e) = tree.visualize(&scratch_path_str, "example_1_weakly_balanced", false) {
+    if let Err(e) = tree.visualize(scratch_path_str, "example_1_weakly_balanced", false) {
         eprintln!("Visualization failed: {}", e);
     }
     Ok(())
@@ -113,7 +119,7 @@ fn manual_subdivision(scratch_path_str: &str) -> Result<(), String> {
 
     // Visualize the unbalanced tree
     println!("\nVisualizing quadtree BEFORE balancing...");
-    if let Err(e) = tree.visualize(&scratch_path_str, "example_2_before_balancing", false) {
+    if let Err(e) = tree.visualize(scratch_path_str, "example_2_before_balancing", false) {
         eprintln!("Visualization failed: {}", e);
     }
 
@@ -124,7 +130,7 @@ fn manual_subdivision(scratch_path_str: &str) -> Result<(), String> {
 
     // Visualize the balanced tree
     println!("\nVisualizing quadtree AFTER balancing...");
-    if let Err(e) = tree.visualize(&scratch_path_str, "example_2_weakly_balanced", false) {
+    if let Err(e) = tree.visualize(scratch_path_str, "example_2_weakly_balanced", false) {
         eprintln!("Visualization failed: {}", e);
     }
 
@@ -147,9 +153,11 @@ fn circle_with_balancing(scratch_path_str: &str) -> Result<(), String> {
 
     println!("Inserting points...");
     let circle_points = circle_points(100);
+    // The point at (1, 0) is on the right edge of the root.
+    tree.boundary_policy = BoundaryPolicy::ClosedAtRoot;
     for point in circle_points {
         // println!("Inserting point: {:?}", point);
-        tree.insert(point);
+        tree.insert(point).map_err(|e| e.to_string())?;
     }
 
     // println!("\nQuadtree before refinement:");
@@ -163,7 +171,7 @@ fn circle_with_balancing(scratch_path_str: &str) -> Result<(), String> {
 
     // Visualize the unbalanced tree
     println!("\nVisualizing quadtree BEFORE balancing...");
-    if let Err(e) = tree.visualize(&scratch_path_str, "example_3_before_balancing", false) {
+    if let Err(e) = tree.visualize(scratch_path_str, "example_3_before_balancing", false) {
         eprintln!("Visualization failed: {}", e);
     }
 
@@ -174,7 +182,7 @@ fn circle_with_balancing(scratch_path_str: &str) -> Result<(), String> {
 
     // Visualize the balanced tree
     println!("\nVisualizing quadtree AFTER balancing...");
-    if let Err(e) = tree.visualize(&scratch_path_str, "example_3_weakly_balanced", false) {
+    if let Err(e) = tree.visualize(scratch_path_str, "example_3_weakly_balanced", false) {
         eprintln!("Visualization failed: {}", e);
     }
 
@@ -198,7 +206,7 @@ fn level_1_fully_refined(scratch_path_str: &str) -> Result<(), String> {
     tree.subdivide(); // L0 -> L1
 
     println!("\nVisualizing quadtree...");
-    if let Err(e) = tree.visualize(&scratch_path_str, title, true) {
+    if let Err(e) = tree.visualize(scratch_path_str, title, true) {
         eprintln!("Visualization failed: {}", e);
     }
 
@@ -230,46 +238,513 @@ fn transition_wine_glass(scratch_path_str: &str) -> Result<(), String> {
     se.subdivide(); // L1 -> L2
 
     println!("\nVisualizing quadtree...");
-    if let Err(e) = tree.visualize(&scratch_path_str, title, true) {
+    if let Err(e) = tree.visualize(scratch_path_str, title, true) {
         eprintln!("Visualization failed: {}", e);
     }
 
     Ok(())
 }
 
-fn main() -> Result<(), String> {
-    println!("Hello quadtree!");
-
-    // Step 0: Setup
-    // Create an output directory for output YAML and visualization
-    let home_dir =
-        dirs::home_dir().ok_or_else(|| "Could not find the home directory".to_string())?;
-    let scratch_path = home_dir.join("scratch").join("quadtree");
-
-    // Ensure the custom directory exists
-    fs::create_dir_all(&scratch_path).map_err(|e| {
-        format!(
-            "Failed to create custom directory {:?}: {}",
-            scratch_path, e
-        )
-    })?;
-    println!("Using scratch path: {:?}", scratch_path);
-    let scratch_path_str = scratch_path.to_string_lossy();
+/// The quadtree command line interface.
+#[derive(Parser)]
+#[command(about = "Quadtree refinement, balancing, and dual meshes", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Creates a refined quadtree from points
+    Refine(RefineArgs),
+    /// Weakly balances an existing quadtree
+    Balance {
+        /// Quadtree input file (yaml | json)
+        #[arg(long, short, value_name = "FILE")]
+        input: String,
+
+        /// Balanced quadtree output file (yaml | json)
+        #[arg(long, short, value_name = "FILE")]
+        output: String,
+
+        /// Output format [default: from the output extension]
+        #[arg(ignore_case = true, long, short, value_enum, value_name = "FORMAT")]
+        format: Option<Format>,
+    },
+    /// Writes the smoothed or unsmoothed dual mesh of a quadtree
+    Dual {
+        /// Quadtree input file (yaml | json)
+        #[arg(long, short, value_name = "FILE")]
+        input: String,
+
+        /// Dual mesh output file (yaml | json)
+        #[arg(long, short, value_name = "FILE")]
+        output: String,
+
+        /// Number of Taubin smoothing iterations
+        #[arg(default_value_t = 0, long, short = 'n', value_name = "NUM")]
+        iterations: usize,
+
+        /// Output format [default: from the output extension]
+        #[arg(ignore_case = true, long, short, value_enum, value_name = "FORMAT")]
+        format: Option<Format>,
+    },
+    /// Writes the primal or dual quadrilateral mesh of a quadtree for Gmsh
+    Mesh {
+        /// Quadtree input file (yaml | json)
+        #[arg(long, short, value_name = "FILE")]
+        input: String,
+
+        /// Mesh output file (msh)
+        #[arg(long, short, value_name = "FILE")]
+        output: String,
+
+        /// Pass to write the dual mesh instead of the primal mesh
+        #[arg(action, long, short)]
+        dual: bool,
+
+        /// Pass to label the elements of the primal mesh by leaf level
+        #[arg(action, long, short, conflicts_with = "dual")]
+        levels: bool,
+    },
+    /// Prints leaf counts and the quality of the primal and dual meshes
+    Stats {
+        /// Quadtree input file (yaml | json)
+        #[arg(long, short, value_name = "FILE")]
+        input: String,
+
+        /// Quality metrics output file of the dual mesh (csv)
+        #[arg(long, short, value_name = "FILE")]
+        output: Option<String>,
+
+        /// Number of histogram bins
+        #[arg(default_value_t = 10, long, short, value_name = "NUM")]
+        bins: usize,
+    },
+    /// Renders a quadtree to an image with visualize_quadtree.py
+    Render {
+        /// Quadtree input file (yaml | json)
+        #[arg(long, short, value_name = "FILE")]
+        input: String,
+
+        /// Image output file (png)
+        #[arg(long, short, value_name = "FILE")]
+        output: String,
+
+        /// Pass to draw the dual vertices and edges
+        #[arg(action, long, short, conflicts_with = "parts")]
+        dual: bool,
+
+        /// Shade the leaves by NUM parts of equal leaf count along a Hilbert curve
+        #[arg(long, short, value_name = "NUM")]
+        parts: Option<usize>,
+    },
+    /// Runs the built-in examples
+    Example {
+        /// Output directory for the YAML files and images
+        #[arg(long, short, value_name = "DIR")]
+        output: String,
+
+        /// Example to run (point | manual | circle | level_1 | wine_glass) [default: all]
+        #[arg(long, short, value_name = "NAME")]
+        name: Option<String>,
+    },
+}
+
+/// The options of `refine`.
+#[derive(clap::Args)]
+struct RefineArgs {
+    /// Points input file (csv | yaml), with one x, y pair per point
+    #[arg(long, short, value_name = "FILE", conflicts_with = "circle")]
+    input: Option<String>,
+
+    /// Sample NUM points on the unit circle instead of reading an input file
+    #[arg(long, value_name = "NUM")]
+    circle: Option<u32>,
+
+    /// Quadtree output file (yaml | json)
+    #[arg(long, short, value_name = "FILE")]
+    output: String,
+
+    /// Root boundary as x,y,width,height [default: bounding box of the points]
+    #[arg(long, value_name = "X,Y,W,H", value_delimiter = ',')]
+    domain: Option<Vec<f64>>,
+
+    /// Maximum refinement level
+    #[arg(default_value_t = 5, long, short, value_name = "LEVEL")]
+    level_max: usize,
+
+    /// Refinement criterion (any | max:N), where max:N subdivides leaves with more than N points
+    #[arg(default_value = "any", long, short, value_name = "RULE", value_parser = parse_criterion)]
+    criterion: RefinementCriterion,
+
+    /// Pass to weakly balance the tree after refinement
+    #[arg(action, long, short)]
+    balance: bool,
+
+    /// Trace output file (json) of every subdivision
+    #[arg(long, short, value_name = "FILE")]
+    trace: Option<String>,
+
+    /// Output directory for one SVG frame per subdivision
+    #[arg(long, value_name = "DIR")]
+    frames: Option<String>,
+
+    /// Output format [default: from the output extension]
+    #[arg(ignore_case = true, long, short, value_enum, value_name = "FORMAT")]
+    format: Option<Format>,
+}
+
+/// The file format of a quadtree or mesh.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Format {
+    #[value(alias = "yml")]
+    Yaml,
+    Json,
+}
+
+impl Format {
+    /// Returns `format`, if there is one, or else the format of the file extension.
+    fn new(path: &str, format: Option<Format>) -> Result<Self, String> {
+        if let Some(format) = format {
+            return Ok(format);
+        }
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        Format::from_str(extension, true).map_err(|_| {
+            format!(
+                "Unsupported format '{}' for {}, use yaml or json",
+                extension, path
+            )
+        })
+    }
+}
+
+/// Parses a refinement criterion of the form `any` or `max:N`.
+fn parse_criterion(criterion: &str) -> Result<RefinementCriterion, String> {
+    match criterion.split_once(':') {
+        None if criterion == "any" => Ok(RefinementCriterion::AnyPoint),
+        Some(("max", count)) => count
+            .parse()
+            .map(RefinementCriterion::MaxPoints)
+            .map_err(|e| format!("Invalid point count in criterion '{}': {}", criterion, e)),
+        _ => Err(format!(
+            "Invalid criterion '{}', use any or max:N",
+            criterion
+        )),
+    }
+}
+
+/// Reads points from a CSV file of x, y pairs, or a YAML list of points.
+///
+/// A first CSV line that does not parse as numbers is treated as a header.
+fn read_points(path: &str) -> Result<Vec<Point>, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    match extension {
+        "csv" => {
+            let mut points = Vec::new();
+            for (i, line) in contents.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let values: Result<Vec<f64>, _> =
+                    line.split(',').map(|v| v.trim().parse::<f64>()).collect();
+                match values.as_deref() {
+                    Ok([x, y]) => points.push(Point { x: *x, y: *y }),
+                    Err(_) if i == 0 => continue,
+                    _ => return Err(format!("Invalid point on line {} of {}", i + 1, path)),
+                }
+            }
+            Ok(points)
+        }
+        "yaml" | "yml" => serde_yaml::from_str(&contents)
+            .map_err(|e| format!("Failed to parse points from {}: {}", path, e)),
+        _ => Err(format!("Unsupported points file {}, use csv or yaml", path)),
+    }
+}
+
+/// Returns the bounding box of the points.
+fn bounding_box(points: &[Point]) -> Result<Rectangle, String> {
+    let (mut x_min, mut y_min) = (f64::INFINITY, f64::INFINITY);
+    let (mut x_max, mut y_max) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for p in points {
+        x_min = x_min.min(p.x);
+        y_min = y_min.min(p.y);
+        x_max = x_max.max(p.x);
+        y_max = y_max.max(p.y);
+    }
+    if !(x_max > x_min && y_max > y_min) {
+        return Err("The points do not span an area, use --domain".to_string());
+    }
+    Ok(Rectangle {
+        origin: Point { x: x_min, y: y_min },
+        width: x_max - x_min,
+        height: y_max - y_min,
+    })
+}
+
+fn read_tree(path: &str) -> Result<Quadtree, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    match Format::new(path, None)? {
+        Format::Yaml => Quadtree::from_yaml(&contents).map_err(|e| e.to_string()),
+        Format::Json => Quadtree::from_json(&contents).map_err(|e| e.to_string()),
+    }
+    .map_err(|e| format!("Failed to parse quadtree from {}: {}", path, e))
+}
+
+/// Writes a serializable value in the requested format.
+fn write_output<T: Serialize>(value: &T, path: &str, format: Option<Format>) -> Result<(), String> {
+    let contents = match Format::new(path, format)? {
+        Format::Yaml => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+        Format::Json => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+    }
+    .map_err(|e| format!("Failed to serialize {}: {}", path, e))?;
+    fs::write(path, contents).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    println!("Wrote {}", path);
+    Ok(())
+}
+
+impl RefineArgs {
+    fn run(self) -> Result<(), String> {
+        let format = Format::new(&self.output, self.format)?;
+        let points = match (&self.input, self.circle) {
+            (Some(input), _) => read_points(input)?,
+            (None, Some(num_points)) => circle_points(num_points),
+            (None, None) => return Err("Either --input or --circle is required".to_string()),
+        };
+        let boundary = match self.domain.as_deref() {
+            Some(&[x, y, width, height]) => Rectangle {
+                origin: Point { x, y },
+                width,
+                height,
+            },
+            Some(_) => {
+                return Err("The domain must be four values, x,y,width,height".to_string());
+            }
+            None => bounding_box(&points)?,
+        };
+
+        let mut tree = Quadtree::new(boundary, self.level_max);
+        // The bounding box has points on its top and right edges.
+        tree.boundary_policy = BoundaryPolicy::ClosedAtRoot;
+
+        let total = points.len();
+        let mut rejected = 0;
+        for point in points {
+            if let Err(e) = tree.insert(point) {
+                eprintln!("Skipped {}", e);
+                rejected += 1;
+            }
+        }
+        println!("Inserted {} of {} points", total - rejected, total);
 
+        // The subdivisions are recorded only when they are written.
+        let mut trace = (self.trace.is_some() || self.frames.is_some()).then(|| Trace::new(&tree));
+        match trace.as_mut() {
+            Some(trace) => tree.refine_with_trace(self.criterion, trace),
+            None => tree.refine_by(self.criterion),
+        }
+        if self.balance {
+            match trace.as_mut() {
+                Some(trace) => tree.weak_balance_with_trace(trace),
+                None => tree.weak_balance(),
+            }
+        }
+        if let (Some(trace_path), Some(trace)) = (&self.trace, &trace) {
+            let json = trace
+                .to_json()
+                .map_err(|e| format!("Failed to serialize the trace: {}", e))?;
+            fs::write(trace_path, json)
+                .map_err(|e| format!("Failed to write {}: {}", trace_path, e))?;
+            println!("Wrote {} with {} steps", trace_path, trace.events.len());
+        }
+        if let (Some(frames), Some(trace)) = (&self.frames, &trace) {
+            let count = trace.write_svg_frames(frames, "frame")?;
+            println!("Wrote {} frames to {}", count, frames);
+        }
+        println!("Created {} leaves", tree.get_all_leaves().len());
+        write_output(&tree, &self.output, Some(format))
+    }
+}
+
+fn balance(input: String, output: String, format: Option<Format>) -> Result<(), String> {
+    let mut tree = read_tree(&input)?;
+    let before = tree.get_all_leaves().len();
+    tree.weak_balance();
+    println!(
+        "Balanced from {} to {} leaves",
+        before,
+        tree.get_all_leaves().len()
+    );
+    write_output(&tree, &output, format)
+}
+
+fn dual(
+    input: String,
+    output: String,
+    iterations: usize,
+    format: Option<Format>,
+) -> Result<(), String> {
+    let tree = read_tree(&input)?;
+    let mut mesh = tree.dual_mesh();
+    if iterations > 0 {
+        mesh.smooth(&SmoothingParams {
+            iterations,
+            ..Default::default()
+        });
+    }
+    println!(
+        "Created {} dual vertices and {} dual edges",
+        mesh.vertices.len(),
+        mesh.edges.len()
+    );
+    write_output(&mesh, &output, format)
+}
+
+fn mesh(input: String, output: String, dual: bool, levels: bool) -> Result<(), String> {
+    if !output.ends_with(".msh") {
+        return Err(format!("Unsupported mesh file {}, use msh", output));
+    }
+    let tree = read_tree(&input)?;
+    let mesh = if dual {
+        tree.dual_quad_mesh()
+    } else {
+        tree.primal_mesh()
+    };
+    let labels: Option<Vec<usize>> = levels.then(|| {
+        tree.get_all_leaves()
+            .iter()
+            .map(|leaf| leaf.level)
+            .collect()
+    });
+    mesh.write_msh(&output, labels.as_deref())?;
+    println!(
+        "Wrote {} with {} nodes and {} elements",
+        output,
+        mesh.nodes.len(),
+        mesh.elements.len()
+    );
+    Ok(())
+}
+
+fn stats(input: String, output: Option<String>, bins: usize) -> Result<(), String> {
+    let tree = read_tree(&input)?;
+    let leaves = tree.get_all_leaves();
+    let level_count_max = leaves.iter().map(|leaf| leaf.level).max().unwrap_or(0);
+    println!("Leaves: {}", leaves.len());
+    for level in 0..=level_count_max {
+        let count = leaves.iter().filter(|leaf| leaf.level == level).count();
+        if count > 0 {
+            println!("  level {:>2}: {}", level, count);
+        }
+    }
+    println!("Hanging edges: {}", tree.hanging_edges().len());
+
+    let primal = tree.primal_mesh();
+    println!("\nPrimal mesh: {} elements", primal.elements.len());
+    let dual = tree.dual_quad_mesh();
+    println!("Dual mesh: {} elements\n", dual.elements.len());
+    let qualities = dual.quality();
+    for histogram in histograms(&qualities, bins) {
+        print!("{}", histogram);
+    }
+    if let Some(output) = output {
+        write_metrics_csv(&qualities, &output)?;
+        println!("Wrote {}", output);
+    }
+    Ok(())
+}
+
+fn render(input: String, output: String, dual: bool, parts: Option<usize>) -> Result<(), String> {
+    let tree = read_tree(&input)?;
+    let path = Path::new(&output);
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or_else(|| format!("Invalid output file {}", output))?;
+    match parts {
+        Some(n) => {
+            let partition = tree.partition(n, |_| 1.0);
+            for (part, stats) in partition.stats.iter().enumerate() {
+                println!(
+                    "Part {}: {} leaves, {} interface edges",
+                    part, stats.leaves, stats.interface_edges
+                );
+            }
+            tree.visualize_partition(&directory.to_string_lossy(), stem, &partition)
+        }
+        None => tree.visualize(&directory.to_string_lossy(), stem, dual),
+    }
+}
+
+fn example(output: String, name: Option<String>) -> Result<(), String> {
     // Examples library and selection
-    let examples: &[(fn(&str) -> Result<(), String>, bool)] = &[
-        (point_stimulated_refinement, false),
-        (manual_subdivision, false),
-        (circle_with_balancing, false),
-        (level_1_fully_refined, true),
-        (transition_wine_glass, false),
+    let examples: &[(&str, Example)] = &[
+        ("point", point_stimulated_refinement),
+        ("manual", manual_subdivision),
+        ("circle", circle_with_balancing),
+        ("level_1", level_1_fully_refined),
+        ("wine_glass", transition_wine_glass),
     ];
-
-    for (func, enabled) in examples {
-        if *enabled {
-            func(&scratch_path_str)?;
+    if let Some(name) = &name {
+        if !examples.iter().any(|(key, _)| key == name) {
+            return Err(format!("Unknown example '{}'", name));
         }
     }
 
+    fs::create_dir_all(&output)
+        .map_err(|e| format!("Failed to create directory {}: {}", output, e))?;
+    for (key, func) in examples {
+        if name.as_deref().is_none_or(|name| name == *key) {
+            func(&output)?;
+        }
+    }
     Ok(())
 }
+
+fn main() -> Result<(), String> {
+    match Cli::parse().command {
+        Commands::Refine(args) => args.run(),
+        Commands::Balance {
+            input,
+            output,
+            format,
+        } => balance(input, output, format),
+        Commands::Dual {
+            input,
+            output,
+            iterations,
+            format,
+        } => dual(input, output, iterations, format),
+        Commands::Mesh {
+            input,
+            output,
+            dual,
+            levels,
+        } => mesh(input, output, dual, levels),
+        Commands::Stats {
+            input,
+            output,
+            bins,
+        } => stats(input, output, bins),
+        Commands::Render {
+            input,
+            output,
+            dual,
+            parts,
+        } => render(input, output, dual, parts),
+        Commands::Example { output, name } => example(output, name),
+    }
+}
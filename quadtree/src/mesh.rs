@@ -0,0 +1,277 @@
+//! Quadrilateral meshes derived from a quadtree.
+//!
+//! The primal mesh uses each leaf as a quadrilateral element.  The dual mesh
+//! connects the centers of the leaves that meet at each interior leaf corner.
+//! Both meshes list their boundary edges by the side of the root they follow.
+
+use crate::{Direction, DyadicCell, Node, Point, Quadtree, Rectangle, VertexKey, VERTEX_LEVEL};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// A side of the root rectangle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub enum Side {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Side {
+    /// All four sides, counter-clockwise from the south.
+    pub const ALL: [Side; 4] = [Side::South, Side::East, Side::North, Side::West];
+
+    /// Returns the lowercase name of the side.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Side::North => "north",
+            Side::East => "east",
+            Side::South => "south",
+            Side::West => "west",
+        }
+    }
+
+    fn direction(&self) -> Direction {
+        match self {
+            Side::North => Direction::North,
+            Side::East => Direction::East,
+            Side::South => Direction::South,
+            Side::West => Direction::West,
+        }
+    }
+}
+
+/// An indexed mesh of quadrilateral elements.
+///
+/// Element nodes are ordered counter-clockwise.  A transition element of the
+/// dual mesh, where only three distinct leaves meet, repeats its last node.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct QuadMesh {
+    /// The node coordinates.
+    pub nodes: Vec<Point>,
+    /// The element connectivity, as four node indices per element.
+    pub elements: Vec<[usize; 4]>,
+    /// The boundary edges, as two node indices in counter-clockwise order, with
+    /// the side of the root that each edge follows.
+    pub boundary: Vec<(Side, [usize; 2])>,
+}
+
+/// Assigns node indices to exact vertex locations, in order of first use.
+struct NodeMap<'a> {
+    domain: &'a Rectangle,
+    nodes: Vec<Point>,
+    index_of: HashMap<VertexKey, usize>,
+}
+
+impl<'a> NodeMap<'a> {
+    fn new(domain: &'a Rectangle) -> Self {
+        Self {
+            domain,
+            nodes: Vec::new(),
+            index_of: HashMap::new(),
+        }
+    }
+
+    fn index(&mut self, key: VertexKey) -> usize {
+        *self.index_of.entry(key).or_insert_with(|| {
+            self.nodes.push(self.domain.vertex_point(key));
+            self.nodes.len() - 1
+        })
+    }
+}
+
+impl Quadtree {
+    /// Builds the primal quadrilateral mesh of the quadtree.
+    ///
+    /// Each leaf becomes one element with its four corners as nodes, shared
+    /// between leaves.  Hanging nodes are not inserted into the coarse elements,
+    /// so the mesh is not conforming where the leaf levels differ.
+    ///
+    /// # Returns
+    ///
+    /// A `QuadMesh` with one element per leaf, in the order of `dual_vertices`.
+    pub fn primal_mesh(&self) -> QuadMesh {
+        let mut node_map = NodeMap::new(&self.domain);
+        let leaves = self.get_all_leaves();
+        let elements = leaves
+            .iter()
+            .map(|leaf| leaf.cell.corner_keys().map(|key| node_map.index(key)))
+            .collect();
+
+        let mut boundary = Vec::new();
+        for side in Side::ALL {
+            for leaf in self.leaves_on_side(side) {
+                let (k1, k2) = leaf.cell.edge_keys(side.direction());
+                // The edge keys run west to east, or south to north.
+                let (k1, k2) = match side {
+                    Side::South | Side::East => (k1, k2),
+                    Side::North | Side::West => (k2, k1),
+                };
+                boundary.push((side, [node_map.index(k1), node_map.index(k2)]));
+            }
+        }
+
+        QuadMesh {
+            nodes: node_map.nodes,
+            elements,
+            boundary,
+        }
+    }
+
+    /// Builds the dual quadrilateral mesh of the quadtree.
+    ///
+    /// For every leaf corner in the interior of the root, one element connects the
+    /// centers of the leaves in the four quadrants around that corner.  Where a
+    /// coarse leaf occupies two of the quadrants, the element is a triangle, stored
+    /// as a quadrilateral with its last node repeated.
+    ///
+    /// # Returns
+    ///
+    /// A `QuadMesh` whose nodes are the leaf centers that appear in an element.
+    pub fn dual_quad_mesh(&self) -> QuadMesh {
+        let (_, root_max, _, _) = DyadicCell::root().edges_at(VERTEX_LEVEL);
+
+        let mut node_map = NodeMap::new(&self.domain);
+        let mut corners_seen = HashSet::new();
+        let mut elements = Vec::new();
+
+        for leaf in self.get_all_leaves() {
+            for corner in leaf.cell.corner_keys() {
+                let on_boundary =
+                    corner.0 == 0 || corner.0 == root_max || corner.1 == 0 || corner.1 == root_max;
+                if on_boundary || !corners_seen.insert(corner) {
+                    continue;
+                }
+                // Counter-clockwise around the corner: SW, SE, NE, NW quadrants.
+                let mut element: Vec<usize> = Vec::with_capacity(4);
+                for (east, north) in [(false, false), (true, false), (true, true), (false, true)] {
+                    let center = self.leaf_at_corner(corner, east, north).cell.center_key();
+                    let index = node_map.index(center);
+                    if element.last() != Some(&index) && element.first() != Some(&index) {
+                        element.push(index);
+                    }
+                }
+                match element.len() {
+                    4 => elements.push([element[0], element[1], element[2], element[3]]),
+                    3 => elements.push([element[0], element[1], element[2], element[2]]),
+                    // A corner hanging in the middle of a coarse edge on both sides
+                    // cannot occur; anything with fewer nodes has no area.
+                    _ => {}
+                }
+            }
+        }
+
+        // The boundary runs through the centers of the leaves along each side.
+        let mut boundary = Vec::new();
+        if !elements.is_empty() {
+            for side in Side::ALL {
+                let centers: Vec<usize> = self
+                    .leaves_on_side(side)
+                    .iter()
+                    .map(|leaf| node_map.index(leaf.cell.center_key()))
+                    .collect();
+                for pair in centers.windows(2) {
+                    boundary.push((side, [pair[0], pair[1]]));
+                }
+            }
+        }
+
+        QuadMesh {
+            nodes: node_map.nodes,
+            elements,
+            boundary,
+        }
+    }
+
+    /// Returns the leaves that touch a `side` of the root, counter-clockwise.
+    fn leaves_on_side(&self, side: Side) -> Vec<&Quadtree> {
+        let mut leaves: Vec<&Quadtree> = self
+            .get_all_leaves()
+            .into_iter()
+            .filter(|leaf| leaf.cell.on_root_side(side.direction()))
+            .collect();
+        // Sort by the position of the leaf's start along the side, at a common level.
+        leaves.sort_by_key(|leaf| {
+            let (west, east, south, north) = leaf.cell.edges_at(VERTEX_LEVEL - 1);
+            match side {
+                Side::South => west,
+                Side::East => south,
+                Side::North => u64::MAX - east,
+                Side::West => u64::MAX - north,
+            }
+        });
+        leaves
+    }
+
+    /// Returns the leaf that touches the `vertex` from the quadrant to its east (or west)
+    /// and north (or south).
+    fn leaf_at_corner(&self, vertex: VertexKey, east: bool, north: bool) -> &Quadtree {
+        match &self.node {
+            Node::Leaf { .. } => self,
+            Node::Children { nw, ne, sw, se } => {
+                let center = self.cell.center_key();
+                let go_east = vertex.0 > center.0 || (vertex.0 == center.0 && east);
+                let go_north = vertex.1 > center.1 || (vertex.1 == center.1 && north);
+                let child = match (go_east, go_north) {
+                    (false, true) => nw,
+                    (true, true) => ne,
+                    (false, false) => sw,
+                    (true, false) => se,
+                };
+                child.leaf_at_corner(vertex, east, north)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_primal_and_dual_mesh() {
+        let mut tree = Quadtree::new(
+            Rectangle {
+                origin: Point { x: 0.0, y: 0.0 },
+                width: 4.0,
+                height: 4.0,
+            },
+            2,
+        );
+        tree.subdivide();
+        if let Node::Children { ne, .. } = &mut tree.node {
+            ne.subdivide();
+        }
+
+        // 3 coarse leaves and 4 fine leaves.
+        let primal = tree.primal_mesh();
+        assert_eq!(primal.elements.len(), 7);
+        assert_eq!(primal.nodes.len(), 14);
+
+        // One quad at the root center, two triangles at the hanging nodes, and
+        // one quad at the center of the refined quadrant.
+        let dual = tree.dual_quad_mesh();
+        assert_eq!(dual.elements.len(), 4);
+        let triangles = dual.elements.iter().filter(|e| e[2] == e[3]).count();
+        assert_eq!(triangles, 2);
+
+        // Each boundary is a closed, counter-clockwise loop of edges.
+        for (mesh, count) in [(&primal, 10), (&dual, 6)] {
+            assert_eq!(mesh.boundary.len(), count);
+            for (k, (_, [_, end])) in mesh.boundary.iter().enumerate() {
+                let (_, [start, _]) = mesh.boundary[(k + 1) % count];
+                assert_eq!(*end, start);
+            }
+        }
+        let east: Vec<&[usize; 2]> = primal
+            .boundary
+            .iter()
+            .filter(|(side, _)| *side == Side::East)
+            .map(|(_, edge)| edge)
+            .collect();
+        assert_eq!(east.len(), 3);
+        assert!(east
+            .iter()
+            .all(|[a, b]| primal.nodes[*a].x == 4.0 && primal.nodes[*a].y < primal.nodes[*b].y));
+    }
+}
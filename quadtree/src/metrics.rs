@@ -0,0 +1,346 @@
+//! Element quality metrics for quadrilateral meshes.
+//!
+//! The metrics follow the definitions used for quadrilaterals in the Verdict
+//! library, so the 2D prototypes can be judged with the same criteria as the
+//! 3D meshes of the `clap1 metrics` command.
+
+use crate::{Point, QuadMesh};
+use serde::Serialize;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// The names of the metrics, in the column order of the CSV output.
+pub const METRIC_NAMES: [&str; 6] = [
+    "min_scaled_jacobian",
+    "aspect_ratio",
+    "skew",
+    "min_angle",
+    "max_angle",
+    "area",
+];
+
+/// The quality metrics of a single quadrilateral element.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ElementQuality {
+    /// The minimum over the four corners of the cross product of the two corner
+    /// edges, divided by the product of their lengths.  It is `1.0` for a
+    /// rectangle, and negative for an inverted corner.  A triangle stored with a
+    /// repeated corner is scaled by `2 / sqrt(3)`, so that it is `1.0` for an
+    /// equilateral triangle.
+    pub min_scaled_jacobian: f64,
+    /// The ratio of the longer to the shorter principal axis, `1.0` for a square.
+    pub aspect_ratio: f64,
+    /// The absolute cosine of the angle between the principal axes, `0.0` for a rectangle.
+    pub skew: f64,
+    /// The minimum interior angle, in degrees.
+    pub min_angle: f64,
+    /// The maximum interior angle, in degrees, which is more than `180.0` at a
+    /// reflex corner.
+    pub max_angle: f64,
+    /// The signed area, positive for counter-clockwise nodes.
+    pub area: f64,
+}
+
+impl ElementQuality {
+    /// Returns the metric values in the order of `METRIC_NAMES`.
+    pub fn values(&self) -> [f64; 6] {
+        [
+            self.min_scaled_jacobian,
+            self.aspect_ratio,
+            self.skew,
+            self.min_angle,
+            self.max_angle,
+            self.area,
+        ]
+    }
+}
+
+/// Returns the vector from `a` to `b`.
+fn sub(b: &Point, a: &Point) -> (f64, f64) {
+    (b.x - a.x, b.y - a.y)
+}
+
+fn norm(v: (f64, f64)) -> f64 {
+    v.0.hypot(v.1)
+}
+
+/// Computes the quality metrics of a quadrilateral with counter-clockwise corners.
+///
+/// A corner repeated by the next one, as in a transition element of the dual
+/// mesh, collapses an edge.  Such an element is judged by the corners and angles
+/// of the triangle of its distinct corners.
+pub fn quad_quality(corners: [&Point; 4]) -> ElementQuality {
+    let distinct: Vec<&Point> = (0..4)
+        .filter(|&k| corners[k] != corners[(k + 1) % 4])
+        .map(|k| corners[k])
+        .collect();
+    let n = distinct.len();
+    let normalization = if n == 3 { 2.0 / 3.0_f64.sqrt() } else { 1.0 };
+
+    let mut min_scaled_jacobian = f64::INFINITY;
+    let mut min_angle = f64::INFINITY;
+    let mut max_angle = f64::NEG_INFINITY;
+    if n < 3 {
+        // Collapsed to a line or a point.
+        (min_scaled_jacobian, min_angle, max_angle) = (0.0, 0.0, 0.0);
+    }
+
+    for k in 0..n {
+        let here = distinct[k];
+        let next = sub(distinct[(k + 1) % n], here);
+        let prev = sub(distinct[(k + n - 1) % n], here);
+        let cross = next.0 * prev.1 - next.1 * prev.0;
+        let dot = next.0 * prev.0 + next.1 * prev.1;
+        let scaled_jacobian = normalization * cross / (norm(next) * norm(prev));
+        // A reflex corner has a negative cross product, and an angle over 180.
+        let angle = cross.atan2(dot).to_degrees().rem_euclid(360.0);
+        min_scaled_jacobian = min_scaled_jacobian.min(scaled_jacobian);
+        min_angle = min_angle.min(angle);
+        max_angle = max_angle.max(angle);
+    }
+
+    // Principal axes, joining the midpoints of opposite edges.
+    let [p0, p1, p2, p3] = corners;
+    let x1 = (p1.x - p0.x + p2.x - p3.x, p1.y - p0.y + p2.y - p3.y);
+    let x2 = (p2.x - p1.x + p3.x - p0.x, p2.y - p1.y + p3.y - p0.y);
+    let (l1, l2) = (norm(x1), norm(x2));
+    let (aspect_ratio, skew) = if l1 > 0.0 && l2 > 0.0 {
+        (
+            l1.max(l2) / l1.min(l2),
+            ((x1.0 * x2.0 + x1.1 * x2.1) / (l1 * l2)).abs(),
+        )
+    } else {
+        (f64::INFINITY, 1.0)
+    };
+
+    let area = 0.5
+        * (0..4)
+            .map(|k| {
+                let (a, b) = (corners[k], corners[(k + 1) % 4]);
+                a.x * b.y - b.x * a.y
+            })
+            .sum::<f64>();
+
+    ElementQuality {
+        min_scaled_jacobian,
+        aspect_ratio,
+        skew,
+        min_angle,
+        max_angle,
+        area,
+    }
+}
+
+impl QuadMesh {
+    /// Computes the quality metrics of every element.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<ElementQuality>` with one entry per element, in element order.
+    pub fn quality(&self) -> Vec<ElementQuality> {
+        self.elements
+            .iter()
+            .map(|element| {
+                quad_quality([
+                    &self.nodes[element[0]],
+                    &self.nodes[element[1]],
+                    &self.nodes[element[2]],
+                    &self.nodes[element[3]],
+                ])
+            })
+            .collect()
+    }
+}
+
+/// A summary of one metric over all elements.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Histogram {
+    /// The name of the metric.
+    pub name: String,
+    /// The minimum value.
+    pub min: f64,
+    /// The maximum value.
+    pub max: f64,
+    /// The mean value.
+    pub mean: f64,
+    /// The number of elements in each of the equal-width bins from `min` to `max`.
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    /// Bins the finite `values` into `bins` equal-width bins.
+    ///
+    /// Without any finite values, the histogram has no bins, and its minimum,
+    /// maximum and mean are zero.
+    pub fn new(name: &str, values: &[f64], bins: usize) -> Self {
+        let finite: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+        if finite.is_empty() {
+            return Self {
+                name: name.to_string(),
+                min: 0.0,
+                max: 0.0,
+                mean: 0.0,
+                counts: Vec::new(),
+            };
+        }
+        let min = finite.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = finite.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let mean = finite.iter().sum::<f64>() / finite.len() as f64;
+        let mut counts = vec![0; bins.max(1)];
+        let width = (max - min) / counts.len() as f64;
+        for value in &finite {
+            let bin = if width > 0.0 {
+                (((value - min) / width) as usize).min(counts.len() - 1)
+            } else {
+                0
+            };
+            counts[bin] += 1;
+        }
+        Self {
+            name: name.to_string(),
+            min,
+            max,
+            mean,
+            counts,
+        }
+    }
+}
+
+impl fmt::Display for Histogram {
+    /// Writes the summary line followed by one bar per bin.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.counts.is_empty() {
+            return writeln!(f, "{}: no elements", self.name);
+        }
+        writeln!(
+            f,
+            "{}: min {:.4}, max {:.4}, mean {:.4}",
+            self.name, self.min, self.max, self.mean
+        )?;
+        let width = (self.max - self.min) / self.counts.len() as f64;
+        let count_max = self.counts.iter().copied().max().unwrap_or(0).max(1);
+        for (i, count) in self.counts.iter().enumerate() {
+            let lower = self.min + i as f64 * width;
+            let bar = "#".repeat((40 * count).div_ceil(count_max));
+            writeln!(f, "  {:>12.4} | {:>6} {}", lower, count, bar)?;
+        }
+        Ok(())
+    }
+}
+
+/// Summarizes every metric as a histogram with `bins` bins.
+pub fn histograms(qualities: &[ElementQuality], bins: usize) -> Vec<Histogram> {
+    METRIC_NAMES
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let values: Vec<f64> = qualities.iter().map(|q| q.values()[i]).collect();
+            Histogram::new(name, &values, bins)
+        })
+        .collect()
+}
+
+/// Writes the metrics to a CSV file, with a header row and one row per element.
+pub fn write_metrics_csv(qualities: &[ElementQuality], path: &str) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+    let mut writer = BufWriter::new(file);
+    let write_error = |e: std::io::Error| format!("Failed to write {}: {}", path, e);
+
+    writeln!(writer, "element,{}", METRIC_NAMES.join(",")).map_err(write_error)?;
+    for (i, quality) in qualities.iter().enumerate() {
+        let row: Vec<String> = quality
+            .values()
+            .iter()
+            .map(|v| format!("{:e}", v))
+            .collect();
+        writeln!(writer, "{},{}", i, row.join(",")).map_err(write_error)?;
+    }
+    writer.flush().map_err(write_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Quadtree, Rectangle};
+
+    #[test]
+    fn test_quad_quality() {
+        let square = [
+            Point { x: 0.0, y: 0.0 },
+            Point { x: 2.0, y: 0.0 },
+            Point { x: 2.0, y: 2.0 },
+            Point { x: 0.0, y: 2.0 },
+        ];
+        let q = quad_quality([&square[0], &square[1], &square[2], &square[3]]);
+        assert!((q.min_scaled_jacobian - 1.0).abs() < 1.0e-12);
+        assert!((q.aspect_ratio - 1.0).abs() < 1.0e-12);
+        assert!(q.skew.abs() < 1.0e-12);
+        assert!((q.min_angle - 90.0).abs() < 1.0e-12);
+        assert!((q.max_angle - 90.0).abs() < 1.0e-12);
+        assert!((q.area - 4.0).abs() < 1.0e-12);
+
+        // A triangle stored with a repeated node is judged as the triangle.
+        let q = quad_quality([&square[0], &square[1], &square[2], &square[2]]);
+        assert!((q.min_scaled_jacobian - (2.0_f64 / 3.0).sqrt()).abs() < 1.0e-12);
+        assert!((q.min_angle - 45.0).abs() < 1.0e-12);
+        assert!((q.max_angle - 90.0).abs() < 1.0e-12);
+        assert!((q.area - 2.0).abs() < 1.0e-12);
+        let corners = [
+            Point { x: 0.0, y: 0.0 },
+            Point { x: 1.0, y: 0.0 },
+            Point { x: 0.0, y: 1.0 },
+        ];
+        let q = quad_quality([&corners[0], &corners[1], &corners[2], &corners[2]]);
+        assert!((q.min_scaled_jacobian - (2.0_f64 / 3.0).sqrt()).abs() < 1.0e-12);
+        assert!((q.area - 0.5).abs() < 1.0e-12);
+
+        // A chevron, with a reflex corner at (1, 1).
+        let chevron = [
+            Point { x: 0.0, y: 0.0 },
+            Point { x: 2.0, y: 0.0 },
+            Point { x: 1.0, y: 1.0 },
+            Point { x: 2.0, y: 2.0 },
+        ];
+        let q = quad_quality([&chevron[0], &chevron[1], &chevron[2], &chevron[3]]);
+        assert!((q.min_scaled_jacobian - -1.0).abs() < 1.0e-12);
+        assert!((q.max_angle - 270.0).abs() < 1.0e-12);
+        assert!((q.area - 1.0).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn test_empty_histogram() {
+        let histogram = Histogram::new("area", &[], 5);
+        assert!(histogram.counts.is_empty());
+        assert_eq!(histogram.mean, 0.0);
+        assert_eq!(histogram.to_string(), "area: no elements\n");
+    }
+
+    #[test]
+    fn test_histograms_and_csv() {
+        let mut tree = Quadtree::new(
+            Rectangle {
+                origin: Point { x: 0.0, y: 0.0 },
+                width: 1.0,
+                height: 1.0,
+            },
+            1,
+        );
+        tree.subdivide();
+        let qualities = tree.primal_mesh().quality();
+        assert_eq!(qualities.len(), 4);
+
+        let summary = histograms(&qualities, 5);
+        assert_eq!(summary.len(), METRIC_NAMES.len());
+        assert!(summary.iter().all(|h| h.counts.iter().sum::<usize>() == 4));
+        assert!((summary[5].mean - 0.25).abs() < 1.0e-12);
+
+        let path =
+            std::env::temp_dir().join(format!("quadtree_test_metrics_{}.csv", std::process::id()));
+        let path = path.to_string_lossy();
+        write_metrics_csv(&qualities, &path).unwrap();
+        let contents = std::fs::read_to_string(path.as_ref()).unwrap();
+        assert_eq!(contents.lines().count(), 5);
+        assert!(contents.starts_with("element,min_scaled_jacobian"));
+    }
+}
@@ -0,0 +1,276 @@
+//! Partitioning of quadtree leaves along a space-filling curve.
+//!
+//! The leaves are ordered by the Morton (Z-order) or Hilbert index of their
+//! centers, and the ordered list is cut into parts of nearly equal total weight.
+//! Because both curves visit every cell's descendants consecutively, each part
+//! is a contiguous run of the curve, which keeps the parts compact.
+
+use crate::{Direction, DyadicCell, Quadtree};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// The space-filling curve that orders the leaves.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpaceFillingCurve {
+    /// The Morton, or Z-order, curve, which interleaves the bits of `i` and `j`.
+    Morton,
+    /// The Hilbert curve, whose consecutive cells always share an edge.
+    Hilbert,
+}
+
+/// The summary of one part of a partition.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PartStats {
+    /// The number of leaves in the part.
+    pub leaves: usize,
+    /// The total weight of the leaves in the part.
+    pub weight: f64,
+    /// The number of leaf faces shared with leaves of other parts.
+    pub interface_edges: usize,
+}
+
+/// A partition of the leaves of a quadtree.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Partition {
+    /// The part of each leaf, in the order of `get_all_leaves`.
+    pub parts: Vec<usize>,
+    /// The summary of each part, indexed by part.
+    pub stats: Vec<PartStats>,
+}
+
+impl Partition {
+    /// Returns the total number of leaf faces between different parts.
+    pub fn interface_edges(&self) -> usize {
+        // Every interface face is counted once by the part on each side.
+        self.stats.iter().map(|s| s.interface_edges).sum::<usize>() / 2
+    }
+
+    /// Returns the ratio of the heaviest part weight to the mean part weight,
+    /// which is `1.0` for a perfect balance.
+    pub fn imbalance(&self) -> f64 {
+        let total: f64 = self.stats.iter().map(|s| s.weight).sum();
+        let max = self.stats.iter().map(|s| s.weight).fold(0.0, f64::max);
+        if total > 0.0 {
+            max * self.stats.len() as f64 / total
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Returns the Morton index of the cell at `(x, y)`, interleaving the bits with
+/// `x` in the lower position.
+fn morton_index(x: u64, y: u64) -> u128 {
+    let mut index = 0u128;
+    for bit in 0..64 {
+        index |= (((x >> bit) & 1) as u128) << (2 * bit);
+        index |= (((y >> bit) & 1) as u128) << (2 * bit + 1);
+    }
+    index
+}
+
+/// Returns the Hilbert index of the cell at `(x, y)` on a grid of `2^order` cells
+/// per side.
+fn hilbert_index(order: usize, mut x: u64, mut y: u64) -> u128 {
+    let mut index = 0u128;
+    let mut s = 1u64 << (order - 1);
+    while s > 0 {
+        let rx = (x & s) > 0;
+        let ry = (y & s) > 0;
+        index += (s as u128) * (s as u128) * ((3 * rx as u128) ^ ry as u128);
+        // Rotate the quadrant so that the curve inside it has the base orientation.
+        if !ry {
+            if rx {
+                x = !x;
+                y = !y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s >>= 1;
+    }
+    index
+}
+
+impl Quadtree {
+    /// Partitions the leaves into `n` parts of nearly equal weight along a Hilbert curve.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of parts, at least one.
+    /// * `weight_fn` - The weight of a leaf, such as its number of points.
+    ///
+    /// # Returns
+    ///
+    /// A `Partition` with the part of each leaf and the summary of each part.
+    pub fn partition<F: Fn(&Quadtree) -> f64>(&self, n: usize, weight_fn: F) -> Partition {
+        self.partition_along(n, SpaceFillingCurve::Hilbert, weight_fn)
+    }
+
+    /// Partitions the leaves into `n` parts of nearly equal weight along a `curve`.
+    ///
+    /// The leaves are sorted by the curve index of their centers, then each leaf is
+    /// assigned to the part that contains the middle of its weight in the running
+    /// total.  If every weight is zero, each leaf has a weight of one.
+    pub fn partition_along<F: Fn(&Quadtree) -> f64>(
+        &self,
+        n: usize,
+        curve: SpaceFillingCurve,
+        weight_fn: F,
+    ) -> Partition {
+        let n = n.max(1);
+        let leaves = self.get_all_leaves();
+        let mut weights: Vec<f64> = leaves.iter().map(|leaf| weight_fn(leaf).max(0.0)).collect();
+        let mut total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            weights.iter_mut().for_each(|w| *w = 1.0);
+            total = leaves.len() as f64;
+        }
+
+        // The centers of all leaves lie on the grid one level below the finest leaf.
+        let order = leaves.iter().map(|leaf| leaf.level).max().unwrap_or(0) + 1;
+        let mut sorted: Vec<(u128, usize)> = leaves
+            .iter()
+            .enumerate()
+            .map(|(k, leaf)| {
+                let shift = order - 1 - leaf.level;
+                let x = (2 * leaf.cell.i + 1) << shift;
+                let y = (2 * leaf.cell.j + 1) << shift;
+                let index = match curve {
+                    SpaceFillingCurve::Morton => morton_index(x, y),
+                    SpaceFillingCurve::Hilbert => hilbert_index(order, x, y),
+                };
+                (index, k)
+            })
+            .collect();
+        sorted.sort_unstable();
+
+        let mut parts = vec![0; leaves.len()];
+        let mut stats = vec![
+            PartStats {
+                leaves: 0,
+                weight: 0.0,
+                interface_edges: 0,
+            };
+            n
+        ];
+        let mut running = 0.0;
+        for (_, k) in sorted {
+            let middle = running + 0.5 * weights[k];
+            let part = ((middle / total * n as f64) as usize).min(n - 1);
+            running += weights[k];
+            parts[k] = part;
+            stats[part].leaves += 1;
+            stats[part].weight += weights[k];
+        }
+
+        // Count the faces each part shares with the other parts.
+        let index_of: HashMap<DyadicCell, usize> = leaves
+            .iter()
+            .enumerate()
+            .map(|(k, leaf)| (leaf.cell, k))
+            .collect();
+        for (k, leaf) in leaves.iter().enumerate() {
+            for direction in [
+                Direction::North,
+                Direction::East,
+                Direction::South,
+                Direction::West,
+            ] {
+                for neighbor in self.find_neighbors_recursive(&leaf.cell, direction) {
+                    if parts[index_of[&neighbor.cell]] != parts[k] {
+                        stats[parts[k]].interface_edges += 1;
+                    }
+                }
+            }
+        }
+
+        Partition { parts, stats }
+    }
+
+    /// Visualizes the quadtree as in `visualize`, with each leaf shaded by its part.
+    pub fn visualize_partition(
+        &self,
+        scratch_path: &str,
+        file_suffix: &str,
+        partition: &Partition,
+    ) -> Result<(), String> {
+        self.visualize_with(scratch_path, file_suffix, false, Some(partition))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Node, Point, Rectangle};
+
+    #[test]
+    fn test_curve_indices() {
+        // The first-order Hilbert curve visits (0, 0), (0, 1), (1, 1), (1, 0).
+        let order: Vec<u128> = [(0, 0), (0, 1), (1, 1), (1, 0)]
+            .iter()
+            .map(|&(x, y)| hilbert_index(1, x, y))
+            .collect();
+        assert_eq!(order, vec![0, 1, 2, 3]);
+        assert_eq!(morton_index(1, 0), 1);
+        assert_eq!(morton_index(0, 1), 2);
+        assert_eq!(morton_index(3, 3), 15);
+
+        // Consecutive cells of a Hilbert curve share an edge.
+        let mut cells: Vec<(u128, u64, u64)> = (0..8)
+            .flat_map(|x| (0..8).map(move |y| (hilbert_index(3, x, y), x, y)))
+            .collect();
+        cells.sort();
+        for pair in cells.windows(2) {
+            let (_, x0, y0) = pair[0];
+            let (_, x1, y1) = pair[1];
+            assert_eq!(x0.abs_diff(x1) + y0.abs_diff(y1), 1);
+        }
+    }
+
+    #[test]
+    fn test_partition() {
+        let mut tree = Quadtree::new(
+            Rectangle {
+                origin: Point { x: 0.0, y: 0.0 },
+                width: 1.0,
+                height: 1.0,
+            },
+            3,
+        );
+        tree.subdivide();
+        if let Node::Children { nw, ne, sw, se } = &mut tree.node {
+            for child in [nw, ne, sw, se] {
+                child.subdivide();
+            }
+        }
+
+        // A uniform 4x4 grid cut into 4 parts along the Hilbert curve gives the
+        // four quadrants, each with two faces toward each of its two neighbors.
+        let partition = tree.partition(4, |_| 1.0);
+        assert_eq!(partition.parts.len(), 16);
+        for stats in &partition.stats {
+            assert_eq!(stats.leaves, 4);
+            assert_eq!(stats.weight, 4.0);
+            assert_eq!(stats.interface_edges, 4);
+        }
+        assert_eq!(partition.interface_edges(), 8);
+        assert_eq!(partition.imbalance(), 1.0);
+
+        // Morton order also gives the quadrants for this grid.
+        let morton = tree.partition_along(4, SpaceFillingCurve::Morton, |_| 1.0);
+        assert_eq!(morton.interface_edges(), 8);
+
+        // A heavy leaf takes a part nearly to itself.
+        let heavy = Point { x: 0.1, y: 0.1 };
+        let partition = tree.partition(2, |leaf| {
+            if leaf.boundary.contains(&heavy) {
+                15.0
+            } else {
+                1.0
+            }
+        });
+        let lightest = partition.stats.iter().map(|s| s.leaves).min().unwrap();
+        assert!(lightest <= 2);
+        assert!(partition.imbalance() < 1.1);
+    }
+}
@@ -0,0 +1,322 @@
+//! Laplacian and Taubin smoothing of the dual mesh of a quadtree.
+//!
+//! The dual mesh is built from `Quadtree::dual_vertices` and `Quadtree::dual_edges`,
+//! with each unique point given an index so that the dual edges define the vertex
+//! adjacency used by the smoothing operators.
+
+use crate::{point_key, Direction, Point, Quadtree};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// The default scaling parameter (lambda) for all smoothing methods.
+pub const SCALE_DEFAULT: f64 = 0.6307;
+
+/// The default pass-band frequency (k) for Taubin smoothing.
+pub const PASS_BAND_DEFAULT: f64 = 0.1;
+
+/// The default number of smoothing iterations.
+pub const ITERATIONS_DEFAULT: usize = 20;
+
+/// The smoothing algorithm applied to the dual vertices.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SmoothingMethod {
+    /// Laplacian smoothing, which moves each vertex by `scale` toward the
+    /// average of its neighbors.  Repeated application shrinks the mesh.
+    Laplace,
+    /// Taubin (lambda/mu) smoothing, which follows each Laplacian step with an
+    /// inflating step so that the mesh does not shrink.
+    Taubin,
+}
+
+/// How dual vertices of leaves on the root boundary are treated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BoundaryTreatment {
+    /// Boundary vertices do not move.
+    Fixed,
+    /// Boundary vertices move only parallel to the side of the root they touch.
+    /// Vertices touching two sides (a root corner) do not move.
+    Sliding,
+}
+
+/// Parameters that control the smoothing of a dual mesh.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SmoothingParams {
+    /// The smoothing algorithm.
+    pub method: SmoothingMethod,
+    /// The number of smoothing iterations.
+    pub iterations: usize,
+    /// The scaling parameter (lambda), with `0.0 < scale < 1.0`.
+    pub scale: f64,
+    /// The pass-band frequency (k) used to derive mu, for Taubin only.
+    pub pass_band: f64,
+    /// The treatment of vertices on the root boundary.
+    pub boundary: BoundaryTreatment,
+}
+
+impl Default for SmoothingParams {
+    /// Taubin smoothing with the same defaults as the `clap1 smooth` command.
+    fn default() -> Self {
+        Self {
+            method: SmoothingMethod::Taubin,
+            iterations: ITERATIONS_DEFAULT,
+            scale: SCALE_DEFAULT,
+            pass_band: PASS_BAND_DEFAULT,
+            boundary: BoundaryTreatment::Fixed,
+        }
+    }
+}
+
+impl SmoothingParams {
+    /// Returns the Taubin inflation parameter, mu, from the relationship
+    /// `k = 1 / lambda + 1 / mu`, where `k` is the pass-band frequency.
+    ///
+    /// For the defaults, `lambda = 0.6307` and `k = 0.1`, mu is about `-0.6732`.
+    pub fn mu(&self) -> f64 {
+        1.0 / (self.pass_band - 1.0 / self.scale)
+    }
+}
+
+/// Classifies how a dual vertex may move during smoothing.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum VertexKind {
+    /// The center of a leaf that does not touch the root boundary.
+    Interior,
+    /// The center of a leaf that touches the north or south side of the root.
+    /// When sliding, it moves only in the x-direction.
+    BoundaryX,
+    /// The center of a leaf that touches the east or west side of the root.
+    /// When sliding, it moves only in the y-direction.
+    BoundaryY,
+    /// The center of a leaf that touches two sides of the root.
+    Corner,
+    /// A hanging node introduced by a transition edge.  It is connected only to
+    /// the center of its coarse cell, so it is held fixed.
+    Hanging,
+}
+
+/// An indexed dual mesh of a quadtree.
+#[derive(Clone, Debug, Serialize)]
+pub struct DualMesh {
+    /// The vertex coordinates: leaf centers, followed by any hanging nodes.
+    pub vertices: Vec<Point>,
+    /// The movement classification of each vertex.
+    pub kinds: Vec<VertexKind>,
+    /// The unique dual edges, as pairs of vertex indices.
+    pub edges: Vec<(usize, usize)>,
+}
+
+impl Quadtree {
+    /// Builds an indexed dual mesh from the dual vertices and dual edges of the quadtree.
+    ///
+    /// # Returns
+    ///
+    /// A `DualMesh` whose first vertices are the leaf centers, in the same order
+    /// as `dual_vertices`, followed by the hanging nodes of the transition edges.
+    pub fn dual_mesh(&self) -> DualMesh {
+        let mut vertices = Vec::new();
+        let mut kinds = Vec::new();
+        let mut index_of: HashMap<(u64, u64), usize> = HashMap::new();
+
+        for leaf in self.get_all_leaves() {
+            let cell = &leaf.cell;
+            let on_x_side =
+                cell.on_root_side(Direction::West) || cell.on_root_side(Direction::East);
+            let on_y_side =
+                cell.on_root_side(Direction::South) || cell.on_root_side(Direction::North);
+            let kind = match (on_x_side, on_y_side) {
+                (false, false) => VertexKind::Interior,
+                (false, true) => VertexKind::BoundaryX,
+                (true, false) => VertexKind::BoundaryY,
+                (true, true) => VertexKind::Corner,
+            };
+            let center = leaf.center();
+            index_of.insert(point_key(&center), vertices.len());
+            vertices.push(center);
+            kinds.push(kind);
+        }
+
+        let mut seen = HashSet::new();
+        let mut edges = Vec::new();
+        for (p1, p2) in self.dual_edges() {
+            let mut index = |p: Point| -> usize {
+                *index_of.entry(point_key(&p)).or_insert_with(|| {
+                    vertices.push(p);
+                    kinds.push(VertexKind::Hanging);
+                    vertices.len() - 1
+                })
+            };
+            let (i, j) = (index(p1), index(p2));
+            if i != j && seen.insert((i.min(j), i.max(j))) {
+                edges.push((i, j));
+            }
+        }
+
+        DualMesh {
+            vertices,
+            kinds,
+            edges,
+        }
+    }
+}
+
+impl DualMesh {
+    /// Returns the list of neighboring vertex indices for every vertex.
+    pub fn neighbors(&self) -> Vec<Vec<usize>> {
+        let mut neighbors = vec![Vec::new(); self.vertices.len()];
+        for &(i, j) in &self.edges {
+            neighbors[i].push(j);
+            neighbors[j].push(i);
+        }
+        neighbors
+    }
+
+    /// Smooths the vertex positions in place.
+    ///
+    /// Each iteration applies one Laplacian step scaled by `params.scale`; for
+    /// Taubin smoothing, it is followed by a second step scaled by `params.mu()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The smoothing method, iteration count, and boundary treatment.
+    pub fn smooth(&mut self, params: &SmoothingParams) {
+        let neighbors = self.neighbors();
+        for _ in 0..params.iterations {
+            self.laplace_step(&neighbors, params.scale, params.boundary);
+            if params.method == SmoothingMethod::Taubin {
+                self.laplace_step(&neighbors, params.mu(), params.boundary);
+            }
+        }
+    }
+
+    /// Moves every unconstrained vertex by `factor` times its Laplacian, which is the
+    /// vector from the vertex to the average of its neighbors.
+    fn laplace_step(&mut self, neighbors: &[Vec<usize>], factor: f64, boundary: BoundaryTreatment) {
+        // Compute all of the updates from the current positions before applying any.
+        let updates: Vec<Point> = self
+            .vertices
+            .iter()
+            .zip(neighbors)
+            .map(|(vertex, adjacent)| {
+                if adjacent.is_empty() {
+                    return Point { x: 0.0, y: 0.0 };
+                }
+                let n = adjacent.len() as f64;
+                let (sum_x, sum_y) = adjacent.iter().fold((0.0, 0.0), |(sx, sy), &j| {
+                    (sx + self.vertices[j].x, sy + self.vertices[j].y)
+                });
+                Point {
+                    x: factor * (sum_x / n - vertex.x),
+                    y: factor * (sum_y / n - vertex.y),
+                }
+            })
+            .collect();
+
+        for ((vertex, kind), update) in self.vertices.iter_mut().zip(&self.kinds).zip(updates) {
+            let (move_x, move_y) = match (kind, boundary) {
+                (VertexKind::Interior, _) => (true, true),
+                (VertexKind::BoundaryX, BoundaryTreatment::Sliding) => (true, false),
+                (VertexKind::BoundaryY, BoundaryTreatment::Sliding) => (false, true),
+                _ => (false, false),
+            };
+            if move_x {
+                vertex.x += update.x;
+            }
+            if move_y {
+                vertex.y += update.y;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Rectangle;
+
+    /// Returns a uniformly refined 4x4 quadtree on the unit square.
+    fn uniform_level_2() -> Quadtree {
+        let mut tree = Quadtree::new(
+            Rectangle {
+                origin: Point { x: 0.0, y: 0.0 },
+                width: 1.0,
+                height: 1.0,
+            },
+            2,
+        );
+        tree.subdivide();
+        if let crate::Node::Children { nw, ne, sw, se } = &mut tree.node {
+            nw.subdivide();
+            ne.subdivide();
+            sw.subdivide();
+            se.subdivide();
+        }
+        tree
+    }
+
+    #[test]
+    fn test_smoothing_params_mu() {
+        let params = SmoothingParams::default();
+        assert!((params.mu() - (-0.6732)).abs() < 1.0e-4);
+    }
+
+    #[test]
+    fn test_dual_mesh() {
+        let mesh = uniform_level_2().dual_mesh();
+        assert_eq!(mesh.vertices.len(), 16);
+        // A 4x4 grid of dual vertices has 2 * 4 * 3 edges.
+        assert_eq!(mesh.edges.len(), 24);
+        let count = |kind| mesh.kinds.iter().filter(|k| **k == kind).count();
+        assert_eq!(count(VertexKind::Interior), 4);
+        assert_eq!(count(VertexKind::Corner), 4);
+        assert_eq!(count(VertexKind::BoundaryX), 4);
+        assert_eq!(count(VertexKind::BoundaryY), 4);
+    }
+
+    #[test]
+    fn test_smooth() {
+        let mut mesh = uniform_level_2().dual_mesh();
+        let original = mesh.vertices.clone();
+
+        // Perturb an interior vertex, Laplace smoothing should pull it back toward
+        // its original position, and leave the fixed boundary untouched.
+        let interior = mesh
+            .kinds
+            .iter()
+            .position(|k| *k == VertexKind::Interior)
+            .unwrap();
+        mesh.vertices[interior].x += 0.1;
+        mesh.smooth(&SmoothingParams {
+            method: SmoothingMethod::Laplace,
+            iterations: 10,
+            ..Default::default()
+        });
+        let error = (mesh.vertices[interior].x - original[interior].x).abs();
+        assert!(error < 0.1);
+        for (i, kind) in mesh.kinds.iter().enumerate() {
+            if *kind != VertexKind::Interior {
+                assert_eq!(mesh.vertices[i], original[i]);
+            }
+        }
+
+        // With sliding, a boundary vertex on the north or south side keeps its y.
+        // The vertex at (0.375, 0.875) is moved to x = 0.425, and its neighbors
+        // average x = 0.375, so one step with a scale of 0.5 leaves it at x = 0.4.
+        let mut mesh = uniform_level_2().dual_mesh();
+        let boundary_x = mesh
+            .kinds
+            .iter()
+            .position(|k| *k == VertexKind::BoundaryX)
+            .unwrap();
+        assert_eq!(original[boundary_x], Point { x: 0.375, y: 0.875 });
+        mesh.vertices[boundary_x].x += 0.05;
+        mesh.smooth(&SmoothingParams {
+            method: SmoothingMethod::Laplace,
+            iterations: 1,
+            scale: 0.5,
+            boundary: BoundaryTreatment::Sliding,
+            ..Default::default()
+        });
+        assert_eq!(mesh.vertices[boundary_x].y, 0.875);
+        assert!((mesh.vertices[boundary_x].x - 0.4).abs() < 1.0e-12);
+    }
+}
@@ -0,0 +1,250 @@
+//! A step-by-step record of the subdivisions made by refinement and balancing.
+//!
+//! Each subdivision is recorded with the cell that was subdivided, the reason,
+//! and the pass in which it was made.  A trace can be written as JSON, and can be
+//! replayed from the root to draw one SVG frame per step.
+
+use crate::{DyadicCell, Quadtree, Rectangle, RefinementCriterion, LEVEL_LIMIT};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+/// The width and height, in pixels, of the larger side of an SVG frame.
+const FRAME_SIZE: f64 = 512.0;
+
+/// Why a leaf was subdivided.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum SubdivisionReason {
+    /// Refinement: the leaf held `count` points, which met the refinement criterion.
+    Points { count: usize },
+    /// Balancing: the face neighbor `leaf` was more than one level finer.
+    Neighbor { leaf: DyadicCell },
+}
+
+/// A single subdivision.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TraceEvent {
+    /// The position of the event in the trace, from one.
+    pub step: usize,
+    /// The refinement or balancing pass that made the subdivision, from one.
+    pub pass: usize,
+    /// The cell of the subdivided leaf.
+    pub cell: DyadicCell,
+    /// The boundary of the subdivided leaf.
+    pub boundary: Rectangle,
+    /// The reason for the subdivision.
+    pub reason: SubdivisionReason,
+}
+
+/// A record of the subdivisions of a quadtree.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Trace {
+    /// The boundary of the root of the traced tree.
+    pub domain: Rectangle,
+    /// The subdivisions, in the order they were made.
+    pub events: Vec<TraceEvent>,
+    /// The number of passes started so far.
+    pub passes: usize,
+}
+
+impl Trace {
+    /// Creates an empty trace for a quadtree.
+    pub fn new(tree: &Quadtree) -> Self {
+        Self {
+            domain: tree.domain.clone(),
+            events: Vec::new(),
+            passes: 0,
+        }
+    }
+
+    /// Starts a new pass, to which the following events belong.
+    pub(crate) fn begin_pass(&mut self) {
+        self.passes += 1;
+    }
+
+    /// Records the subdivision of the leaf at `cell` in the current pass.
+    pub(crate) fn record(&mut self, cell: DyadicCell, reason: SubdivisionReason) {
+        self.events.push(TraceEvent {
+            step: self.events.len() + 1,
+            pass: self.passes,
+            cell,
+            boundary: self.domain.cell_rectangle(&cell),
+            reason,
+        });
+    }
+
+    /// Serializes the trace to a JSON string.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Replays the trace from the root, and writes one SVG frame before the first
+    /// step and one after each step, numbered `<prefix>_0000.svg` and onward, to
+    /// the `directory`.
+    ///
+    /// # Returns
+    ///
+    /// The number of frames written.
+    pub fn write_svg_frames(&self, directory: &str, prefix: &str) -> Result<usize, String> {
+        fs::create_dir_all(directory)
+            .map_err(|e| format!("Failed to create directory {}: {}", directory, e))?;
+
+        let mut tree = Quadtree::new(self.domain.clone(), LEVEL_LIMIT);
+        let write_frame = |tree: &Quadtree, event: Option<&TraceEvent>, frame: usize| {
+            let path = Path::new(directory).join(format!("{}_{:04}.svg", prefix, frame));
+            fs::write(&path, self.svg_frame(tree, event))
+                .map_err(|e| format!("Failed to write {:?}: {}", path, e))
+        };
+
+        write_frame(&tree, None, 0)?;
+        for event in &self.events {
+            let leaf = tree
+                .node_mut(&event.cell)
+                .ok_or_else(|| format!("Step {} subdivides a cell not in the tree", event.step))?;
+            leaf.subdivide();
+            write_frame(&tree, Some(event), event.step)?;
+        }
+        Ok(self.events.len() + 1)
+    }
+
+    /// Draws the leaves of `tree`, with the cell of the latest `event` highlighted.
+    fn svg_frame(&self, tree: &Quadtree, event: Option<&TraceEvent>) -> String {
+        let scale = FRAME_SIZE / self.domain.width.max(self.domain.height);
+        let (width, height) = (self.domain.width * scale, self.domain.height * scale);
+        // SVG y points down, so the rectangles are flipped about the domain.
+        let rect = |r: &Rectangle| {
+            (
+                (r.origin.x - self.domain.origin.x) * scale,
+                height - (r.origin.y + r.height - self.domain.origin.y) * scale,
+                r.width * scale,
+                r.height * scale,
+            )
+        };
+
+        let mut svg = String::new();
+        let margin = 24.0;
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+            width,
+            height + margin,
+            width,
+            height + margin
+        );
+        let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+        if let Some(event) = event {
+            let (x, y, w, h) = rect(&event.boundary);
+            let color = match event.reason {
+                SubdivisionReason::Points { .. } => "lightskyblue",
+                SubdivisionReason::Neighbor { .. } => "lightsalmon",
+            };
+            let _ = writeln!(
+                svg,
+                r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+                x, y, w, h, color
+            );
+        }
+        for leaf in tree.get_all_leaves() {
+            let (x, y, w, h) = rect(&leaf.boundary);
+            let _ = writeln!(
+                svg,
+                r#"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="black" stroke-width="1"/>"#,
+                x, y, w, h
+            );
+        }
+        let caption = match event {
+            None => "initial".to_string(),
+            Some(event) => {
+                let reason = match &event.reason {
+                    SubdivisionReason::Points { count } => format!("{} points", count),
+                    SubdivisionReason::Neighbor { leaf } => {
+                        format!("neighbor ({}, {}) at level {}", leaf.i, leaf.j, leaf.level)
+                    }
+                };
+                format!(
+                    "step {}, pass {}: cell ({}, {}) at level {}, {}",
+                    event.step, event.pass, event.cell.i, event.cell.j, event.cell.level, reason
+                )
+            }
+        };
+        let _ = writeln!(
+            svg,
+            r#"<text x="4" y="{}" font-family="sans-serif" font-size="14">{}</text>"#,
+            height + margin - 7.0,
+            caption
+        );
+        let _ = writeln!(svg, "</svg>");
+        svg
+    }
+}
+
+impl Quadtree {
+    /// Refines the quadtree as in `refine_by`, recording each subdivision in a new
+    /// pass of the `trace`.
+    pub fn refine_with_trace(&mut self, criterion: RefinementCriterion, trace: &mut Trace) {
+        trace.begin_pass();
+        self.refine_traced(criterion, Some(trace));
+    }
+
+    /// Balances the quadtree as in `weak_balance`, recording each subdivision in
+    /// the `trace`, with one pass per balancing pass.
+    pub fn weak_balance_with_trace(&mut self, trace: &mut Trace) {
+        self.weak_balance_traced(Some(trace));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Point;
+
+    #[test]
+    fn test_trace() {
+        let mut tree = Quadtree::new(
+            Rectangle {
+                origin: Point { x: 1.0, y: -1.0 },
+                width: 2.0,
+                height: 2.0,
+            },
+            5,
+        );
+        tree.insert(Point { x: 2.6, y: 0.6 }).unwrap();
+
+        let mut trace = Trace::new(&tree);
+        tree.refine_with_trace(RefinementCriterion::AnyPoint, &mut trace);
+        let refined = trace.events.len();
+        assert_eq!(refined, 5);
+        assert!(trace
+            .events
+            .iter()
+            .all(|e| e.pass == 1 && e.reason == SubdivisionReason::Points { count: 1 }));
+
+        tree.weak_balance_with_trace(&mut trace);
+        assert!(trace.events.len() > refined);
+        for event in &trace.events[refined..] {
+            assert!(event.pass > 1);
+            match event.reason {
+                SubdivisionReason::Neighbor { leaf } => assert!(leaf.level > event.cell.level + 1),
+                _ => panic!("Balancing should only subdivide for neighbors."),
+            }
+        }
+        // The last pass finds nothing to subdivide.
+        assert_eq!(trace.passes, trace.events.last().unwrap().pass + 1);
+
+        let json = trace.to_json().unwrap();
+        assert!(json.contains("\"Neighbor\""));
+
+        // Replaying the trace reproduces the leaves of the tree.
+        let directory =
+            std::env::temp_dir().join(format!("quadtree_test_trace_{}", std::process::id()));
+        let frames = trace
+            .write_svg_frames(&directory.to_string_lossy(), "frame")
+            .unwrap();
+        assert_eq!(frames, trace.events.len() + 1);
+        let last =
+            fs::read_to_string(directory.join(format!("frame_{:04}.svg", frames - 1))).unwrap();
+        let leaf_count = tree.get_all_leaves().len();
+        assert_eq!(last.matches("stroke=\"black\"").count(), leaf_count);
+    }
+}
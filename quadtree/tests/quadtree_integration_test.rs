@@ -10,7 +10,7 @@ fn integration_test_quadtree_subdivision_and_reinsertion() {
     let mut quadtree = Quadtree::new(boundary, 1); // level_max is 1
     let point = Point { x: 50.0, y: 60.0 }; // poin in the ne quadrant
 
-    assert!(quadtree.insert(point.clone()));
+    assert_eq!(quadtree.insert(point.clone()), Ok(()));
 }
 
 #[test]
@@ -103,3 +103,32 @@ fn test_weak_balance_no_unnecessary_corner_refinement() {
         _ => panic!("Tree should be a Children node."),
     }
 }
+
+#[test]
+fn test_circle_points_on_closed_root() {
+    // A unit circle sampled from angle zero has a point on the right edge of its
+    // bounding square, which only a closed root accepts.
+    let mut tree = Quadtree::new(
+        Rectangle {
+            origin: Point { x: -1.0, y: -1.0 },
+            width: 2.0,
+            height: 2.0,
+        },
+        3,
+    );
+    tree.boundary_policy = BoundaryPolicy::ClosedAtRoot;
+    for i in 0..8 {
+        let angle = i as f64 * std::f64::consts::PI / 4.0;
+        let point = Point {
+            x: angle.cos(),
+            y: angle.sin(),
+        };
+        assert_eq!(tree.insert(point), Ok(()));
+    }
+    tree.refine();
+    tree.weak_balance();
+
+    let yaml = tree.to_yaml().unwrap();
+    let copy = Quadtree::from_yaml(&yaml).unwrap();
+    assert_eq!(copy.get_all_leaves().len(), tree.get_all_leaves().len());
+}
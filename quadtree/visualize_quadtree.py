@@ -1,119 +1,121 @@
 """
-This module, visualize_quadtree.py, draw the quadtree visualization with matplotlib.
+This module, visualize_quadtree.py, draws the quadtree visualization with matplotlib.
+
+`render` draws a `pyquadtree.Quadtree` built in Python, so notebooks pass the
+tree straight from the bindings of the Rust library, which also compute the dual
+vertices and edges.  From the command line, the tree is read from a file, or
+from the standard input with `-`, as `Quadtree::visualize` streams it.  Install
+the bindings into the virtual environment first, with `maturin develop` in
+prust/pyquadtree.
 
 Example:
     source .venv/bin/activate
-    python visualize_quadtree.py ~/scratch/quadtree/quadtree_data.yaml
+    python visualize_quadtree.py ~/scratch/quadtree/quadtree_data.yaml --dual
 
 """
 
-import sys
+import argparse
 from pathlib import Path
+import sys
 from typing import Final
-import yaml
 
 from matplotlib import rc
 import matplotlib.colors as mcolors
 import matplotlib.pyplot as plt
 import matplotlib.patches as patches
+from matplotlib.collections import LineCollection
+import numpy as np
+
+import pyquadtree as pq
+
+
+def draw_quadtree(ax, leaves, levels, colors):
+    """Draws each leaf in the color of its level, with the root dashed."""
+    for (x, y, width, height), level in zip(leaves, levels):
+        # Use the modulo operator to cycle through the colors list.
+        # This prevents an IndexError if the quadtree level is larger than the number of colors.
+        rect = patches.Rectangle(
+            (x, y),
+            width,
+            height,
+            linewidth=2,
+            edgecolor=colors[level % len(colors)],
+            facecolor="none",
+            zorder=level,
+        )
+        ax.add_patch(rect)
+
+    x_min, y_min = leaves[:, 0].min(), leaves[:, 1].min()
+    x_max = (leaves[:, 0] + leaves[:, 2]).max()
+    y_max = (leaves[:, 1] + leaves[:, 3]).max()
+    root = patches.Rectangle(
+        (x_min, y_min),
+        x_max - x_min,
+        y_max - y_min,
+        linewidth=4,
+        edgecolor=colors[0],
+        linestyle="dashed",
+        facecolor="none",
+        zorder=0,
+    )
+    ax.add_patch(root)
+    ax.set_xlim(x_min, x_max)
+    ax.set_ylim(y_min, y_max)
 
 
-def construct_quadtree_leaf(loader, node):
-    """
-    Register constructors for custom tags used by serde for enums.
-    This tells PyYAML to treat !Leaf as regular mappings (diectionaries)
-    """
-    result = loader.construct_mapping(node)
-    return {"Leaf": result}
-
-
-def construct_quadtree_children(loader, node):
-    """
-    Register constructors for custom tags used by serde for enums.
-    This tells PyYAML to treat !Children as regular mappings (diectionaries)
-    """
-    result = loader.construct_mapping(node)
-    return {"Children": result}
-
-
-yaml.FullLoader.add_constructor("!Leaf", construct_quadtree_leaf)
-yaml.FullLoader.add_constructor("!Children", construct_quadtree_children)
-
-
-def draw_quadtree(ax, node_data, colors, level=0):
-    """Draws the quadtree with matplotlib."""
-    # Use the modulo operator to cycle through the colors list.
-    # This prevents an IndexError if the quadtree level is larger than the number of colors.
-    current_color = colors[level % len(colors)]
+def draw_points(ax, points, color="black"):
+    """Draws the points held by the leaves."""
+    if len(points) == 0:
+        return
 
-    # Define line style for the first level
-    linestyle = "dashed" if level == 0 else "solid"
-    linewidth = 4 if level == 0 else 2
-
-    # print(f"Drawing level: {level}")
-
-    # Draw the boundary of the current node
-    boundary = node_data["boundary"]
-    rect = patches.Rectangle(
-        (boundary["origin"]["x"], boundary["origin"]["y"]),
-        boundary["width"],
-        boundary["height"],
-        linewidth=linewidth,
-        edgecolor=current_color,
-        linestyle=linestyle,
-        facecolor="none",
-        zorder=level,
-    )
-    ax.add_patch(rect)
-
-    # Draw points if it's a Leaf node
-    if "Leaf" in node_data["node"]:
-        points = node_data["node"]["Leaf"]["points"]
-        if points:
-            x_coords = [p["x"] for p in points]
-            y_coords = [p["y"] for p in points]
-            ax.scatter(x_coords, y_coords, color=current_color, s=10)
-    # Recursively draw children if it's a Children node
-    elif "Children" in node_data["node"]:
-        children = node_data["node"]["Children"]
-        draw_quadtree(ax, children["nw"], colors, level + 1)
-        draw_quadtree(ax, children["ne"], colors, level + 1)
-        draw_quadtree(ax, children["sw"], colors, level + 1)
-        draw_quadtree(ax, children["se"], colors, level + 1)
+    ax.scatter(points[:, 0], points[:, 1], color=color, s=10, zorder=11)
 
 
 def draw_dual_vertices(ax, dual_vertices, color="black"):
     """Draws the dual vertices on the quadtree visualization."""
-    if not dual_vertices:
+    if len(dual_vertices) == 0:
         return
 
-    x_coords = [p["x"] for p in dual_vertices]
-    y_coords = [p["y"] for p in dual_vertices]
-    ax.scatter(x_coords, y_coords, color=color, s=25, zorder=10, marker="o")
+    ax.scatter(
+        dual_vertices[:, 0],
+        dual_vertices[:, 1],
+        color=color,
+        s=25,
+        zorder=10,
+        marker="o",
+    )
 
 
 def draw_dual_edges(ax, dual_edges, color="darkgray"):
     """Draws the dual edges on the quadtree visualization."""
-    if not dual_edges:
+    if len(dual_edges) == 0:
         return
 
-    for edge in dual_edges:
-        p1, p2 = edge
-        ax.plot(
-            [p1["x"], p2["x"]],
-            [p1["y"], p2["y"]],
-            color=color,
-            linewidth=1,
-            zorder=9,
+    ax.add_collection(LineCollection(dual_edges, colors=color, linewidths=1, zorder=9))
+
+
+def draw_partition(ax, leaves, parts, cmap="tab20"):
+    """Shades each leaf with the color of its part."""
+    colormap = plt.get_cmap(cmap)
+    for (x, y, width, height), part in zip(leaves, parts):
+        rect = patches.Rectangle(
+            (x, y),
+            width,
+            height,
+            linewidth=0,
+            facecolor=colormap(part % colormap.N),
+            alpha=0.5,
+            zorder=-1,
         )
+        ax.add_patch(rect)
 
 
-def main():
-    """The main entry point for the script."""
-    if len(sys.argv) < 2:
-        print("Usage: python3 visualize_quadtree.py <path_to_yaml_file>")
-        sys.exit(1)
+def render(tree, output_path, dual=False, parts=None):
+    """Draws a pyquadtree.Quadtree, and saves the figure to output_path.
 
+    The leaves are shaded by parts, the part of each leaf in the order of
+    tree.leaves(), if given.
+    """
     dpi: Final[int] = 300
     show: Final[bool] = False
     save: Final[bool] = True
@@ -122,75 +124,76 @@ def main():
         rc("font", **{"family": "serif", "serif": ["Computer Modern Roman"]})
         rc("text", usetex=True)
 
-    yaml_file_path = sys.argv[1]
-    print(f"Processing file: {yaml_file_path}")
-
-    file_path = Path(yaml_file_path)
-    filename_stem = file_path.stem  # removes the .yaml extension
-    print(f"Filename stem: {filename_stem}")
-
-    try:
-        with open(yaml_file_path, "r", encoding="utf8") as f:
-            viz_data = yaml.load(
-                f, Loader=yaml.FullLoader
-            )  # needed to resolve custom tags
-        print("Python successfully loaded YAML data.")
-        # print("Python successfully loaded YAML data:")
-        # print(json.dumps(quadtree_data, indent=2))
-    except FileNotFoundError:
-        print(f"Error: YAML file not found at {yaml_file_path}")
-        sys.exit(1)
-    except yaml.YAMLError as e:
-        print(
-            f"Error: Could not decode YAML from {yaml_file_path}.  Error details: {e}"
-        )
-        sys.exit(1)
-
-    quadtree_data = viz_data["quadtree"]
-    _level_max = quadtree_data["level_max"]
+    output_path = Path(output_path)
+    filename_stem = output_path.stem
 
     fig, ax = plt.subplots(figsize=(6.0, 6.0), dpi=dpi)
     ax.set_aspect("equal", adjustable="box")
 
     # Define colors for each level
-    # colors = ["tab:blue", "tab:orange", "tab:green", "tab:red"]
     colors = list(mcolors.TABLEAU_COLORS.keys())
 
-    # Set plot limits based on the root boundary
-    root_boundary = quadtree_data["boundary"]
-    ax.set_xlim(
-        root_boundary["origin"]["x"],
-        root_boundary["origin"]["x"] + root_boundary["width"],
-    )
-    ax.set_ylim(
-        root_boundary["origin"]["y"],
-        root_boundary["origin"]["y"] + root_boundary["height"],
-    )
+    # Draw the primary quadtree, and the points held by its leaves
+    leaves = tree.leaves()
+    draw_quadtree(ax, leaves, tree.levels(), colors)
+    draw_points(ax, tree.points())
 
-    # Draw the primary quadtree
-    draw_quadtree(ax, quadtree_data, colors)
+    # Shade the leaves by part if a partition exists
+    if parts is not None:
+        draw_partition(ax, leaves, parts)
 
-    # Draw dual vertices and edges if they exist
-    draw_dual_vertices(ax, viz_data.get("dual_vertices"))
-    draw_dual_edges(ax, viz_data.get("dual_edges"))
+    # Draw dual vertices and edges if requested
+    if dual:
+        draw_dual_vertices(ax, tree.dual_vertices())
+        draw_dual_edges(ax, tree.dual_edges())
 
-    # plt.title("Quadtree Visualization")
-    # plt.title(f"level max = {level_max}")
     plt.title(f"{filename_stem}")
     plt.xlabel(r"$x$")
     plt.ylabel(r"$y$")
-    # plt.grid(True)
     plt.grid(False)
     if show:
         plt.show()
 
     if save:
-        extension = ".png"  # ".png" | ".pdf" | ".svg"
-        output_path = file_path.parent  # same directory as input file
-        filename = filename_stem + extension
-        pathfilename = output_path.joinpath(filename)
-        fig.savefig(pathfilename, bbox_inches="tight", pad_inches=0)
-        print(f"Serialized to {pathfilename}")
+        fig.savefig(output_path, bbox_inches="tight", pad_inches=0)
+        print(f"Serialized to {output_path}")
+
+
+def main():
+    """The main entry point for the script."""
+    parser = argparse.ArgumentParser(description=__doc__.split("\n\n")[0].strip())
+    parser.add_argument(
+        "file", help="quadtree file (yaml), or - for the standard input"
+    )
+    parser.add_argument(
+        "--output",
+        type=Path,
+        help="image file (png | pdf | svg), by default the file with a png extension",
+    )
+    parser.add_argument(
+        "--dual", action="store_true", help="draw the dual vertices and edges"
+    )
+    parser.add_argument(
+        "--parts", type=Path, help="text file with the part of each leaf, one per line"
+    )
+    args = parser.parse_args()
+
+    if args.file == "-":
+        if args.output is None:
+            parser.error("--output is required when reading the standard input")
+        yaml = sys.stdin.read()
+    else:
+        yaml = Path(args.file).read_text(encoding="utf8")
+    output_path = args.output or Path(args.file).with_suffix(".png")
+    print(f"Processing file: {args.file}")
+
+    tree = pq.Quadtree.from_yaml(yaml)
+    print(f"Loaded {tree}")
+
+    parts = None
+    if args.parts is not None:
+        parts = np.loadtxt(args.parts, dtype=int, ndmin=1)
+    render(tree, output_path, dual=args.dual, parts=parts)
 
 
 if __name__ == "__main__":